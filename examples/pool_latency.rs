@@ -0,0 +1,44 @@
+//! Compares request latency against a local server with and without
+//! connection reuse, as tuned by `--http-pool-max-idle-per-host`.
+//!
+//! ```text
+//! cargo run --release --example pool_latency
+//! ```
+
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    time::{Duration, Instant}
+};
+
+use auto_youtube_live_recorder::youtube::{ClientOptions, client_builder};
+use hyper::{
+    Body, Response, Server,
+    service::{make_service_fn, service_fn}
+};
+
+const REQUESTS: u32 = 200;
+
+async fn average(options: ClientOptions, url: &str) -> Duration {
+    let client = client_builder(&options).build().unwrap();
+    let started = Instant::now();
+    for _ in 0..REQUESTS {
+        client.get(url).send().await.unwrap().bytes().await.unwrap();
+    }
+    started.elapsed() / REQUESTS
+}
+
+#[tokio::main]
+async fn main() {
+    let make_service = make_service_fn(|_| async {
+        Ok::<_, Infallible>(service_fn(|_| async { Ok::<_, Infallible>(Response::new(Body::from("{}"))) }))
+    });
+    let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+    let url = format!("http://{}/youtube/v3/search", server.local_addr());
+    tokio::spawn(server);
+
+    let pooled = average(ClientOptions::default(), &url).await;
+    let unpooled = average(ClientOptions { pool_max_idle_per_host: Some(0), ..ClientOptions::default() }, &url).await;
+    println!("pooled:   {:?} per request", pooled);
+    println!("unpooled: {:?} per request", unpooled);
+}
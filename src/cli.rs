@@ -0,0 +1,404 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf
+};
+
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    clock::{SchedulerKind, parse_interval},
+    config::parse_channel_key,
+    filters::DuplicatePolicy,
+    manifest::ManifestFormat,
+    metadata::parse_tag,
+    postprocess::Container,
+    registry::{DedupStrategy, parse_priority},
+    timezone::Timezone,
+    youtube::{DetectionMethod, SearchOrder},
+    ytdlp::{OnExisting, RecoverParts, parse_channel_height, parse_sub_lang}
+};
+
+/// Whether log lines get ANSI colors.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never
+}
+
+impl ColorChoice {
+    /// `auto` turns into `never` when `no_color` is set to anything but
+    /// an empty string, per no-color.org.
+    pub fn resolve(&self, no_color: Option<&str>) -> ColorChoice {
+        match self {
+            ColorChoice::Auto if no_color.is_some_and(|value| !value.is_empty()) => ColorChoice::Never,
+            choice => *choice
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// YouTube Data API key, needed for YouTube channels unless they're scraped
+    #[arg(short, long)]
+    pub api_key: Option<String>,
+    /// Channel to monitor, as @handle, UC... channel ID, legacy username or channel URL. A twitch.tv URL
+    /// is checked through Twitch and any other http(s) URL through yt-dlp (repeatable)
+    #[arg(short = 'c', long = "channel", required_unless_present_any = ["config", "urls", "video_ids"])]
+    pub channels: Vec<String>,
+    /// Record this URL directly, from any site yt-dlp supports, once a poll finds it live (repeatable)
+    #[arg(long = "url", value_name = "URL")]
+    pub urls: Vec<String>,
+    /// Record this YouTube video directly, without any API lookups (repeatable)
+    #[arg(long = "video-id", value_name = "ID")]
+    pub video_ids: Vec<String>,
+    /// TOML file with settings, reloaded on SIGHUP; flags given here take precedence.
+    /// Its strings may refer to environment variables as ${NAME}, with $$ for a literal $
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    #[arg(short, long, default_value_t = false)]
+    pub quiet: bool,
+    /// More logging: -v for debug, -vv for trace (RUST_LOG, if set, wins)
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+    /// What to do when a recording of the stream already exists
+    #[arg(long, value_enum, default_value_t = OnExisting::Resume)]
+    pub on_existing: OnExisting,
+    /// Log and store the concurrent viewer count every N seconds while recording
+    #[arg(long, value_name = "SECS")]
+    pub viewer_sample_interval: Option<u64>,
+    /// Print the effective configuration as JSON and exit
+    #[arg(long, default_value_t = false)]
+    pub dump_config: bool,
+    /// Channel ID to check when the primary channel isn't live (repeatable)
+    #[arg(long = "fallback-channel", value_name = "CHANNEL_ID")]
+    pub fallback_channels: Vec<String>,
+    /// Also check the channels featured on the primary channel's page
+    #[arg(long, default_value_t = false)]
+    pub follow_linked: bool,
+    /// When nothing is live, download the channel's latest finished stream
+    #[arg(long, default_value_t = false)]
+    pub include_completed: bool,
+    /// Skip streams scheduled to run for less than this many seconds
+    #[arg(long, value_name = "SECS")]
+    pub min_expected_duration: Option<u64>,
+    /// Skip streams whose title or description marks them as members-only
+    #[arg(long, default_value_t = false)]
+    pub skip_members_only: bool,
+    /// Only record these video IDs: a file with one ID per line or a comma-separated list (repeatable)
+    #[arg(long, value_name = "IDS|FILE")]
+    pub allowlist_ids: Vec<String>,
+    /// Never record these video IDs: a file with one ID per line or a comma-separated list (repeatable)
+    #[arg(long, value_name = "IDS|FILE")]
+    pub blocklist_ids: Vec<String>,
+    /// Split recordings into one file per day, rolling over at midnight
+    #[arg(long, default_value_t = false)]
+    pub roll_daily: bool,
+    /// Timezone for date-based behavior: local, utc, or an offset like +02:00
+    #[arg(long, default_value = "local")]
+    pub timezone: Timezone,
+    /// Also save the description, info.json and thumbnail via yt-dlp
+    #[arg(long, default_value_t = false)]
+    pub write_metadata_files: bool,
+    /// Save the live chat, description and thumbnail into a VIDEO_ID.extras directory next to each recording
+    #[arg(long, default_value_t = false)]
+    pub capture_extras: bool,
+    /// Have yt-dlp write directly into the final file, without a .part
+    #[arg(long, default_value_t = false)]
+    pub no_part: bool,
+    /// On startup, resume or clean up .part files left by an earlier crash
+    #[arg(long, value_enum)]
+    pub recover_parts: Option<RecoverParts>,
+    /// ISO 3166-1 region code passed to the live search, e.g. HU
+    #[arg(long)]
+    pub region_code: Option<String>,
+    /// ISO 639-1 language code passed to the live search as relevanceLanguage
+    #[arg(long)]
+    pub language: Option<String>,
+    /// yt-dlp config file to use, passed as --config-location
+    #[arg(long, value_name = "FILE")]
+    pub yt_dlp_config: Option<PathBuf>,
+    /// Named yt-dlp profile from the config file's [profiles.<name>] tables
+    #[arg(long)]
+    pub profile: Option<String>,
+    /// How many channels to look up concurrently each tick
+    #[arg(long, default_value_t = 4)]
+    pub lookup_concurrency: usize,
+    /// Periodically append a report to this file: markdown for .md, JSON lines otherwise
+    #[arg(long, value_name = "FILE")]
+    pub report_file: Option<PathBuf>,
+    /// Seconds between reports written to --report-file
+    #[arg(long, default_value_t = 86400)]
+    pub report_interval: u64,
+    /// What drives the polls: the cron scheduler or a plain interval loop
+    #[arg(long, value_enum, default_value_t = SchedulerKind::Cron)]
+    pub scheduler: SchedulerKind,
+    /// Restart yt-dlp when the recording hasn't grown for this many seconds
+    #[arg(long, value_name = "SECS")]
+    pub stall_timeout: Option<u64>,
+    /// Netscape cookie file for members-only or age-restricted streams, passed to yt-dlp and sent with scrape detection
+    #[arg(long, visible_alias = "cookies-file", value_name = "FILE", conflicts_with = "cookies_from_browser")]
+    pub cookies: Option<PathBuf>,
+    /// Browser to export cookies from on every yt-dlp start, e.g. firefox; scrape detection gets them exported
+    /// to a file at startup and every --cookie-refresh-interval, or hourly
+    #[arg(long, value_name = "BROWSER")]
+    pub cookies_from_browser: Option<String>,
+    /// Every N seconds, restart yt-dlp if the cookies were refreshed (browser cookies always are)
+    #[arg(long, value_name = "SECS")]
+    pub cookie_refresh_interval: Option<u64>,
+    /// Local IP address to send API requests and downloads from
+    #[arg(long, value_name = "IP")]
+    pub source_address: Option<IpAddr>,
+    /// Only connect over IPv4
+    #[arg(long, default_value_t = false, conflicts_with = "force_ipv6")]
+    pub force_ipv4: bool,
+    /// Only connect over IPv6
+    #[arg(long, default_value_t = false)]
+    pub force_ipv6: bool,
+    /// Label recordings with key=value, stored in the sidecar and passed as RECORDER_TAGS (repeatable)
+    #[arg(long = "tag", value_name = "KEY=VALUE", value_parser = parse_tag)]
+    pub tags: Vec<(String, String)>,
+    /// Shell command run before each recording with VIDEO_ID, CHANNEL_TITLE and OUTPUT_PATH set; non-zero skips it
+    #[arg(long, value_name = "COMMAND")]
+    pub pre_hook: Option<String>,
+    /// Seconds to wait for --pre-hook before skipping the recording
+    #[arg(long, value_name = "SECS", default_value_t = 30)]
+    pub pre_hook_timeout: u64,
+    /// Have yt-dlp prefer HDR formats when the stream offers them
+    #[arg(long, default_value_t = false)]
+    pub prefer_hdr: bool,
+    /// How to find live streams: search (100 quota units), the uploads playlist (2 units) or scraping the /live page (no API key)
+    #[arg(long, value_enum, default_value_t = DetectionMethod::Search)]
+    pub detection_method: DetectionMethod,
+    /// Times to retry starting yt-dlp after a transient failure such as EAGAIN
+    #[arg(long, default_value_t = 3)]
+    pub spawn_retries: u32,
+    /// Serve a status page at http://ADDR/, as JSON at /status, and a Server-Sent Events
+    /// stream of ticks, recordings and errors at /events
+    #[arg(long, value_name = "ADDR", alias = "listen")]
+    pub events_addr: Option<SocketAddr>,
+    /// Most clients connected to --events-addr at once
+    #[arg(long, default_value_t = 8)]
+    pub events_max_subscribers: usize,
+    /// Serve Prometheus metrics at http://ADDR/metrics, apart from --events-addr
+    #[arg(long, value_name = "ADDR")]
+    pub metrics_addr: Option<SocketAddr>,
+    /// Delete the oldest finished recordings once the output directory exceeds this many GB
+    #[arg(long, value_name = "GB")]
+    pub max_total_gb: Option<f64>,
+    /// Delete finished recordings last written more than this many days ago
+    #[arg(long, value_name = "DAYS")]
+    pub max_age_days: Option<u64>,
+    /// Never delete recordings that have tags when enforcing --max-total-gb or --max-age-days
+    #[arg(long, default_value_t = false)]
+    pub keep_tagged: bool,
+    /// Don't start recording while the output directory's disk has less than this many GB free
+    #[arg(long, value_name = "GB")]
+    pub min_free_gb: Option<f64>,
+    /// Niceness for yt-dlp so recordings yield CPU to other work (priority class on Windows)
+    #[arg(long, value_name = "N", allow_negative_numbers = true)]
+    pub nice: Option<i32>,
+    /// Cap each recording's download rate, passed to yt-dlp as --limit-rate (e.g. 2M)
+    #[arg(long, value_name = "RATE")]
+    pub limit_rate: Option<String>,
+    /// Once a stream has ended, join its segment files into one with ffmpeg
+    #[arg(long, default_value_t = false)]
+    pub merge_segments: bool,
+    /// Delete the segment files after --merge-segments joined them
+    #[arg(long, default_value_t = false, requires = "merge_segments")]
+    pub delete_segments: bool,
+    /// Remux finished recordings into this container with ffmpeg, deleting the original
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub remux: Option<Container>,
+    /// Shell command run for each finished recording with VIDEO_ID and RECORDING_PATH set
+    #[arg(long, value_name = "COMMAND")]
+    pub post_hook: Option<String>,
+    /// Seconds to wait for --post-hook before counting it as failed
+    #[arg(long, value_name = "SECS", default_value_t = 3600)]
+    pub post_hook_timeout: u64,
+    /// Upload finished recordings to s3://BUCKET/PREFIX with the AWS CLI or to REMOTE:PATH with rclone
+    #[arg(long, value_name = "DEST")]
+    pub upload: Option<String>,
+    /// S3-compatible endpoint for --upload s3://..., e.g. https://minio.example.com
+    #[arg(long, value_name = "URL")]
+    pub s3_endpoint: Option<String>,
+    /// Times a failed --post-hook or --upload is tried again
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub post_retries: u32,
+    /// Seconds an idle API connection is kept for reuse
+    #[arg(long, value_name = "SECS")]
+    pub http_pool_idle_timeout: Option<u64>,
+    /// Most idle API connections kept per host; 0 disables reuse
+    #[arg(long, value_name = "N")]
+    pub http_pool_max_idle_per_host: Option<usize>,
+    /// Most recordings running at once; live streams found beyond it wait in a queue for a free slot
+    #[arg(long, visible_alias = "max-concurrent-recordings", value_name = "N")]
+    pub max_recordings: Option<usize>,
+    /// Slots of --max-recordings kept free for channels with a positive --priority
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "max_recordings")]
+    pub reserved_slots: usize,
+    /// Recording priority of a channel or direct target, 0 if not given (repeatable)
+    #[arg(long = "priority", value_name = "CHANNEL=N", value_parser = parse_priority)]
+    pub priorities: Vec<(String, i32)>,
+    /// When every slot is taken, stop the lowest-priority recording for a higher-priority one
+    #[arg(long, default_value_t = false, requires = "max_recordings")]
+    pub preempt: bool,
+    /// Read yt-dlp's progress as JSON instead of showing its progress bar, for the event stream
+    #[arg(long, default_value_t = false)]
+    pub structured_progress: bool,
+    /// How to detect a video is already being recorded; process scans every running program
+    #[arg(long, value_enum, default_value_t = DedupStrategy::Registry)]
+    pub dedup_strategy: DedupStrategy,
+    /// Record into containers that stay playable if yt-dlp is killed mid-stream (MPEG-TS, fragmented MP4, MKV)
+    #[arg(long, default_value_t = false)]
+    pub crash_safe: bool,
+    /// Time between polls, in seconds or with an s, m or h suffix
+    #[arg(long, value_name = "SECS", default_value = "60", value_parser = parse_interval)]
+    pub interval: u64,
+    /// Shortest --interval allowed, default 5 for search detection and 1 for playlist
+    #[arg(long, value_name = "SECS")]
+    pub min_interval_floor: Option<u64>,
+    /// Poll as often as --interval says even below the floor, quota permitting
+    #[arg(long, default_value_t = false)]
+    pub allow_aggressive_polling: bool,
+    /// Quota units a day polls may spend across every API key; YouTube channels are polled less often
+    /// as it runs low, and not at all once it's spent, until the quota resets at midnight Pacific time
+    #[arg(long, value_name = "UNITS")]
+    pub quota_budget: Option<u64>,
+    /// Record only the first N minutes of each stream, then never again
+    #[arg(long, value_name = "N")]
+    pub sample_minutes: Option<u64>,
+    /// Also look for scheduled streams and premieres on YouTube channels, poll their channel every tick
+    /// once one is close, and start yt-dlp waiting for it this many minutes before its planned start
+    #[arg(long, value_name = "MINUTES")]
+    pub upcoming_lead: Option<u64>,
+    /// Data API base URL, for reaching it through a mirror or proxy [default: https://www.googleapis.com/youtube/v3]
+    #[arg(long, value_name = "URL")]
+    pub api_base_url: Option<String>,
+    /// Client ID of a Twitch application, to check twitch.tv channels through the Helix API
+    /// instead of yt-dlp
+    #[arg(long, value_name = "ID", requires = "twitch_client_secret")]
+    pub twitch_client_id: Option<String>,
+    /// Client secret of the --twitch-client-id application
+    #[arg(long, value_name = "SECRET", requires = "twitch_client_id")]
+    pub twitch_client_secret: Option<String>,
+    /// Poll once right at startup instead of waiting for the first scheduled tick
+    #[arg(long, default_value_t = false)]
+    pub run_now: bool,
+    /// At startup, also download VODs of streams that already ended today
+    #[arg(long, default_value_t = false)]
+    pub backfill_completed_today: bool,
+    /// Color log lines; auto colors terminals unless NO_COLOR is set
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+    /// yt-dlp executable to run [default: yt-dlp on the PATH]
+    #[arg(long, value_name = "PATH", alias = "ytdlp-path")]
+    pub yt_dlp_path: Option<String>,
+    /// Poll once, wait for the recordings it started to finish, then exit
+    #[arg(long, default_value_t = false)]
+    pub once: bool,
+    /// How the live search ranks streams [default: the API's own, relevance]
+    #[arg(long, value_enum)]
+    pub search_order: Option<SearchOrder>,
+    /// Results per live search, 1-50 [default: the API's own, 5]
+    #[arg(long, value_name = "N")]
+    pub max_results: Option<u32>,
+    /// Log a heartbeat line every SECS, with the time since the last poll
+    #[arg(long, value_name = "SECS")]
+    pub heartbeat: Option<u64>,
+    /// What to record when a channel has a premiere and a live stream of the same content
+    #[arg(long, value_enum, default_value_t = DuplicatePolicy::RecordBoth)]
+    pub on_duplicate_detection: DuplicatePolicy,
+    /// API key to use for one channel instead of --api-key (repeatable)
+    #[arg(long = "channel-api-key", value_name = "CHANNEL=KEY", value_parser = parse_channel_key)]
+    pub channel_api_keys: Vec<(String, String)>,
+    /// Describe finished recordings for media servers, as .nfo files or a manifest.json index
+    #[arg(long, value_enum)]
+    pub manifest_format: Option<ManifestFormat>,
+    /// Validate the configuration, output directory and yt-dlp, print any problems and exit
+    #[arg(long, default_value_t = false)]
+    pub check_config: bool,
+    /// With --check-config, also look up every channel with its API key
+    #[arg(long, default_value_t = false, requires = "check_config")]
+    pub check_api: bool,
+    /// Fail recordings unless yt-dlp merges the best separate video and audio, verified with ffprobe
+    #[arg(long, default_value_t = false)]
+    pub require_merge: bool,
+    /// Ask yt-dlp for each stream's formats first and record the best up to --max-height
+    #[arg(long, default_value_t = false)]
+    pub smart_format: bool,
+    /// Highest resolution --smart-format picks, or the lowest offered when nothing fits
+    #[arg(long, value_name = "N")]
+    pub max_height: Option<u32>,
+    /// --max-height for one channel (repeatable)
+    #[arg(long = "channel-max-height", value_name = "CHANNEL=N", value_parser = parse_channel_height)]
+    pub channel_max_heights: Vec<(String, u32)>,
+    /// Save YouTube's auto-generated captions next to the recording
+    #[arg(long, default_value_t = false)]
+    pub write_auto_subs: bool,
+    /// Caption languages to save, comma-separated, e.g. en,de.* or all
+    #[arg(long, value_name = "LANGS", value_delimiter = ',', value_parser = parse_sub_lang)]
+    pub sub_langs: Vec<String>,
+    /// Directory to record into [default: the current directory]
+    #[arg(long, value_name = "DIR")]
+    pub output_dir: Option<PathBuf>,
+    /// Poll on this cron schedule, with a leading seconds field, instead of every --interval
+    #[arg(long, value_name = "EXPR")]
+    pub cron: Option<String>,
+    /// Recording file name from {channel}, {title}, {date} and {id}; must keep [{id}]
+    /// [default: {title} [{id}]]
+    #[arg(long, value_name = "TEMPLATE")]
+    pub output_template: Option<String>,
+    /// yt-dlp format selector, passed as -f, e.g. "bestvideo[height<=1080]+bestaudio"
+    #[arg(long, value_name = "SELECTOR", conflicts_with = "smart_format")]
+    pub format: Option<String>,
+    /// More arguments for yt-dlp, split on spaces, e.g. "--embed-chapters --embed-thumbnail"
+    #[arg(long, value_name = "ARGS", alias = "ytdlp-args", value_delimiter = ' ', allow_hyphen_values = true)]
+    pub yt_dlp_args: Vec<String>,
+    /// Seconds yt-dlp gets to finalize its file after being interrupted, on exit or otherwise, before it's killed
+    #[arg(long, value_name = "SECS", default_value_t = 60)]
+    pub shutdown_grace: u64,
+    /// Remember every recording in this JSON-lines file, so finished VODs aren't downloaded
+    /// again after a restart and the history subcommand can list them
+    #[arg(long, value_name = "FILE")]
+    pub history_file: Option<PathBuf>
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print the channel ID and title for each handle, username or ID, then exit
+    Resolve {
+        /// @handle, legacy username, channel ID or channel URL
+        #[arg(required = true)]
+        channels: Vec<String>
+    },
+    /// Stop one recording of the recorder serving --events-addr, keeping the others going
+    Cancel {
+        #[arg(long)]
+        video_id: String
+    },
+    /// List the recordings remembered in --history-file, oldest first, then exit
+    History {
+        /// Only the latest N
+        #[arg(long, value_name = "N")]
+        last: Option<usize>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_color_only_overrides_auto() {
+        assert_eq!(ColorChoice::Auto.resolve(Some("1")), ColorChoice::Never);
+        assert_eq!(ColorChoice::Auto.resolve(Some("")), ColorChoice::Auto);
+        assert_eq!(ColorChoice::Auto.resolve(None), ColorChoice::Auto);
+        assert_eq!(ColorChoice::Always.resolve(Some("1")), ColorChoice::Always);
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    str::FromStr,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH}
+};
+
+use chrono::Utc;
+use clap::ValueEnum;
+use cron::Schedule;
+use serde::{Serialize, Deserialize};
+
+/// What drives the polls.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SchedulerKind {
+    /// tokio-cron-scheduler running the cron schedule
+    Cron,
+    /// A plain fixed-interval loop
+    Interval
+}
+
+/// Cron schedule firing every `interval`, when it divides a minute evenly;
+/// other intervals can't be written as a cron seconds field.
+pub fn cron_schedule(interval: Duration) -> Option<String> {
+    let secs = interval.as_secs();
+    (secs > 0 && 60 % secs == 0).then(|| format!("1/{} * * * * *", secs))
+}
+
+/// Parses an `--interval` value: seconds, optionally with an `s`, `m` or
+/// `h` suffix, like `90`, `60s` or `2m`.
+pub fn parse_interval(arg: &str) -> Result<u64, String> {
+    let (number, unit) = match arg.char_indices().last() {
+        Some((at, 's')) => (&arg[..at], 1),
+        Some((at, 'm')) => (&arg[..at], 60),
+        Some((at, 'h')) => (&arg[..at], 3600),
+        _ => (arg, 1)
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+        .ok_or_else(|| format!("expected seconds like 90, 60s, 2m or 1h, got {}", arg))
+}
+
+/// Parses `expr` as a cron schedule with a leading seconds field, the way
+/// the scheduler takes it.
+pub fn parse_cron(expr: &str) -> Result<Schedule, String> {
+    Schedule::from_str(expr).map_err(|err| format!("cron expression \"{}\": {}", expr, err))
+}
+
+/// Time between the next two firings of `schedule`, taken as its period.
+pub fn cron_period(schedule: &Schedule) -> Option<Duration> {
+    let mut upcoming = schedule.upcoming(Utc);
+    let (first, second) = (upcoming.next()?, upcoming.next()?);
+    (second - first).to_std().ok()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Tick {
+    Normal,
+    /// Wall-clock and monotonic time disagreed by this much since the last
+    /// tick, e.g. after a suspend/resume or a manual clock change.
+    Jumped(Duration),
+    /// Fired too soon after the previous tick; part of a burst of missed
+    /// ticks the scheduler is catching up on.
+    Coalesced
+}
+
+/// Tracks tick times to notice wall-clock jumps. `Instant` doesn't advance
+/// while the machine sleeps but `SystemTime` does, so the two drift apart.
+pub struct ClockWatch {
+    interval: Duration,
+    last: Mutex<Option<(Instant, SystemTime)>>
+}
+
+fn millis_since_epoch(time: SystemTime) -> i128 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as i128,
+        Err(err) => -(err.duration().as_millis() as i128)
+    }
+}
+
+impl ClockWatch {
+    pub fn new(interval: Duration) -> ClockWatch {
+        ClockWatch {
+            interval,
+            last: Mutex::new(None)
+        }
+    }
+
+    pub fn tick(&self) -> Tick {
+        self.observe(Instant::now(), SystemTime::now())
+    }
+
+    fn observe(&self, mono: Instant, wall: SystemTime) -> Tick {
+        let mut last = self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        let (last_mono, last_wall) = match *last {
+            Some(last) => last,
+            None => {
+                *last = Some((mono, wall));
+                return Tick::Normal;
+            }
+        };
+
+        let mono_elapsed = mono.saturating_duration_since(last_mono);
+        let wall_elapsed = millis_since_epoch(wall) - millis_since_epoch(last_wall);
+        let drift = Duration::from_millis((wall_elapsed - mono_elapsed.as_millis() as i128).unsigned_abs() as u64);
+
+        if drift > self.interval {
+            *last = Some((mono, wall));
+            return Tick::Jumped(drift);
+        }
+
+        if mono_elapsed < self.interval / 2 {
+            return Tick::Coalesced;
+        }
+
+        *last = Some((mono, wall));
+        Tick::Normal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_dividing_a_minute_become_cron_schedules() {
+        assert_eq!(cron_schedule(Duration::from_secs(10)).as_deref(), Some("1/10 * * * * *"));
+        assert_eq!(cron_schedule(Duration::from_secs(60)).as_deref(), Some("1/60 * * * * *"));
+        assert_eq!(cron_schedule(Duration::from_secs(7)), None);
+        assert_eq!(cron_schedule(Duration::from_secs(300)), None);
+    }
+
+    #[test]
+    fn intervals_take_units_and_cron_expressions_are_checked() {
+        assert_eq!(parse_interval("90"), Ok(90));
+        assert_eq!(parse_interval("60s"), Ok(60));
+        assert_eq!(parse_interval("2m"), Ok(120));
+        assert_eq!(parse_interval("1h"), Ok(3600));
+        assert!(parse_interval("m").is_err());
+        assert!(parse_interval("1d").is_err());
+
+        let schedule = parse_cron("0 */2 * * * *").unwrap();
+        assert_eq!(cron_period(&schedule), Some(Duration::from_secs(120)));
+        assert!(parse_cron("*/2 * * *").unwrap_err().contains("*/2 * * *"));
+    }
+
+    #[test]
+    fn suspend_is_a_jump_and_catch_up_ticks_coalesce() {
+        let watch = ClockWatch::new(Duration::from_secs(10));
+        let mono = Instant::now();
+        let wall = SystemTime::now();
+        assert_eq!(watch.observe(mono, wall), Tick::Normal);
+
+        let mono = mono + Duration::from_secs(10);
+        let wall = wall + Duration::from_secs(10);
+        assert_eq!(watch.observe(mono, wall), Tick::Normal);
+
+        // slept for an hour: monotonic time barely moved
+        let mono = mono + Duration::from_secs(10);
+        let wall = wall + Duration::from_secs(3610);
+        assert_eq!(watch.observe(mono, wall), Tick::Jumped(Duration::from_secs(3600)));
+
+        let mono = mono + Duration::from_millis(5);
+        let wall = wall + Duration::from_millis(5);
+        assert_eq!(watch.observe(mono, wall), Tick::Coalesced);
+    }
+}
@@ -0,0 +1,55 @@
+use std::{
+    io,
+    path::PathBuf
+};
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RecorderError {
+    #[error("invalid configuration: {0}")]
+    Config(String),
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("YouTube API error: {0}")]
+    Api(String),
+    #[error("channel {0} not found")]
+    ChannelNotFound(String),
+    #[error("channel {channel} is ambiguous, matches {}", .candidates.join(", "))]
+    AmbiguousChannel {
+        channel: String,
+        candidates: Vec<String>
+    },
+    #[error("YouTube API returned HTTP {status} {}: {}", .reason.as_deref().unwrap_or("(no reason)"), .message.as_deref().unwrap_or("no details"))]
+    ApiStatus {
+        status: u16,
+        reason: Option<String>,
+        message: Option<String>
+    },
+    #[error("failed to start {program}: {source}")]
+    Spawn {
+        program: String,
+        #[source]
+        source: io::Error
+    },
+    #[error("no space left in {}", .0.display())]
+    DiskFull(PathBuf),
+    #[error("recording of {video_id} isn't merged from separate video and audio: {reason}")]
+    NotMerged {
+        video_id: String,
+        reason: String
+    },
+    #[error("{video_id} is members-only or age-restricted, {hint}")]
+    AuthRequired {
+        video_id: String,
+        hint: String
+    },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("shutdown failed: {0}")]
+    Shutdown(String),
+    #[error("scheduler setup failed, {0}")]
+    Scheduler(String)
+}
+
+pub type Result<T> = std::result::Result<T, RecorderError>;
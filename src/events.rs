@@ -0,0 +1,304 @@
+use std::path::PathBuf;
+
+use chrono::Utc;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::{
+    postprocess::Step,
+    registry::RecordingKind,
+    report::RecordedFile,
+    ytdlp::Progress
+};
+
+/// Events buffered per subscriber before the slowest ones start missing some.
+const CAPACITY: usize = 256;
+
+/// Something that happened, as published on the [`EventBus`].
+#[derive(
+    Serialize,
+    Debug,
+    Clone
+)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Tick {
+        checked: usize,
+        live: usize,
+        started: usize,
+        already_recording: usize,
+        skipped: usize,
+        queued: usize,
+        errors: usize,
+        elapsed_ms: u128
+    },
+    /// A live stream not already being recorded was found
+    LiveDetected {
+        video_id: String,
+        channel_title: String,
+        title: String
+    },
+    RecordingStarted {
+        video_id: String,
+        kind: RecordingKind,
+        channel_title: String,
+        title: String
+    },
+    Progress {
+        video_id: String,
+        progress: Progress
+    },
+    RecordingStopped {
+        video_id: String,
+        exit_code: Option<i32>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        files: Vec<RecordedFile>,
+        /// Why the recording failed, if it did
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>
+    },
+    Error {
+        message: String
+    },
+    QuotaExceeded {
+        message: String
+    },
+    /// Finished recordings were deleted to stay within the retention limits
+    RecordingsDeleted {
+        dir: PathBuf,
+        files: Vec<PathBuf>
+    },
+    /// A step after recording failed for good on one of its files
+    PostProcessFailed {
+        video_id: String,
+        step: Step,
+        file: PathBuf,
+        error: String
+    }
+}
+
+#[derive(
+    Serialize,
+    Debug,
+    Clone
+)]
+pub struct Stamped {
+    pub at: String,
+    #[serde(flatten)]
+    pub event: Event
+}
+
+/// Fans events out to every subscriber. Publishing with nobody listening
+/// is a no-op.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Stamped>
+}
+
+impl Default for EventBus {
+    fn default() -> EventBus {
+        EventBus { sender: broadcast::channel(CAPACITY).0 }
+    }
+}
+
+impl EventBus {
+    pub fn publish(&self, event: Event) {
+        let _ = self.sender.send(Stamped {
+            at: Utc::now().to_rfc3339(),
+            event
+        });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Stamped> {
+        self.sender.subscribe()
+    }
+}
+
+/// `event` as one Server-Sent Events message.
+pub fn sse_message(event: &Stamped) -> String {
+    format!("data: {}\n\n", serde_json::to_string(event).unwrap())
+}
+
+#[cfg(feature = "events")]
+mod server {
+    use std::{
+        convert::Infallible,
+        io,
+        net::SocketAddr,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering}
+        }
+    };
+
+    use futures::stream;
+    use hyper::{
+        Body, Method, Request, Response, Server, StatusCode,
+        header::{CACHE_CONTROL, CONTENT_TYPE},
+        service::{make_service_fn, service_fn}
+    };
+    use log::info;
+    use tokio::sync::broadcast::error::RecvError;
+
+    use super::{EventBus, sse_message};
+    use crate::{
+        error::{RecorderError, Result},
+        metrics,
+        status::StatusSource
+    };
+
+    /// Counts a subscriber for as long as its stream is alive.
+    struct Subscriber(Arc<AtomicUsize>);
+
+    impl Drop for Subscriber {
+        fn drop(&mut self) {
+            self.0.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    fn status(code: StatusCode) -> Response<Body> {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = code;
+        response
+    }
+
+    fn html(page: String) -> Response<Body> {
+        Response::builder()
+            .header(CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(Body::from(page))
+            .unwrap()
+    }
+
+    fn metrics_text(source: &StatusSource) -> Response<Body> {
+        Response::builder()
+            .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Body::from(metrics::render(source)))
+            .unwrap()
+    }
+
+    fn json(value: &impl serde::Serialize) -> Response<Body> {
+        Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_string(value).unwrap()))
+            .unwrap()
+    }
+
+    fn stream_events(bus: &EventBus, subscribers: Arc<AtomicUsize>, max_subscribers: usize) -> Response<Body> {
+        if subscribers.fetch_add(1, Ordering::SeqCst) >= max_subscribers {
+            subscribers.fetch_sub(1, Ordering::SeqCst);
+            return status(StatusCode::SERVICE_UNAVAILABLE);
+        }
+
+        let subscriber = Subscriber(subscribers);
+        let events = stream::unfold((bus.subscribe(), subscriber), |(mut receiver, subscriber)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((Ok::<_, Infallible>(sse_message(&event)), (receiver, subscriber))),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None
+                }
+            }
+        });
+
+        Response::builder()
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(Body::wrap_stream(events))
+            .unwrap()
+    }
+
+    async fn handle(
+        request: Request<Body>,
+        bus: EventBus,
+        source: StatusSource,
+        subscribers: Arc<AtomicUsize>,
+        max_subscribers: usize
+    ) -> std::result::Result<Response<Body>, Infallible> {
+        let recordings = &source.recordings;
+        let path = request.uri().path();
+        let cancel = path
+            .strip_prefix("/recordings/")
+            .and_then(|rest| rest.strip_suffix("/cancel"));
+        Ok(match (request.method(), path, cancel) {
+            (&Method::GET, "/", _) => html(source.collect().html()),
+            (&Method::GET, "/status", _) => json(&source.collect()),
+            (&Method::GET, "/metrics", _) => metrics_text(&source),
+            (&Method::GET, "/events", _) => stream_events(&bus, subscribers, max_subscribers),
+            (&Method::GET, "/recordings", _) => json(&recordings.list()),
+            (&Method::POST, _, Some(video_id)) => match recordings.cancel(&video_id.to_owned()) {
+                Some(info) => {
+                    info!("Cancelling recording of {} on request", video_id);
+                    json(&info)
+                },
+                None => status(StatusCode::NOT_FOUND)
+            },
+            _ => status(StatusCode::NOT_FOUND)
+        })
+    }
+
+    /// Serves `GET /events`, the status page at `GET /` and as JSON at
+    /// `GET /status`, Prometheus metrics at `GET /metrics`, plus `GET /recordings` and
+    /// `POST /recordings/{video_id}/cancel` to control the recordings, on
+    /// `addr` until the process exits.
+    pub async fn serve(addr: SocketAddr, bus: EventBus, source: StatusSource, max_subscribers: usize) -> Result<()> {
+        let subscribers = Arc::new(AtomicUsize::new(0));
+        let make_service = make_service_fn(move |_| {
+            let bus = bus.clone();
+            let source = source.clone();
+            let subscribers = subscribers.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    handle(request, bus.clone(), source.clone(), subscribers.clone(), max_subscribers)
+                }))
+            }
+        });
+
+        let server = Server::try_bind(&addr)
+            .map_err(|err| RecorderError::Io(io::Error::other(err)))?
+            .serve(make_service);
+        info!("Serving the status page on http://{}/ and events on http://{}/events", addr, addr);
+        server.await.map_err(|err| RecorderError::Io(io::Error::other(err)))
+    }
+
+    /// Serves only `GET /metrics` on `addr` until the process exits.
+    pub async fn serve_metrics(addr: SocketAddr, source: StatusSource) -> Result<()> {
+        let make_service = make_service_fn(move |_| {
+            let source = source.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let response = match (request.method(), request.uri().path()) {
+                        (&Method::GET, "/metrics") => metrics_text(&source),
+                        _ => status(StatusCode::NOT_FOUND)
+                    };
+                    async move { Ok::<_, Infallible>(response) }
+                }))
+            }
+        });
+
+        let server = Server::try_bind(&addr)
+            .map_err(|err| RecorderError::Io(io::Error::other(err)))?
+            .serve(make_service);
+        info!("Serving metrics on http://{}/metrics", addr);
+        server.await.map_err(|err| RecorderError::Io(io::Error::other(err)))
+    }
+}
+
+#[cfg(feature = "events")]
+pub use server::{serve, serve_metrics};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn published_events_reach_subscribers_as_sse() {
+        let bus = EventBus::default();
+        bus.publish(Event::Error { message: "nobody listening".to_owned() });
+
+        let mut receiver = bus.subscribe();
+        bus.publish(Event::RecordingStopped { video_id: "abc".to_owned(), exit_code: Some(0), files: Vec::new(), error: None });
+        let message = sse_message(&receiver.try_recv().unwrap());
+        assert!(message.starts_with("data: {\"at\":"));
+        assert!(message.ends_with(",\"type\":\"recording_stopped\",\"video_id\":\"abc\",\"exit_code\":0}\n\n"));
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs,
+    path::Path,
+    time::Duration
+};
+
+use clap::ValueEnum;
+use serde::{Serialize, Deserialize};
+
+use crate::stream::{LiveStream, StreamDetails};
+
+const MEMBERS_ONLY_MARKERS: &[&str] = &["members only", "members-only", "member only", "メン限", "メンバー限定"];
+
+/// Video IDs mapped to where they were listed, for logging the rule that
+/// matched.
+pub type IdList = HashMap<String, String>;
+
+/// Expands `entries` into an [`IdList`]. Each entry is either a file with
+/// one ID per line (`#` starts a comment) or a comma-separated list of IDs.
+pub fn load_id_list(entries: &[String]) -> IdList {
+    let mut ids = IdList::new();
+
+    for entry in entries {
+        let path = Path::new(entry);
+        if path.is_file() {
+            for line in fs::read_to_string(path).unwrap_or_default().lines() {
+                let id = line.split('#').next().unwrap_or("").trim();
+                if !id.is_empty() {
+                    ids.insert(id.to_owned(), format!("{}", path.display()));
+                }
+            }
+        } else {
+            for id in entry.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+                ids.insert(id.to_owned(), "command line".to_owned());
+            }
+        }
+    }
+    ids
+}
+
+/// What to record when one poll of a channel finds both a premiere and a
+/// live stream of the same content.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicatePolicy {
+    RecordBoth,
+    PreferLive,
+    PreferPremiere
+}
+
+/// Whether two different videos look like the same content: the same title
+/// on the same channel. The API doesn't link a premiere to its live
+/// counterpart, so the title is the best hint there is.
+pub fn same_content(a: &LiveStream, b: &LiveStream) -> bool {
+    a.video_id != b.video_id
+        && a.channel_id == b.channel_id
+        && a.title.trim().to_lowercase() == b.title.trim().to_lowercase()
+}
+
+/// The first of `items` followed by other videos of the same content,
+/// dropping unrelated ones like the monitor always has.
+pub fn duplicates_of_first(mut items: Vec<LiveStream>) -> Vec<LiveStream> {
+    if items.is_empty() {
+        return items;
+    }
+    let first = items.remove(0);
+    let mut group: Vec<LiveStream> = Vec::new();
+    for item in items {
+        if same_content(&first, &item) && !group.iter().any(|seen: &LiveStream| seen.video_id == item.video_id) {
+            group.push(item);
+        }
+    }
+    group.insert(0, first);
+    group
+}
+
+/// Settings deciding which detected streams are worth recording.
+#[derive(Debug, Clone, Default)]
+pub struct Filters {
+    pub min_expected_duration: Option<Duration>,
+    pub skip_members_only: bool,
+    /// When set, only these videos are recorded
+    pub allowlist: Option<IdList>,
+    pub blocklist: IdList
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    Blocklisted(String),
+    NotAllowlisted,
+    TooShort(Duration),
+    MembersOnly
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::Blocklisted(source) => write!(f, "blocklisted in {}", source),
+            SkipReason::NotAllowlisted => write!(f, "not on the allowlist"),
+            SkipReason::TooShort(expected) => write!(f, "only scheduled for {}s", expected.as_secs()),
+            SkipReason::MembersOnly => write!(f, "looks members-only")
+        }
+    }
+}
+
+/// The Data API has no members-only flag, so this goes by the markers
+/// creators conventionally put in the title or description.
+pub fn looks_members_only(stream: &LiveStream) -> bool {
+    let text = format!("{} {}", stream.title, stream.description).to_lowercase();
+    MEMBERS_ONLY_MARKERS.iter().any(|marker| text.contains(marker))
+}
+
+impl Filters {
+    pub fn check(&self, stream: &LiveStream, details: Option<&StreamDetails>) -> Option<SkipReason> {
+        let video_id = &stream.video_id;
+        if let Some(source) = self.blocklist.get(video_id) {
+            return Some(SkipReason::Blocklisted(source.clone()));
+        }
+        if self.allowlist.as_ref().is_some_and(|allowlist| !allowlist.contains_key(video_id)) {
+            return Some(SkipReason::NotAllowlisted);
+        }
+        if let (Some(min), Some(expected)) = (self.min_expected_duration, details.and_then(|details| details.expected_duration)) {
+            if expected < min {
+                return Some(SkipReason::TooShort(expected));
+            }
+        }
+        if self.skip_members_only && looks_members_only(stream) {
+            return Some(SkipReason::MembersOnly);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(video_id: &str, channel_id: &str, title: &str) -> LiveStream {
+        serde_json::from_value::<crate::youtube::Item>(serde_json::json!({
+            "id": { "videoId": video_id },
+            "snippet": {
+                "channelId": channel_id,
+                "title": title,
+                "description": "",
+                "channelTitle": "Channel",
+                "liveBroadcastContent": "live"
+            }
+        })).unwrap().into()
+    }
+
+    #[test]
+    fn duplicates_need_the_same_title_on_the_same_channel() {
+        let items = vec![
+            item("live", "UC1", "Album release"),
+            item("other", "UC1", "Just chatting"),
+            item("premiere", "UC1", " album RELEASE"),
+            item("premiere", "UC1", "Album release"),
+            item("elsewhere", "UC2", "Album release")
+        ];
+        let ids: Vec<String> = duplicates_of_first(items).into_iter().map(|item| item.video_id).collect();
+        assert_eq!(ids, vec!["live", "premiere"]);
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError}
+};
+
+use chrono::Utc;
+use serde::{Serialize, Deserialize};
+
+use crate::registry::{RecordingInfo, RecordingKind, VideoId};
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Recording,
+    Completed,
+    Failed,
+    /// Still recording when the process last exited without finishing it
+    Interrupted
+}
+
+impl Status {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Recording => "recording",
+            Status::Completed => "completed",
+            Status::Failed => "failed",
+            Status::Interrupted => "interrupted"
+        }
+    }
+}
+
+/// One recording as remembered in the history file.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    PartialEq
+)]
+pub struct HistoryEntry {
+    pub video_id: VideoId,
+    pub kind: RecordingKind,
+    pub channel_title: String,
+    pub title: String,
+    pub started_at: String,
+    pub ended_at: Option<String>,
+    pub output_dir: PathBuf,
+    #[serde(default)]
+    pub files: Vec<PathBuf>,
+    pub status: Status
+}
+
+/// Every recording made with `--history-file`, kept as JSON lines: each
+/// line is the latest state of one recording, so later lines win and a
+/// crash loses at most the line being written.
+pub struct History {
+    path: PathBuf,
+    entries: Mutex<BTreeMap<VideoId, HistoryEntry>>
+}
+
+impl HistoryEntry {
+    /// A recording of `info` into `output_dir` that just started.
+    pub fn started(info: &RecordingInfo, output_dir: &Path) -> HistoryEntry {
+        HistoryEntry {
+            video_id: info.video_id.clone(),
+            kind: info.kind,
+            channel_title: info.channel_title.clone(),
+            title: info.title.clone(),
+            started_at: info.started_at.clone(),
+            ended_at: None,
+            output_dir: output_dir.to_path_buf(),
+            files: Vec::new(),
+            status: Status::Recording
+        }
+    }
+}
+
+/// The latest state of every recording in the history file at `path`,
+/// none if there's no file yet.
+fn load(path: &Path) -> io::Result<BTreeMap<VideoId, HistoryEntry>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err)
+    };
+    let mut entries = BTreeMap::new();
+    for (number, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let entry: HistoryEntry = serde_json::from_str(line)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{} line {}: {}", path.display(), number + 1, err)))?;
+        entries.insert(entry.video_id.clone(), entry);
+    }
+    Ok(entries)
+}
+
+/// Every recording in the history file at `path`, oldest first, without
+/// touching the file, which a running recorder may be writing to.
+pub fn read(path: &Path) -> io::Result<Vec<HistoryEntry>> {
+    let mut entries: Vec<HistoryEntry> = load(path)?.into_values().collect();
+    entries.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+    Ok(entries)
+}
+
+impl History {
+    /// Reads the history at `path`, or starts one if it doesn't exist.
+    /// Recordings it still lists as running were cut off by the last exit
+    /// and are marked interrupted.
+    pub fn open(path: &Path) -> io::Result<History> {
+        let history = History { path: path.to_path_buf(), entries: Mutex::new(load(path)?) };
+        for mut entry in history.entries().into_iter().filter(|entry| entry.status == Status::Recording) {
+            entry.status = Status::Interrupted;
+            history.save(entry)?;
+        }
+        Ok(history)
+    }
+
+    /// Appends `entry` as the latest state of its recording.
+    pub fn save(&self, entry: HistoryEntry) -> io::Result<()> {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        entries.insert(entry.video_id.clone(), entry);
+        Ok(())
+    }
+
+    /// Notes that the recording of `video_id` ended with `status`, leaving
+    /// `files`. Does nothing for recordings it doesn't know.
+    pub fn finish(&self, video_id: &VideoId, status: Status, files: Vec<PathBuf>) -> io::Result<()> {
+        let Some(mut entry) = self.get(video_id) else {
+            return Ok(());
+        };
+        entry.status = status;
+        entry.ended_at = Some(Utc::now().to_rfc3339());
+        entry.files = files;
+        self.save(entry)
+    }
+
+    pub fn get(&self, video_id: &VideoId) -> Option<HistoryEntry> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner).get(video_id).cloned()
+    }
+
+    /// Every recording, oldest first.
+    pub fn entries(&self) -> Vec<HistoryEntry> {
+        let mut entries: Vec<HistoryEntry> = self.entries.lock().unwrap_or_else(PoisonError::into_inner).values().cloned().collect();
+        entries.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+        entries
+    }
+
+    /// Whether `video_id` was downloaded in full as a VOD, so it needn't be
+    /// downloaded again.
+    pub fn has_vod(&self, video_id: &VideoId) -> bool {
+        self.get(video_id).is_some_and(|entry| entry.kind == RecordingKind::Vod && entry.status == Status::Completed)
+    }
+}
+
+/// `entry` as one tab-separated line of the `history` listing.
+pub fn listing_line(entry: &HistoryEntry) -> String {
+    let files: Vec<String> = entry.files.iter().map(|file| file.display().to_string()).collect();
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}",
+        entry.started_at,
+        entry.status.name(),
+        entry.video_id,
+        entry.channel_title,
+        entry.title,
+        files.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(video_id: &str, kind: RecordingKind, started_at: &str) -> HistoryEntry {
+        HistoryEntry {
+            video_id: video_id.to_owned(),
+            kind,
+            channel_title: "Someone".to_owned(),
+            title: "Stream".to_owned(),
+            started_at: started_at.to_owned(),
+            ended_at: None,
+            output_dir: PathBuf::from("."),
+            files: Vec::new(),
+            status: Status::Recording
+        }
+    }
+
+    #[test]
+    fn restarts_see_finished_vods_and_interrupted_recordings() {
+        let path = std::env::temp_dir().join(format!("ytlr-history-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let history = History::open(&path).unwrap();
+        history.save(entry("live", RecordingKind::Live, "2024-01-02T00:00:00Z")).unwrap();
+        history.save(entry("vod", RecordingKind::Vod, "2024-01-01T00:00:00Z")).unwrap();
+        history.finish(&"vod".to_owned(), Status::Completed, vec![PathBuf::from("Stream [vod].mp4")]).unwrap();
+        history.finish(&"unknown".to_owned(), Status::Failed, Vec::new()).unwrap();
+
+        let reopened = History::open(&path).unwrap();
+        assert!(reopened.has_vod(&"vod".to_owned()));
+        assert!(!reopened.has_vod(&"live".to_owned()));
+        let entries = reopened.entries();
+        assert_eq!(read(&path).unwrap(), entries);
+        assert_eq!(entries.iter().map(|entry| entry.video_id.as_str()).collect::<Vec<_>>(), ["vod", "live"]);
+        assert_eq!(entries[1].status, Status::Interrupted);
+        assert_eq!(
+            listing_line(&entries[0]),
+            "2024-01-01T00:00:00Z\tcompleted\tvod\tSomeone\tStream\tStream [vod].mp4"
+        );
+
+        fs::write(&path, "not json\n").unwrap();
+        assert!(History::open(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,55 @@
+use std::{
+    io,
+    process::ExitStatus,
+    time::Duration
+};
+
+use tokio::process::Command;
+
+use crate::error::{RecorderError, Result};
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+/// Runs `command` through the shell with `env` added, killing it if it
+/// takes longer than `timeout`.
+pub async fn run(command: &str, env: &[(String, String)], timeout: Duration) -> Result<ExitStatus> {
+    let mut child = shell(command)
+        .envs(env.iter().cloned())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|source| RecorderError::Spawn { program: command.to_owned(), source })?;
+
+    match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => Ok(status?),
+        Err(_) => Err(RecorderError::Io(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("hook {} timed out after {:?}", command, timeout)
+        )))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn exit_status_env_and_timeout() {
+        let env = vec![("VIDEO_ID".to_owned(), "abc".to_owned())];
+        let timeout = Duration::from_secs(5);
+        assert!(run("test \"$VIDEO_ID\" = abc", &env, timeout).await.unwrap().success());
+        assert_eq!(run("exit 3", &env, timeout).await.unwrap().code(), Some(3));
+        assert!(run("sleep 5", &env, Duration::from_millis(50)).await.is_err());
+    }
+}
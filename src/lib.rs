@@ -0,0 +1,29 @@
+pub mod cli;
+pub mod clock;
+pub mod config;
+pub mod error;
+pub mod events;
+pub mod filters;
+pub mod history;
+pub mod hooks;
+pub mod manifest;
+pub mod merge;
+pub mod metadata;
+pub mod metrics;
+pub mod monitor;
+pub mod notify;
+pub mod postprocess;
+pub mod provider;
+pub mod queue;
+pub mod quota;
+pub mod registry;
+pub mod report;
+pub mod resolver;
+pub mod retention;
+pub mod status;
+pub mod stream;
+pub mod timezone;
+pub mod twitch;
+pub mod upcoming;
+pub mod youtube;
+pub mod ytdlp;
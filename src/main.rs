@@ -1,207 +1,631 @@
 use std::{
     collections::HashMap,
-    process::{Command, Stdio}
+    fmt::Display,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    time::Duration
 };
 
-use clap::Parser;
-use env_logger::Env;
-use log::info;
-use serde::{Serialize, Deserialize};
-use reqwest;
-use tokio_cron_scheduler::{JobScheduler, Job};
-use sysinfo::{ProcessExt, System, SystemExt};
-
-
-
-#[derive(
-    Serialize,
-    Deserialize,
-    Debug
-)]
-struct PageInfo {
-    #[serde(alias = "totalResults")]
-    total_results: i32,
-    #[serde(alias = "resultsPerPage")]
-    results_per_page: i32
-}
-
-#[derive(
-    Serialize,
-    Deserialize,
-    Debug
-)]
-struct UserRespItem {
-    kind: String,
-    etag: String,
-    id: String
-}
-
-#[derive(
-    Serialize,
-    Deserialize,
-    Debug
-)]
-struct UserResponse {
-    kind: String,
-    etag: String,
-    #[serde(alias = "pageInfo")]
-    page_info: PageInfo,
-    items: Vec<UserRespItem>
-}
-
-#[derive(
-    Serialize,
-    Deserialize,
-    Debug
-)]
-struct Thumbnail {
-    url: String,
-    width: i32,
-    height: i32
-}
-
-#[derive(
-    Serialize,
-    Deserialize,
-    Debug
-)]
-struct Snippet {
-    #[serde(alias = "publishedAt")]
-    published_at: String,
-    #[serde(alias = "channelId")]
-    channel_id: String,
-    title: String,
-    description: String,
-    thumbnails: HashMap<String, Thumbnail>,
-    #[serde(alias = "channelTitle")]
-    channel_title: String,
-    #[serde(alias = "liveBroadcastContent")]
-    live_broadcast_content: String,
-    #[serde(alias = "publishTime")]
-    publish_time: String
-}
-
-#[derive(
-    Serialize,
-    Deserialize,
-    Debug
-)]
-struct Id {
-    kind: String,
-    #[serde(alias = "videoId")]
-    video_id: String
-}
-
-#[derive(
-    Serialize,
-    Deserialize,
-    Debug
-)]
-struct Item {
-    kind: String,
-    etag: String,
-    id: Id,
-    snippet: Snippet
-}
-
-#[derive(
-    Serialize,
-    Deserialize,
-    Debug
-)]
-struct YoutubeSearchListResponse {
-    kind: String,
-    etag: String,
-    #[serde(alias = "pageInfo")]
-    page_info: PageInfo,
-    items: Vec<Item>
-}
-
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    #[arg(short, long)]
-    api_key: String,
-    #[arg(short, long)]
-    channel: String,
-    #[arg(short, long, default_value_t = false)]
-    quiet: bool
-}
-
-fn user_search(api_key: &String, channel: &String) -> String {
-    format!("https://www.googleapis.com/youtube/v3/channels?key={}&forUsername={}&part=id", api_key, channel)
-}
-
-fn video_search(api_key: &String, user_id: &String) -> String {
-    format!("https://www.googleapis.com/youtube/v3/search?part=snippet&channelId={}&type=video&eventType=live&key={}", user_id, api_key)
-}
-
-fn youtube_live_link(video_id: &String) -> String {
-    format!("https://www.youtube.com/watch?v={}", video_id)
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use env_logger::{Env, WriteStyle};
+use log::{debug, error, info, warn};
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::{sync::Mutex, task::JoinHandle, time::MissedTickBehavior};
+use tokio_cron_scheduler::{JobScheduler, JobSchedulerError, Job};
+use uuid::Uuid;
+
+use auto_youtube_live_recorder::{
+    cli::{Args, ColorChoice, Command},
+    clock::{self, ClockWatch, SchedulerKind, Tick},
+    config::{Config, SharedConfig},
+    error::RecorderError,
+    events::EventBus,
+    history::{self, History},
+    metrics::Metrics,
+    monitor::{self, Context},
+    notify,
+    provider::Platform,
+    registry::ActiveRecordings,
+    report,
+    resolver::ChannelResolver,
+    status::StatusSource,
+    youtube,
+    ytdlp
+};
+
+/// Share of the poll interval a tick may take, leaving the rest for its
+/// bookkeeping.
+const TICK_BUDGET: f64 = 0.8;
+
+/// Exit code when the scheduler can't be set up.
+const SCHEDULER_EXIT: i32 = 4;
+
+/// Time on top of `--shutdown-grace` for recordings to leave the registry
+/// once yt-dlp was killed: the history, events and restarts checks.
+const SHUTDOWN_MARGIN: Duration = Duration::from_secs(15);
+
+/// How often browser cookies are exported for scrape detection without
+/// `--cookie-refresh-interval`.
+const BROWSER_COOKIE_REFRESH: Duration = Duration::from_secs(3600);
+
+/// What a poll job polls.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Target {
+    Channel(String),
+    /// The `--url` targets and disk limits
+    Upkeep
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Channel(channel) => write!(f, "{}", channel),
+            Target::Upkeep => write!(f, "upkeep")
+        }
+    }
+}
+
+/// How often a job runs, and on what cron schedule with the cron scheduler.
+type Schedule = (Duration, Option<String>);
+
+impl Target {
+    fn schedule(&self, config: &Config) -> Schedule {
+        match self {
+            Target::Channel(channel) => (config.channel_interval(channel), config.channel_cron(channel)),
+            Target::Upkeep => {
+                let interval = config.poll_interval();
+                (interval, config.cron.clone().or_else(|| clock::cron_schedule(interval)))
+            }
+        }
+    }
+}
+
+/// State shared by every poll, whichever scheduler drives them.
+struct Poller {
+    shared: Arc<SharedConfig>,
+    context: Context
+}
+
+impl Poller {
+    async fn poll(&self, target: &Target, clock: &ClockWatch, tick: impl Display) {
+        match clock.tick() {
+            Tick::Coalesced => {
+                debug!("skipping catch-up tick {} of {}", tick, target);
+                return;
+            },
+            Tick::Jumped(drift) => warn!("Clock jumped by {:?} since the last tick, polling {} now", drift, target),
+            Tick::Normal => {}
+        }
+        debug!("job of {} is running as {}", target, tick);
+        let config = self.shared.current();
+        let summary = match target {
+            Target::Channel(channel) => {
+                let timeout = config.channel_interval(channel).mul_f64(TICK_BUDGET);
+                monitor::catch_panic(monitor::run_tick(config, self.context.clone(), std::slice::from_ref(channel), timeout)).await
+            },
+            Target::Upkeep => monitor::catch_panic(monitor::upkeep(config, self.context.clone())).await
+        };
+        if let Some(summary) = summary.filter(|summary| summary.checked > 0 || summary.errors > 0) {
+            info!("{}: {}", target, summary);
+        }
+    }
+
+    /// Polls every channel at once, then does the upkeep, for `--once` and
+    /// `--run-now`.
+    async fn poll_all(&self) {
+        let config = self.shared.current();
+        let timeout = config.poll_interval().mul_f64(TICK_BUDGET);
+        let channels = config.channels.clone();
+        if let Some(summary) = monitor::catch_panic(monitor::run_tick(config.clone(), self.context.clone(), &channels, timeout)).await {
+            info!("{}", summary);
+        }
+        if let Some(summary) = monitor::catch_panic(monitor::upkeep(config, self.context.clone())).await.filter(|summary| summary.checked > 0) {
+            info!("upkeep: {}", summary);
+        }
+    }
+}
+
+#[cfg(feature = "events")]
+fn serve_events(addr: SocketAddr, bus: EventBus, status: StatusSource, max_subscribers: usize) -> Result<(), RecorderError> {
+    tokio::spawn(async move {
+        if let Err(err) = auto_youtube_live_recorder::events::serve(addr, bus, status, max_subscribers).await {
+            error!("Event stream on {} stopped: {}", addr, err);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(feature = "events"))]
+fn serve_events(_addr: SocketAddr, _bus: EventBus, _status: StatusSource, _max_subscribers: usize) -> Result<(), RecorderError> {
+    Err(RecorderError::Config("events_addr needs a build with the events feature".to_owned()))
+}
+
+#[cfg(feature = "events")]
+fn serve_metrics(addr: SocketAddr, status: StatusSource) -> Result<(), RecorderError> {
+    tokio::spawn(async move {
+        if let Err(err) = auto_youtube_live_recorder::events::serve_metrics(addr, status).await {
+            error!("Metrics endpoint on {} stopped: {}", addr, err);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(feature = "events"))]
+fn serve_metrics(_addr: SocketAddr, _status: StatusSource) -> Result<(), RecorderError> {
+    Err(RecorderError::Config("metrics_addr needs a build with the events feature".to_owned()))
+}
+
+#[cfg(unix)]
+async fn reload_on_sighup(mut hangups: Signal, shared: Arc<SharedConfig>, matches: ArgMatches, polling: Arc<Mutex<Polling>>) {
+    while hangups.recv().await.is_some() {
+        match Config::load(&matches) {
+            Ok(config) => {
+                let changes = shared.replace(config);
+                if changes.is_empty() {
+                    info!("Reloaded config, nothing changed");
+                }
+                for change in changes.applied {
+                    info!("Reloaded config, {}", change);
+                }
+                for change in changes.restart_required {
+                    warn!("Reloaded config, but {} needs a restart to take effect", change);
+                }
+                if let Err(err) = polling.lock().await.sync(&shared.current()).await {
+                    error!("Rescheduling the polls after the reload failed: {}", err);
+                }
+            },
+            Err(err) => error!("Config reload failed, keeping the current config: {}", err)
+        }
+    }
+}
+
+/// Prints `channel<TAB>id<TAB>title` for every channel that resolves,
+/// returning the exit code: 2 if any wasn't found, 3 if any was ambiguous.
+async fn resolve(config: &Config, channels: &[String]) -> i32 {
+    let resolver = ChannelResolver::default();
+    let mut code = 0;
+    for channel in channels {
+        match resolver.resolve(&config.api_key, channel).await {
+            Ok(resolved) => println!("{}\t{}\t{}", channel, resolved.id, resolved.title),
+            Err(err) => {
+                eprintln!("{}", err);
+                code = code.max(match err {
+                    RecorderError::ChannelNotFound(_) => 2,
+                    RecorderError::AmbiguousChannel { .. } => 3,
+                    _ => 1
+                });
+            }
+        }
+    }
+    code
+}
+
+/// Prints the recordings in `--history-file`, the `last` ones if given,
+/// returning the exit code.
+fn list_history(config: &Config, last: Option<usize>) -> i32 {
+    let Some(path) = &config.history_file else {
+        eprintln!("history needs --history-file");
+        return 1;
+    };
+    match history::read(path) {
+        Ok(entries) => {
+            let skip = last.map_or(0, |last| entries.len().saturating_sub(last));
+            for entry in &entries[skip..] {
+                println!("{}", history::listing_line(entry));
+            }
+            0
+        },
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            1
+        }
+    }
+}
+
+/// Asks the recorder serving `events_addr` to stop recording `video_id`,
+/// returning the exit code: 2 if it isn't recording that video.
+async fn cancel(config: &Config, video_id: &str) -> i32 {
+    let Some(addr) = config.events_addr else {
+        eprintln!("cancel needs --events-addr of the running recorder");
+        return 1;
+    };
+    let url = format!("http://{}/recordings/{}/cancel", addr, video_id);
+    match reqwest::Client::new().post(url).send().await {
+        Ok(response) if response.status().is_success() => {
+            println!("Cancelled recording of {}", video_id);
+            0
+        },
+        Ok(response) if response.status() == reqwest::StatusCode::NOT_FOUND => {
+            eprintln!("{} is not being recorded", video_id);
+            2
+        },
+        Ok(response) => {
+            eprintln!("recorder at {} answered {}", addr, response.status());
+            1
+        },
+        Err(err) => {
+            eprintln!("can't reach the recorder at {}: {}", addr, err.without_url());
+            1
+        }
+    }
+}
+
+/// What fires one job's polls.
+enum Trigger {
+    Cron(Uuid),
+    Interval(JoinHandle<()>)
+}
+
+/// A job per channel, plus one for the upkeep, each on its own schedule
+/// and kept in step with the config until shutdown.
+struct Polling {
+    scheduler: Option<JobScheduler>,
+    poller: Arc<Poller>,
+    jobs: HashMap<Target, (Schedule, Trigger)>,
+    /// Whether interval jobs added next wait a whole interval first, since
+    /// the startup poll stands in for their first tick
+    skip_first: bool
+}
+
+impl Polling {
+    /// Starts the scheduler of `kind`, without jobs yet.
+    async fn new(kind: SchedulerKind, poller: Arc<Poller>, skip_first: bool) -> Result<Polling, RecorderError> {
+        let scheduler = match kind {
+            SchedulerKind::Cron => {
+                let scheduler = JobScheduler::new().await.map_err(|err| scheduler_failed("can't create the scheduler", err))?;
+                scheduler.start().await.map_err(|err| scheduler_failed("can't start the scheduler", err))?;
+                Some(scheduler)
+            },
+            SchedulerKind::Interval => None
+        };
+        Ok(Polling { scheduler, poller, jobs: HashMap::new(), skip_first })
+    }
+
+    /// Adds a job for every channel of `config` and the upkeep, and removes
+    /// or reschedules those whose channel is gone or whose schedule changed.
+    async fn sync(&mut self, config: &Config) -> Result<(), RecorderError> {
+        let wanted: HashMap<Target, Schedule> = config.channels
+            .iter()
+            .map(|channel| Target::Channel(channel.clone()))
+            .chain(std::iter::once(Target::Upkeep))
+            .map(|target| {
+                let schedule = target.schedule(config);
+                (target, schedule)
+            })
+            .collect();
+        let stale: Vec<Target> = self.jobs
+            .iter()
+            .filter(|(target, (schedule, _))| wanted.get(*target) != Some(schedule))
+            .map(|(target, _)| target.clone())
+            .collect();
+        for target in stale {
+            if let Some((_, trigger)) = self.jobs.remove(&target) {
+                self.remove(trigger).await;
+            }
+        }
+        for (target, schedule) in wanted {
+            if self.jobs.contains_key(&target) {
+                continue;
+            }
+            let trigger = self.add(target.clone(), &schedule).await?;
+            debug!("polling {} every {:?}", target, schedule.0);
+            self.jobs.insert(target, (schedule, trigger));
+        }
+        self.skip_first = false;
+        Ok(())
+    }
+
+    /// Registers the job polling `target` on `schedule`.
+    async fn add(&self, target: Target, (interval, cron): &Schedule) -> Result<Trigger, RecorderError> {
+        let interval = *interval;
+        let poller = self.poller.clone();
+        let clock = Arc::new(ClockWatch::new(interval));
+        let Some(scheduler) = &self.scheduler else {
+            let skip = if self.skip_first { interval } else { Duration::ZERO };
+            return Ok(Trigger::Interval(tokio::spawn(async move {
+                let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + skip, interval);
+                ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                for tick in 0u64.. {
+                    ticks.tick().await;
+                    poller.poll(&target, &clock, tick).await;
+                }
+            })));
+        };
+        let run = move |uuid, _l| {
+            let (poller, clock, target) = (poller.clone(), clock.clone(), target.clone());
+            Box::pin(async move { poller.poll(&target, &clock, uuid).await }) as Pin<Box<dyn Future<Output = ()> + Send>>
+        };
+        let job = match cron {
+            Some(schedule) => Job::new_async(schedule.as_str(), run)
+                .map_err(|err| scheduler_failed(&format!("can't use cron expression \"{}\"", schedule), err))?,
+            None => Job::new_repeated_async(interval, run)
+                .map_err(|err| scheduler_failed(&format!("can't schedule a job every {:?}", interval), err))?
+        };
+        let uuid = scheduler.add(job).await.map_err(|err| scheduler_failed("can't add the poll job", err))?;
+        Ok(Trigger::Cron(uuid))
+    }
+
+    async fn remove(&self, trigger: Trigger) {
+        match (trigger, &self.scheduler) {
+            (Trigger::Cron(uuid), Some(scheduler)) => {
+                if let Err(err) = scheduler.remove(&uuid).await {
+                    warn!("Removing a poll job failed: {}", err);
+                }
+            },
+            (Trigger::Cron(_), None) => {},
+            (Trigger::Interval(ticks), _) => ticks.abort()
+        }
+    }
+
+    async fn stop(&mut self) {
+        for (_, (_, trigger)) in std::mem::take(&mut self.jobs) {
+            if let Trigger::Interval(ticks) = trigger {
+                ticks.abort();
+            }
+        }
+        if let Some(mut scheduler) = self.scheduler.take() {
+            if let Err(err) = scheduler.shutdown().await {
+                warn!("Stopping the scheduler failed: {}", err);
+            }
+        }
+    }
+}
+
+fn scheduler_failed(step: &str, err: JobSchedulerError) -> RecorderError {
+    RecorderError::Scheduler(format!("{}: {}", step, err))
+}
+
+/// Resolves on Ctrl-C, or on SIGTERM as sent by service managers.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {},
+                _ = terminate.recv() => {}
+            }
+            return;
+        },
+        Err(err) => warn!("Can't listen for SIGTERM, only Ctrl-C stops the recorder: {}", err)
+    }
+    if let Err(err) = tokio::signal::ctrl_c().await {
+        warn!("Can't listen for Ctrl-C, running until killed: {}", err);
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Exports `--cookies-from-browser` for scrape detection, which sends them
+/// along to see members-only streams.
+async fn export_browser_cookies(config: &Config) {
+    let Some(browser) = config.cookies_from_browser.as_deref() else {
+        return;
+    };
+    if config.detection_method != youtube::DetectionMethod::Scrape {
+        return;
+    }
+    match ytdlp::export_browser_cookies(&config.record_options().program, browser, &ytdlp::browser_cookie_jar()).await {
+        Ok(()) => debug!("Exported {} cookies for scrape detection", browser),
+        Err(err) => warn!("Exporting {} cookies failed, scrape detection may miss members-only streams: {}", browser, err)
+    }
+}
+
+/// Keeps the browser cookies scrape detection sends fresh, as configured
+/// at the time.
+async fn refresh_browser_cookies(shared: Arc<SharedConfig>) {
+    loop {
+        let every = shared.current().cookie_refresh_interval.map_or(BROWSER_COOKIE_REFRESH, Duration::from_secs);
+        tokio::time::sleep(every).await;
+        export_browser_cookies(&shared.current()).await;
+    }
+}
+
+/// Waits until every recording has exited and its bookkeeping is done.
+async fn recordings_finished(active: &ActiveRecordings) {
+    while !active.list().is_empty() {
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+}
+
+/// Prints every problem with `config`, or that it's fine, returning the
+/// exit code. Only talks to the API with `check_api`.
+async fn check_config(config: &Config, check_api: bool) -> i32 {
+    let mut problems = config.check();
+    if check_api {
+        if let Err(err) = youtube::configure_client(&config.client_options()) {
+            problems.push(format!("HTTP client: {}", err));
+        }
+        let resolver = ChannelResolver::default();
+        for channel in config.channels.iter().filter(|channel| Platform::of(channel) == Platform::YouTube) {
+            if let Err(err) = resolver.resolve(config.api_key_for(channel), channel).await {
+                problems.push(format!("{}: {}", channel, err));
+            }
+        }
+    }
+    if problems.is_empty() {
+        println!("Config OK");
+        return 0;
+    }
+    for problem in &problems {
+        eprintln!("problem: {}", problem);
+    }
+    1
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let _args = Args::parse(); // for --help
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
-    let mut scheduler = JobScheduler::new().await?;
-    
-    scheduler.add(Job::new_async("1/10 * * * * *", |uuid, _l| Box::pin( async move {
-        info!("job is running as {}", uuid);
-        let args = Args::parse();
-        let channel = args.channel;
-        let api_key = args.api_key;
-        let user = 
-            reqwest::get(user_search(&api_key, &channel))
-                .await
-                .unwrap()
-                .json::<UserResponse>()
-                .await
-                .unwrap();
-    
-        let search = 
-            reqwest::get(video_search(&api_key, &user.items[0].id))
-                .await
-                .unwrap()
-                .json::<YoutubeSearchListResponse>()
-                .await
-                .unwrap();
-    
-        let mut yt_dlp: String = "yt-dlp".to_owned();
-
-        if cfg!(windows) {
-            yt_dlp.push_str(".exe");
-        }
-
-        let is_running = System::new_all()        
-            .processes_by_exact_name("yt-dlp.exe")
-            .any(|process| process.cmd()[1] == youtube_live_link(&search.items[0].id.video_id));
-
-        
-
-        if search.items.len() >= 1 && !is_running {
-            info!("Recording...");
-            let mut cmd = Command::new("yt-dlp")
-            .args(&[youtube_live_link(&search.items[0].id.video_id)])
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .spawn()
-            .unwrap();
-
-            let status = cmd.wait();
-            info!("Exited with status {:?}", status);
-        }
-    })).unwrap()).await?;
-
-    #[cfg(feature = "signal")]
-    scheduler.shutdown_on_ctrl_c();
-    scheduler.set_shutdown_handler(Box::new(|| {
-      Box::pin(async move {
-        println!("Exiting...");
-      })
-    }));
-
-    scheduler.start().await.unwrap();
-    tokio::time::sleep(core::time::Duration::from_secs(10)).await;
+    let matches = Args::command().get_matches();
+    if matches.get_flag("check_config") {
+        let code = match Config::load(&matches) {
+            Ok(config) => check_config(&config, matches.get_flag("check_api")).await,
+            Err(err) => {
+                eprintln!("problem: {}", err);
+                1
+            }
+        };
+        std::process::exit(code);
+    }
+    let config = Config::load(&matches)?;
+
+    if matches.get_flag("dump_config") {
+        println!("{}", config.dump());
+        return Ok(());
+    }
+
+    let level = match matches.get_count("verbose") {
+        0 => "info",
+        1 => "debug",
+        _ => "trace"
+    };
+    let style = match config.color.resolve(std::env::var("NO_COLOR").ok().as_deref()) {
+        ColorChoice::Auto => WriteStyle::Auto,
+        ColorChoice::Always => WriteStyle::Always,
+        ColorChoice::Never => WriteStyle::Never
+    };
+    env_logger::Builder::from_env(Env::default().default_filter_or(level)).write_style(style).init();
+
+    youtube::configure_client(&config.client_options())?;
+
+    match Args::from_arg_matches(&matches)?.command {
+        Some(Command::Resolve { channels }) => std::process::exit(resolve(&config, &channels).await),
+        Some(Command::Cancel { video_id }) => std::process::exit(cancel(&config, &video_id).await),
+        Some(Command::History { last }) => std::process::exit(list_history(&config, last)),
+        None => {}
+    }
+
+    if let Some(mode) = config.recover_parts {
+        for dir in config.output_dirs() {
+            ytdlp::recover_parts(&dir, mode)?;
+        }
+    }
+
+    let shared = Arc::new(SharedConfig::new(config));
+    // listened for from the start, since a hangup would end the process
+    #[cfg(unix)]
+    let hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => Some(hangups),
+        Err(err) => {
+            warn!("Can't listen for SIGHUP, config reload disabled: {}", err);
+            None
+        }
+    };
+
+    let history = match &shared.current().history_file {
+        Some(path) => Some(Arc::new(History::open(path).map_err(|err| {
+            RecorderError::Config(format!("history file {}: {}", path.display(), err))
+        })?)),
+        None => None
+    };
+    let context = Context { history, ..Context::default() };
+    let active = context.recordings.clone();
+    let post_jobs = context.post_jobs.clone();
+    let current = shared.current();
+    if let Some(path) = current.report_file.clone() {
+        tokio::spawn(report::write_reports(
+            path,
+            Duration::from_secs(current.report_interval),
+            shared.clone(),
+            context.recordings.clone(),
+            context.stats.clone()
+        ));
+    }
+    tokio::spawn(notify::run(context.events.clone(), shared.clone()));
+    if let Some(secs) = current.heartbeat {
+        tokio::spawn(monitor::heartbeat(context.clone(), Duration::from_secs(secs)));
+    }
+    if current.events_addr.is_some() || current.metrics_addr.is_some() {
+        let metrics = Arc::new(Metrics::default());
+        tokio::spawn(metrics.clone().follow(context.events.clone()));
+        let status = StatusSource {
+            shared: shared.clone(),
+            recordings: context.recordings.clone(),
+            stats: context.stats.clone(),
+            metrics,
+            queue: context.queue.clone()
+        };
+        if let Some(addr) = current.events_addr {
+            serve_events(addr, context.events.clone(), status.clone(), current.events_max_subscribers)?;
+        }
+        if let Some(addr) = current.metrics_addr {
+            serve_metrics(addr, status)?;
+        }
+    }
+
+    let interval = current.poll_interval();
+    let grace = current.shutdown_grace;
+    if current.cron.is_none() && interval.as_secs() > current.interval {
+        warn!(
+            "--interval {}s would spend quota too fast with {:?} detection, polling every {:?} instead (--allow-aggressive-polling to override)",
+            current.interval, current.detection_method, interval
+        );
+    }
+    export_browser_cookies(&current).await;
+    tokio::spawn(refresh_browser_cookies(shared.clone()));
+    let youtube_channels = current.channels.iter().filter(|channel| Platform::of(channel) == Platform::YouTube).count();
+    let quota = current.daily_quota_need();
+    if let Some(budget) = current.quota_budget.filter(|budget| quota > *budget) {
+        info!("Polling {} YouTube channels needs about {} quota units a day, slowing down to stay within {}", youtube_channels, quota, budget);
+    } else if current.quota_budget.is_none() && quota > youtube::DAILY_QUOTA {
+        warn!("Polling {} YouTube channels needs about {} quota units a day, over the default {}", youtube_channels, quota, youtube::DAILY_QUOTA);
+    } else if !current.channels.is_empty() {
+        info!("Polling {} channels, each at its own interval, about {} quota units a day", current.channels.len(), quota);
+    }
+
+    let poller = Arc::new(Poller { shared: shared.clone(), context });
+
+    if current.backfill_completed_today {
+        let summary = monitor::catch_panic(monitor::backfill(current.clone(), poller.context.clone())).await;
+        if let Some(summary) = summary {
+            info!("Backfill done, {} VODs started, {} errors", summary.started, summary.errors);
+        }
+    }
+    if current.once {
+        poller.poll_all().await;
+        recordings_finished(&active).await;
+        post_jobs.finished().await;
+        let _ = std::fs::remove_file(ytdlp::browser_cookie_jar());
+        return Ok(());
+    }
+    if current.run_now {
+        poller.poll_all().await;
+    }
+
+    let mut polling = match Polling::new(current.scheduler, poller, current.run_now).await {
+        Ok(polling) => polling,
+        Err(err) => {
+            error!("{}", err);
+            std::process::exit(SCHEDULER_EXIT);
+        }
+    };
+    if let Err(err) = polling.sync(&current).await {
+        error!("{}", err);
+        std::process::exit(SCHEDULER_EXIT);
+    }
+    let polling = Arc::new(Mutex::new(polling));
+    #[cfg(unix)]
+    if let Some(hangups) = hangups {
+        tokio::spawn(reload_on_sighup(hangups, shared, matches, polling.clone()));
+    }
+
+    // run until asked to stop, then let yt-dlp finalize what it recorded
+    shutdown_signal().await;
+    polling.lock().await.stop().await;
+    info!("Exiting, giving {} recording(s) up to {}s to finalize...", active.shutdown_all(), grace);
+    tokio::select! {
+        finished = tokio::time::timeout(Duration::from_secs(grace) + SHUTDOWN_MARGIN, recordings_finished(&active)) => {
+            if finished.is_err() {
+                let left: Vec<String> = active.list().into_iter().map(|info| info.video_id).collect();
+                warn!("Exiting with {} recording(s) still running: {}", left.len(), left.join(", "));
+            }
+        },
+        _ = tokio::signal::ctrl_c() => warn!("Interrupted again, exiting without waiting for recordings")
+    }
+    // post-processing gets the grace period too, then is left unfinished
+    if !post_jobs.pending().is_empty() && tokio::time::timeout(Duration::from_secs(grace), post_jobs.finished()).await.is_err() {
+        warn!("Abandoning the post-processing of {}", post_jobs.abort_all().join(", "));
+    }
+    let _ = std::fs::remove_file(ytdlp::browser_cookie_jar());
     Ok(())
 }
@@ -0,0 +1,175 @@
+use std::{
+    fs,
+    io,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::Duration
+};
+
+use clap::ValueEnum;
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    metadata::RecordingMetadata,
+    ytdlp
+};
+
+/// The library index kept in the output directory with `--manifest-format json`.
+pub const JSON_MANIFEST: &str = "manifest.json";
+
+const THUMBNAIL_EXTENSIONS: &[&str] = &["jpg", "png", "webp"];
+
+/// Serializes updates of the JSON index between recordings finishing at once.
+static JSON_LOCK: Mutex<()> = Mutex::new(());
+
+/// How finished recordings are described for media servers.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestFormat {
+    /// A Kodi-style `.nfo` next to each recorded file, as read by Jellyfin and Plex agents
+    Nfo,
+    /// One `manifest.json` index for the whole output directory
+    Json
+}
+
+/// What a media server needs to list one finished recording.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    PartialEq
+)]
+pub struct ManifestEntry {
+    pub video_id: String,
+    pub title: String,
+    pub description: String,
+    pub channel_id: String,
+    pub channel_title: String,
+    /// When the recording started, RFC 3339
+    pub date: String,
+    pub thumbnail: Option<PathBuf>,
+    pub duration_secs: u64,
+    pub files: Vec<PathBuf>,
+    #[serde(default)]
+    pub subtitles: Vec<PathBuf>
+}
+
+impl ManifestEntry {
+    pub fn new(metadata: &RecordingMetadata, duration: Duration, files: Vec<PathBuf>, thumbnail: Option<PathBuf>) -> ManifestEntry {
+        ManifestEntry {
+            video_id: metadata.video_id.clone(),
+            title: metadata.title.clone(),
+            description: metadata.description.clone(),
+            channel_id: metadata.channel_id.clone(),
+            channel_title: metadata.channel_title.clone(),
+            date: metadata.started_at.clone(),
+            thumbnail,
+            duration_secs: duration.as_secs(),
+            files,
+            subtitles: metadata.subtitles.clone()
+        }
+    }
+
+    fn nfo(&self) -> String {
+        let mut nfo = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<episodedetails>\n".to_owned();
+        let mut tag = |name: &str, value: &str| nfo.push_str(&format!("  <{0}>{1}</{0}>\n", name, escape_xml(value)));
+        tag("title", &self.title);
+        tag("plot", &self.description);
+        tag("showtitle", &self.channel_title);
+        tag("studio", &self.channel_title);
+        tag("aired", self.date.get(..10).unwrap_or(&self.date));
+        tag("runtime", &(self.duration_secs / 60).to_string());
+        if let Some(thumbnail) = &self.thumbnail {
+            tag("thumb", &thumbnail.to_string_lossy());
+        }
+        nfo.push_str(&format!("  <uniqueid type=\"youtube\" default=\"true\">{}</uniqueid>\n", escape_xml(&self.video_id)));
+        nfo.push_str("</episodedetails>\n");
+        nfo
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Replaces `path` with `contents` in one step, so readers never see a
+/// half-written file.
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, contents)?;
+    fs::rename(&temp, path)
+}
+
+/// The thumbnail yt-dlp wrote for `video_id`, if any.
+pub fn find_thumbnail(dir: &Path, video_id: &str) -> Option<PathBuf> {
+    let needle = format!("[{}]", video_id);
+    fs::read_dir(dir).ok()?.filter_map(|entry| entry.ok()).map(|entry| entry.path()).find(|path| {
+        path.file_name().is_some_and(|name| name.to_string_lossy().contains(&needle))
+            && path.extension().is_some_and(|ext| THUMBNAIL_EXTENSIONS.iter().any(|known| ext == *known))
+    })
+}
+
+/// Describes `entry` in `dir` in `format`: a `.nfo` per recorded file, or
+/// its entry in the JSON index, replacing any earlier one for the video.
+pub fn update(format: ManifestFormat, dir: &Path, entry: &ManifestEntry) -> io::Result<()> {
+    match format {
+        ManifestFormat::Nfo => {
+            for file in &entry.files {
+                write_atomically(&file.with_extension("nfo"), entry.nfo().as_bytes())?;
+            }
+            Ok(())
+        },
+        ManifestFormat::Json => {
+            let _guard = JSON_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+            let path = dir.join(JSON_MANIFEST);
+            let mut entries: Vec<ManifestEntry> = match fs::read(&path) {
+                Ok(json) => serde_json::from_slice(&json)?,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+                Err(err) => return Err(err)
+            };
+            entries.retain(|existing| existing.video_id != entry.video_id);
+            entries.push(entry.clone());
+            write_atomically(&path, &serde_json::to_vec_pretty(&entries)?)
+        }
+    }
+}
+
+/// Builds the entry for a finished recording of `video_id` from its sidecar.
+pub fn entry_for(dir: &Path, video_id: &String, duration: Duration) -> io::Result<ManifestEntry> {
+    let mut metadata = RecordingMetadata::read(dir, video_id)?;
+    metadata.subtitles = ytdlp::subtitle_files(dir, video_id);
+    let files = ytdlp::existing_recordings(dir, video_id);
+    Ok(ManifestEntry::new(&metadata, duration, files, find_thumbnail(dir, video_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nfo_per_file_and_json_index_without_duplicates() {
+        let dir = std::env::temp_dir().join(format!("ytlr-manifest-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Q&A <live> [abc].mp4"), b"video").unwrap();
+        fs::write(dir.join("Q&A <live> [abc].webp"), b"image").unwrap();
+        fs::write(dir.join("Q&A <live> [abc].en.vtt"), b"WEBVTT").unwrap();
+        fs::write(dir.join("abc.meta.json"), br#"{"video_id":"abc","kind":"live","title":"Q&A <live>","description":"Ask away","channel_id":"UC1","channel_title":"Channel","started_at":"2024-03-01T20:00:00+00:00","concurrent_viewers":null,"viewer_samples":[]}"#).unwrap();
+
+        let entry = entry_for(&dir, &"abc".to_owned(), Duration::from_secs(3725)).unwrap();
+        assert_eq!(entry.thumbnail, Some(dir.join("Q&A <live> [abc].webp")));
+        assert_eq!(entry.subtitles, vec![dir.join("Q&A <live> [abc].en.vtt")]);
+        update(ManifestFormat::Nfo, &dir, &entry).unwrap();
+        let nfo = fs::read_to_string(dir.join("Q&A <live> [abc].nfo")).unwrap();
+        assert!(nfo.contains("<title>Q&amp;A &lt;live&gt;</title>"));
+        assert!(nfo.contains("<aired>2024-03-01</aired>\n  <runtime>62</runtime>"));
+        assert!(ytdlp::existing_recordings(&dir, &"abc".to_owned()).iter().all(|path| path.extension().unwrap() == "mp4"));
+
+        update(ManifestFormat::Json, &dir, &entry).unwrap();
+        update(ManifestFormat::Json, &dir, &entry).unwrap();
+        let index: Vec<ManifestEntry> = serde_json::from_slice(&fs::read(dir.join(JSON_MANIFEST)).unwrap()).unwrap();
+        assert_eq!(index, vec![entry]);
+    }
+}
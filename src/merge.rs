@@ -0,0 +1,125 @@
+use std::{
+    fs,
+    io,
+    path::{Path, PathBuf},
+    process::Stdio
+};
+
+use log::{info, warn};
+use tokio::process::Command;
+
+use crate::ytdlp;
+
+/// Infix of the single file produced from a recording's segments.
+const MERGED: &str = ".merged";
+
+pub fn ffmpeg_program() -> String {
+    "ffmpeg".to_owned()
+}
+
+pub fn ffprobe_program() -> String {
+    "ffprobe".to_owned()
+}
+
+/// Kinds of the streams in `path` as ffprobe reports them, such as `video`
+/// and `audio`. Blocks until ffprobe is done.
+pub fn stream_types(ffprobe: &str, path: &Path) -> io::Result<Vec<String>> {
+    let output = std::process::Command::new(ffprobe)
+        .args(["-v", "error", "-show_entries", "stream=codec_type", "-of", "csv=p=0"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("{} exited with {} for {}", ffprobe, output.status, path.display())));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().to_owned())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+/// The finished segment files of `video_id` in recording order, or nothing
+/// when there is only one or they've been merged already.
+pub fn segments(dir: &Path, video_id: &String) -> Vec<PathBuf> {
+    let mut segments = ytdlp::existing_recordings(dir, video_id);
+    if segments.iter().any(|path| path.to_string_lossy().contains(MERGED)) {
+        return Vec::new();
+    }
+    segments.sort_by_key(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok());
+    if segments.len() < 2 {
+        return Vec::new();
+    }
+    segments
+}
+
+/// `segments` as an ffmpeg concat demuxer script.
+fn concat_list(segments: &[PathBuf]) -> String {
+    segments
+        .iter()
+        .map(|path| format!("file '{}'\n", path.to_string_lossy().replace('\'', "'\\''")))
+        .collect()
+}
+
+/// Where the merged recording goes: the first segment's name with
+/// `.merged` before the extension.
+fn merged_path(first: &Path) -> PathBuf {
+    let stem = first.file_stem().unwrap_or_default().to_string_lossy();
+    match first.extension() {
+        Some(ext) => first.with_file_name(format!("{}{}.{}", stem, MERGED, ext.to_string_lossy())),
+        None => first.with_file_name(format!("{}{}", stem, MERGED))
+    }
+}
+
+/// Concatenates `segments` losslessly into one file next to them, deleting
+/// the segments afterwards with `delete_segments`. Returns the merged file.
+pub async fn merge(ffmpeg: &str, video_id: &String, segments: &[PathBuf], delete_segments: bool) -> io::Result<PathBuf> {
+    let first = &segments[0];
+    if segments.iter().any(|path| path.extension() != first.extension()) {
+        return Err(io::Error::other(format!("segments of {} have different formats", video_id)));
+    }
+    let output = merged_path(first);
+    let list = first.with_file_name(format!("{}.concat.txt", video_id));
+    fs::write(&list, concat_list(segments))?;
+
+    let status = Command::new(ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-f", "concat", "-safe", "0", "-i"])
+        .arg(&list)
+        .args(["-c", "copy"])
+        .arg(&output)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .status()
+        .await;
+    let _ = fs::remove_file(&list);
+    let status = status?;
+    if !status.success() {
+        let _ = fs::remove_file(&output);
+        return Err(io::Error::other(format!("{} exited with {}", ffmpeg, status)));
+    }
+
+    info!("Merged {} segments of {} into {}", segments.len(), video_id, output.display());
+    if delete_segments {
+        for segment in segments {
+            if let Err(err) = fs::remove_file(segment) {
+                warn!("Failed to delete segment {}: {}", segment.display(), err);
+            }
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concat_list_quotes_and_merged_name() {
+        let segments = vec![PathBuf::from("/rec/It's live [abc].mp4"), PathBuf::from("/rec/It's live [abc].1.mp4")];
+        assert_eq!(
+            concat_list(&segments),
+            "file '/rec/It'\\''s live [abc].mp4'\nfile '/rec/It'\\''s live [abc].1.mp4'\n"
+        );
+        assert_eq!(merged_path(&segments[0]), PathBuf::from("/rec/It's live [abc].merged.mp4"));
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration
+};
+
+use chrono::Utc;
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    registry::RecordingKind,
+    stream::LiveStream,
+    youtube,
+    ytdlp
+};
+
+/// Operator-defined `key=value` labels attached to recordings.
+pub type Tags = BTreeMap<String, String>;
+
+/// Parses a `--tag` value.
+pub fn parse_tag(tag: &str) -> Result<(String, String), String> {
+    match tag.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_owned(), value.to_owned())),
+        _ => Err(format!("expected key=value, got {}", tag))
+    }
+}
+
+/// `tags` as the `RECORDER_TAGS` value: `key=value` pairs joined by commas.
+pub fn tags_env(tags: &Tags) -> String {
+    tags.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join(",")
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone
+)]
+pub struct ViewerSample {
+    pub at: String,
+    pub concurrent_viewers: String
+}
+
+/// JSON sidecar written next to each recording.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone
+)]
+pub struct RecordingMetadata {
+    pub video_id: String,
+    pub kind: RecordingKind,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    pub channel_id: String,
+    pub channel_title: String,
+    pub started_at: String,
+    pub concurrent_viewers: Option<String>,
+    pub viewer_samples: Vec<ViewerSample>,
+    #[serde(default)]
+    pub tags: Tags,
+    /// `360` for 360°/VR streams, when the API reports it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub projection: Option<String>,
+    /// `3d` for stereoscopic streams, when the API reports it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimension: Option<String>,
+    /// Whether yt-dlp was asked to prefer HDR formats
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub prefer_hdr: bool,
+    /// Caption files yt-dlp wrote next to the recording
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subtitles: Vec<PathBuf>
+}
+
+impl RecordingMetadata {
+    pub fn new(stream: &LiveStream, kind: RecordingKind) -> RecordingMetadata {
+        RecordingMetadata {
+            video_id: stream.video_id.clone(),
+            kind,
+            title: stream.title.clone(),
+            description: stream.description.clone(),
+            channel_id: stream.channel_id.clone(),
+            channel_title: stream.channel_title.clone(),
+            started_at: Utc::now().to_rfc3339(),
+            concurrent_viewers: None,
+            viewer_samples: Vec::new(),
+            tags: Tags::new(),
+            projection: None,
+            dimension: None,
+            prefer_hdr: false,
+            subtitles: Vec::new()
+        }
+    }
+
+    pub fn sidecar_path(&self, dir: &Path) -> PathBuf {
+        dir.join(format!("{}.meta.json", self.video_id))
+    }
+
+    pub fn write(&self, dir: &Path) -> io::Result<()> {
+        fs::write(self.sidecar_path(dir), serde_json::to_vec_pretty(self)?)
+    }
+
+    /// The sidecar of `video_id` in `dir`.
+    pub fn read(dir: &Path, video_id: &str) -> io::Result<RecordingMetadata> {
+        Ok(serde_json::from_slice(&fs::read(dir.join(format!("{}.meta.json", video_id)))?)?)
+    }
+}
+
+/// Lists the caption files of `video_id` in its sidecar in `dir`.
+pub fn add_subtitles(dir: &Path, video_id: &str) -> io::Result<()> {
+    let mut metadata = RecordingMetadata::read(dir, video_id)?;
+    metadata.subtitles = ytdlp::subtitle_files(dir, video_id);
+    metadata.write(dir)
+}
+
+/// Polls the concurrent viewer count every `every` and appends it to the
+/// sidecar. Runs until aborted by the caller.
+pub async fn sample_viewers(
+    api_key: String,
+    dir: PathBuf,
+    metadata: Arc<Mutex<RecordingMetadata>>,
+    every: Duration
+) {
+    let video_id = metadata.lock().unwrap_or_else(PoisonError::into_inner).video_id.clone();
+    let mut interval = tokio::time::interval(every);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        let viewers = match youtube::fetch_concurrent_viewers(&api_key, &video_id).await {
+            Some(viewers) => viewers,
+            None => continue
+        };
+        info!("{} concurrent viewers on {}", viewers, video_id);
+
+        let mut metadata = metadata.lock().unwrap_or_else(PoisonError::into_inner);
+        metadata.viewer_samples.push(ViewerSample {
+            at: Utc::now().to_rfc3339(),
+            concurrent_viewers: viewers
+        });
+        if let Err(err) = metadata.write(&dir) {
+            warn!("Failed to update metadata for {}: {}", video_id, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_parse_and_join() {
+        let tags: Tags = ["event=concert", "priority=high"].iter().map(|tag| parse_tag(tag).unwrap()).collect();
+        assert_eq!(tags_env(&tags), "event=concert,priority=high");
+        assert!(parse_tag("=x").is_err());
+        assert!(parse_tag("novalue").is_err());
+    }
+}
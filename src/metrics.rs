@@ -0,0 +1,148 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        Arc,
+        Mutex,
+        PoisonError,
+        atomic::{AtomicU64, Ordering}
+    }
+};
+
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    events::{Event, EventBus},
+    status::StatusSource,
+    youtube,
+    ytdlp
+};
+
+/// Counts from the event bus that the API counters in [`youtube`] don't
+/// cover.
+#[derive(Default)]
+pub struct Metrics {
+    live_detected: AtomicU64,
+    /// yt-dlp exits by exit code, `none` when it was killed by a signal
+    exit_codes: Mutex<BTreeMap<String, u64>>
+}
+
+impl Metrics {
+    pub fn observe(&self, event: &Event) {
+        match event {
+            Event::LiveDetected { .. } => {
+                self.live_detected.fetch_add(1, Ordering::Relaxed);
+            },
+            Event::RecordingStopped { exit_code, .. } => {
+                let code = exit_code.map_or_else(|| "none".to_owned(), |code| code.to_string());
+                *self.exit_codes.lock().unwrap_or_else(PoisonError::into_inner).entry(code).or_default() += 1;
+            },
+            _ => {}
+        }
+    }
+
+    /// Counts every event published on `bus` until the process exits.
+    pub async fn follow(self: Arc<Self>, bus: EventBus) {
+        let mut receiver = bus.subscribe();
+        loop {
+            match receiver.recv().await {
+                Ok(event) => self.observe(&event.event),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return
+            }
+        }
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Everything in the Prometheus text format, as served by `GET /metrics`.
+pub fn render(source: &StatusSource) -> String {
+    let metrics = &source.metrics;
+    let api = youtube::api_counters();
+    let mut out = String::new();
+    for (name, help, value) in [
+        ("recorder_api_calls_total", "YouTube Data API calls made.", api.calls),
+        ("recorder_api_errors_total", "YouTube Data API calls that failed.", api.errors),
+        ("recorder_api_quota_exceeded_total", "YouTube Data API calls turned down for lack of quota.", api.quota_exceeded),
+        ("recorder_api_quota_units_total", "Estimated quota units spent.", youtube::quota_used()),
+        ("recorder_live_streams_detected_total", "Live streams found that weren't being recorded yet.", metrics.live_detected.load(Ordering::Relaxed))
+    ] {
+        family(&mut out, name, "counter", help);
+        let _ = writeln!(out, "{} {}", name, value);
+    }
+
+    let recordings = source.recordings.list();
+    family(&mut out, "recorder_recordings_in_progress", "gauge", "Recordings running now.");
+    let _ = writeln!(out, "recorder_recordings_in_progress {}", recordings.len());
+
+    family(&mut out, "recorder_recordings_queued", "gauge", "Live streams waiting for a free recording slot.");
+    let _ = writeln!(out, "recorder_recordings_queued {}", source.queue.len());
+
+    family(&mut out, "recorder_recording_bytes", "gauge", "Bytes written so far by each running recording.");
+    let dirs = source.shared.current().output_dirs();
+    for recording in &recordings {
+        let bytes: u64 = dirs.iter().map(|dir| ytdlp::recorded_bytes(dir, &recording.video_id)).sum();
+        let _ = writeln!(out, "recorder_recording_bytes{{video_id=\"{}\"}} {}", escape_label(&recording.video_id), bytes);
+    }
+
+    family(&mut out, "recorder_ytdlp_exits_total", "counter", "yt-dlp runs that ended, by exit code.");
+    for (code, count) in metrics.exit_codes.lock().unwrap_or_else(PoisonError::into_inner).iter() {
+        let _ = writeln!(out, "recorder_ytdlp_exits_total{{code=\"{}\"}} {}", code, count);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cli::Args,
+        config::{Config, SharedConfig},
+        queue::Queue,
+        registry::{ActiveRecordings, RecordingHandle, RecordingInfo, RecordingKind},
+        report::Stats
+    };
+    use clap::Parser;
+
+    #[test]
+    fn exposition_counts_events_and_labels_recordings() {
+        let metrics = Arc::new(Metrics::default());
+        metrics.observe(&Event::LiveDetected { video_id: "a".to_owned(), channel_title: String::new(), title: String::new() });
+        for exit_code in [Some(0), Some(1), Some(0), None] {
+            metrics.observe(&Event::RecordingStopped { video_id: "a".to_owned(), exit_code, files: Vec::new(), error: None });
+        }
+
+        let args = Args::parse_from(["recorder", "--api-key", "key", "-c", "@someone", "--output-dir", "/nonexistent"]);
+        let source = StatusSource {
+            shared: Arc::new(SharedConfig::new(Config::from_args(args))),
+            recordings: Arc::new(ActiveRecordings::default()),
+            stats: Arc::new(Stats::default()),
+            metrics,
+            queue: Arc::new(Queue::default())
+        };
+        source.recordings.insert(RecordingHandle::new(RecordingInfo {
+            video_id: "we\"ird".to_owned(),
+            kind: RecordingKind::Live,
+            channel_title: String::new(),
+            title: String::new(),
+            started_at: String::new(),
+            progress: None
+        }, 0));
+
+        let text = render(&source);
+        assert!(text.contains("# TYPE recorder_api_calls_total counter\n"));
+        assert!(text.contains("\nrecorder_live_streams_detected_total 1\n"));
+        assert!(text.contains("\nrecorder_recordings_in_progress 1\n"));
+        assert!(text.contains("\nrecorder_recording_bytes{video_id=\"we\\\"ird\"} 0\n"));
+        assert!(text.contains("\nrecorder_ytdlp_exits_total{code=\"0\"} 2\n"));
+        assert!(text.contains("\nrecorder_ytdlp_exits_total{code=\"none\"} 1\n"));
+    }
+}
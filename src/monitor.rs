@@ -0,0 +1,1180 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    future::Future,
+    io,
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError, atomic::{AtomicBool, Ordering}},
+    time::{Duration, Instant, SystemTime}
+};
+
+use chrono::Utc;
+use futures::{
+    FutureExt,
+    future,
+    stream::{self, StreamExt}
+};
+use log::{debug, error, info, warn};
+use sysinfo::{ProcessExt, System, SystemExt};
+use tokio::task::JoinHandle;
+
+use crate::{
+    config::Config,
+    error::{RecorderError, Result},
+    events::{Event, EventBus},
+    filters::DuplicatePolicy,
+    history::{History, HistoryEntry, Status},
+    hooks,
+    manifest::{self, ManifestFormat},
+    merge,
+    metadata::{self, RecordingMetadata},
+    postprocess,
+    provider::{self, Platform, Provider, YouTubeProvider},
+    queue::Queue,
+    quota::{Pace, QuotaBudget},
+    registry::{ActiveRecordings, Admission, RecordingHandle, RecordingInfo, RecordingKind, Retired},
+    report::{self, PollResult, Stats},
+    resolver::ChannelResolver,
+    retention,
+    stream::{LiveStream, StreamDetails},
+    upcoming::{self, Scheduled, Upcoming},
+    youtube::{self, EventType},
+    ytdlp::{self, ProcessSpawner, ProgressSink, RecordOptions}
+};
+
+pub const VOD_ARCHIVE: &str = "vod-archive.txt";
+
+/// What a poll found for one channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelOutcome {
+    NotLive,
+    Started,
+    AlreadyRecording,
+    Skipped,
+    /// Waiting for a free recording slot
+    Queued
+}
+
+/// Aggregate of one tick across all channels, logged as a single line.
+#[derive(Debug, Default)]
+pub struct TickSummary {
+    pub checked: usize,
+    pub live: usize,
+    pub started: usize,
+    pub already_recording: usize,
+    pub skipped: usize,
+    pub queued: usize,
+    pub errors: usize,
+    pub elapsed: Duration
+}
+
+impl TickSummary {
+    pub fn add(&mut self, outcome: &Result<ChannelOutcome>) {
+        self.checked += 1;
+        match outcome {
+            Ok(ChannelOutcome::NotLive) => {},
+            Ok(ChannelOutcome::Started) => {
+                self.live += 1;
+                self.started += 1;
+            },
+            Ok(ChannelOutcome::AlreadyRecording) => {
+                self.live += 1;
+                self.already_recording += 1;
+            },
+            Ok(ChannelOutcome::Skipped) => {
+                self.live += 1;
+                self.skipped += 1;
+            },
+            Ok(ChannelOutcome::Queued) => {
+                self.live += 1;
+                self.queued += 1;
+            },
+            Err(_) => self.errors += 1
+        }
+    }
+
+    fn result(&self) -> PollResult {
+        PollResult {
+            at: Utc::now().to_rfc3339(),
+            checked: self.checked,
+            live: self.live,
+            started: self.started,
+            already_recording: self.already_recording,
+            skipped: self.skipped,
+            queued: self.queued,
+            errors: self.errors,
+            elapsed_ms: self.elapsed.as_millis()
+        }
+    }
+
+    fn event(&self) -> Event {
+        Event::Tick {
+            checked: self.checked,
+            live: self.live,
+            started: self.started,
+            already_recording: self.already_recording,
+            skipped: self.skipped,
+            queued: self.queued,
+            errors: self.errors,
+            elapsed_ms: self.elapsed.as_millis()
+        }
+    }
+}
+
+impl fmt::Display for TickSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tick: checked {} channels, {} live, {} started, {} already recording, {} skipped, {} queued, {} errors ({}ms)",
+            self.checked,
+            self.live,
+            self.started,
+            self.already_recording,
+            self.skipped,
+            self.queued,
+            self.errors,
+            self.elapsed.as_millis()
+        )
+    }
+}
+
+/// When each channel was last looked up, so its job skips ticks while
+/// polls are slowed for `--quota-budget`, and a poll right after the one
+/// at startup.
+#[derive(Default)]
+pub struct LastPolled(Mutex<HashMap<String, Instant>>);
+
+impl LastPolled {
+    /// Whether `channel`, polled every `every`, is due on a tick of `tick`,
+    /// marking it polled if so. Half a tick of slack keeps jitter from
+    /// pushing a poll back by a whole tick.
+    pub fn due(&self, channel: &str, every: Duration, tick: Duration) -> bool {
+        let mut last = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let due = last.get(channel).is_none_or(|polled| polled.elapsed() + tick / 2 >= every);
+        if due {
+            last.insert(channel.to_owned(), Instant::now());
+        }
+        due
+    }
+}
+
+/// Long-lived state shared by every tick and the recordings it starts.
+#[derive(Clone, Default)]
+pub struct Context {
+    pub recordings: Arc<ActiveRecordings>,
+    pub stats: Arc<Stats>,
+    pub resolver: Arc<ChannelResolver>,
+    pub events: EventBus,
+    pub last_polled: Arc<LastPolled>,
+    /// With `--history-file`
+    pub history: Option<Arc<History>>,
+    pub upcoming: Arc<Upcoming>,
+    /// Streams waiting for a slot with `--max-recordings`
+    pub queue: Arc<Queue>,
+    pub quota: Arc<QuotaBudget>,
+    /// Merging and post-processing of recordings that finished
+    pub post_jobs: Arc<postprocess::Jobs>
+}
+
+impl Context {
+    /// Notes an error for the report and the event stream.
+    pub fn error(&self, message: String) {
+        self.events.publish(Event::Error { message: message.clone() });
+        self.stats.error(message);
+    }
+}
+
+/// Runs one tick, turning a panic inside it into a logged error so the
+/// scheduler keeps firing later ticks. Shared state is locked ignoring
+/// poisoning, so a tick that panics holding a lock doesn't break the rest.
+pub async fn catch_panic<F: Future>(tick: F) -> Option<F::Output> {
+    match AssertUnwindSafe(tick).catch_unwind().await {
+        Ok(output) => Some(output),
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_owned());
+            error!("Tick panicked, continuing with the next one: {}", message);
+            None
+        }
+    }
+}
+
+/// Polls those of `channels` that are still monitored and due once, and
+/// starts any new recordings. Each channel's job polls it alone; at startup
+/// all are polled together. The lookups, through each channel's provider,
+/// run concurrently; recording decisions are then made in channel order.
+/// Lookups still running after `timeout` are cancelled and count as
+/// errors, so a slow network can't make ticks overlap.
+pub async fn run_tick(config: Arc<Config>, context: Context, channels: &[String], timeout: Duration) -> TickSummary {
+    tick_with(config, context, channels, timeout, |channel| Platform::of(channel).provider()).await
+}
+
+/// [`run_tick`], looking channels up with the provider `provider_for`
+/// picks for them.
+async fn tick_with(
+    config: Arc<Config>,
+    context: Context,
+    channels: &[String],
+    timeout: Duration,
+    provider_for: fn(&str) -> &'static dyn Provider
+) -> TickSummary {
+    let started = Instant::now();
+    let mut summary = TickSummary::default();
+
+    let now = Utc::now();
+    let uses_api = config.detection_method.uses_api();
+    let (pace, stopped) = match config.quota_budget {
+        Some(budget) if uses_api => context.quota.pace(budget, config.daily_quota_need(), youtube::quota_used(), now),
+        _ => (Pace::Normal, false)
+    };
+    if stopped {
+        context.events.publish(Event::QuotaExceeded { message: format!("--quota-budget of {} units spent", config.quota_budget.unwrap_or_default()) });
+    }
+    let channels: Vec<String> = channels
+        .iter()
+        .filter(|channel| config.channels.contains(channel))
+        .filter(|channel| {
+            let on_api = uses_api && Platform::of(channel) == Platform::YouTube;
+            if on_api && (pace == Pace::Spent || context.quota.exhausted(config.api_key_for(channel), now)) {
+                return false;
+            }
+            let tick = config.channel_interval(channel);
+            let every = match (on_api, pace) {
+                (true, Pace::Slowed(factor)) => tick.mul_f64(factor),
+                _ => tick
+            };
+            context.last_polled.due(channel, every, tick)
+        })
+        .cloned()
+        .collect();
+    let mut pending = stream::iter(channels.clone().into_iter().enumerate())
+        .map(|(index, channel)| {
+            let config = config.for_channel(&channel);
+            let resolver = context.resolver.clone();
+            async move { (index, provider_for(&channel).lookup(&config, &resolver, &channel).await) }
+        })
+        .buffer_unordered(config.lookup_concurrency);
+    let deadline = tokio::time::Instant::from_std(started + timeout);
+    let mut lookups = Vec::new();
+    while let Ok(Some(lookup)) = tokio::time::timeout_at(deadline, pending.next()).await {
+        lookups.push(lookup);
+    }
+    drop(pending);
+
+    if lookups.len() < channels.len() {
+        warn!("Tick ran out of time after {:?}, cancelled {} lookups", timeout, channels.len() - lookups.len());
+        let done: Vec<usize> = lookups.iter().map(|(index, _)| *index).collect();
+        for index in (0..channels.len()).filter(|index| !done.contains(index)) {
+            let timed_out = io::Error::new(io::ErrorKind::TimedOut, format!("lookup cancelled after {:?}", timeout));
+            lookups.push((index, Err(RecorderError::Io(timed_out))));
+        }
+    }
+    lookups.sort_by_key(|(index, _)| *index);
+
+    for (index, lookup) in lookups {
+        let channel = &channels[index];
+        let live: Option<Vec<&str>> = match &lookup {
+            Ok(Some((items, RecordingKind::Live))) => Some(items.iter().map(|item| item.video_id.as_str()).collect()),
+            Ok(_) => Some(Vec::new()),
+            Err(_) => None
+        };
+        for ended in live.map(|live| context.queue.retain_live(channel, &live)).unwrap_or_default() {
+            info!("{} \"{}\" is no longer live, dropped from the queue", ended.item.video_id, ended.item.title);
+        }
+        let outcome = match lookup {
+            Ok(Some((items, kind))) => {
+                let config = config.for_channel(channel);
+                let mut outcomes = Vec::new();
+                for item in pick_duplicates(&config, items).await {
+                    outcomes.push(decide(config.clone(), context.clone(), item, kind, channel).await);
+                }
+                let (outcome, failed) = pick_outcome(outcomes);
+                for err in failed {
+                    warn!("{}: recording a duplicate failed: {}", channel, err);
+                    context.error(format!("{}: recording a duplicate failed: {}", channel, err));
+                    summary.errors += 1;
+                }
+                outcome
+            },
+            Ok(None) => watch_upcoming(config.for_channel(channel), &context, channel).await,
+            Err(err) => Err(err)
+        };
+        match &outcome {
+            Ok(outcome) => debug!("{}: {:?}", channel, outcome),
+            Err(err) => {
+                warn!("{}: poll failed: {}", channel, err);
+                context.error(format!("{}: poll failed: {}", channel, err));
+                if youtube::is_quota_exceeded(err) {
+                    if let Some(until) = context.quota.exhaust(config.api_key_for(channel), Utc::now()) {
+                        warn!("{}: API key out of quota, no polls with it until {}", channel, until);
+                        context.events.publish(Event::QuotaExceeded { message: format!("{}: {}", channel, err) });
+                    }
+                }
+            }
+        }
+        summary.add(&outcome);
+    }
+
+    summary.elapsed = started.elapsed();
+    context.events.publish(summary.event());
+    context.stats.poll_finished(summary.result());
+    summary
+}
+
+/// The outcome a channel counts as out of those of the duplicates found
+/// on it, the one that started if any, and the errors of the others.
+fn pick_outcome(mut outcomes: Vec<Result<ChannelOutcome>>) -> (Result<ChannelOutcome>, Vec<RecorderError>) {
+    let started = outcomes.iter().position(|outcome| matches!(outcome, Ok(ChannelOutcome::Started))).unwrap_or(0);
+    let outcome = outcomes.remove(started);
+    (outcome, outcomes.into_iter().filter_map(Result::err).collect())
+}
+
+/// Starts the `--url` targets that went live and frees disk space, on a
+/// job of its own at the shortest poll interval.
+pub async fn upkeep(config: Arc<Config>, context: Context) -> TickSummary {
+    let started = Instant::now();
+    let mut summary = TickSummary::default();
+    start_direct(&config, &context, &mut summary).await;
+
+    for dir in config.output_dirs() {
+        free_space(&config, &context, &dir);
+    }
+
+    summary.elapsed = started.elapsed();
+    if summary.checked > 0 {
+        context.events.publish(summary.event());
+        context.stats.poll_finished(summary.result());
+    }
+    summary
+}
+
+/// The line logged by [`heartbeat`].
+fn heartbeat_line(since_last_poll: Option<Duration>, active: usize) -> String {
+    let last_poll = match since_last_poll {
+        Some(since) => format!("last poll {}s ago", since.as_secs()),
+        None => "no poll yet".to_owned()
+    };
+    format!("heartbeat: alive, {}, {} active recordings", last_poll, active)
+}
+
+/// Logs that the process is alive every `every`, whether or not ticks run,
+/// so a wedged scheduler shows up in the logs as a growing last poll age.
+pub async fn heartbeat(context: Context, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        info!("{}", heartbeat_line(context.stats.since_last_poll(), context.recordings.list().len()));
+    }
+}
+
+/// Deletes old recordings in `dir` past `--max-total-gb` and
+/// `--max-age-days`, or the limits of the channels recording there.
+fn free_space(config: &Config, context: &Context, dir: &Path) {
+    let retention = config.retention(dir);
+    if retention.is_empty() {
+        return;
+    }
+    let recorded = context.history.as_ref().map_or_else(HashSet::new, |history| retention::recorded_in(dir, history.entries()));
+    match retention.enforce(dir, &context.recordings, &recorded) {
+        Ok(files) if !files.is_empty() => context.events.publish(Event::RecordingsDeleted { dir: dir.to_owned(), files }),
+        Ok(_) => {},
+        Err(err) => context.error(format!("disk limit cleanup in {} failed: {}", dir.display(), err))
+    }
+}
+
+/// Whether the disk holding `dir` has `--min-free-gb` free, or its free
+/// space can't be told.
+fn enough_space(config: &Config, context: &Context, dir: &Path) -> bool {
+    let Some(min) = config.min_free_bytes() else {
+        return true;
+    };
+    match retention::available_space(dir) {
+        Some(available) if available < min => {
+            context.error(format!(
+                "only {:.1} GB free in {}, below --min-free-gb, not starting a recording",
+                available as f64 / 1e9,
+                dir.display()
+            ));
+            false
+        },
+        _ => true
+    }
+}
+
+/// Applies `--on-duplicate-detection` to detections of the same content,
+/// keeping all of them, or the live ones or premieres if there's a mix.
+async fn pick_duplicates(config: &Config, items: Vec<LiveStream>) -> Vec<LiveStream> {
+    if items.len() < 2 {
+        return items;
+    }
+    let ids = video_ids(&items);
+    let want_premiere = match config.on_duplicate_detection {
+        DuplicatePolicy::RecordBoth => {
+            info!("{} look like the same content, recording all of them", ids);
+            return items;
+        },
+        DuplicatePolicy::PreferLive => false,
+        DuplicatePolicy::PreferPremiere => true
+    };
+    let wanted = if want_premiere { "premiere" } else { "live stream" };
+
+    let mut premieres = Vec::new();
+    for item in &items {
+        let details = provider::provider_of(item).details(config, &item.video_id).await;
+        premieres.push(details.is_some_and(|details| details.premiere));
+    }
+    if !premieres.contains(&want_premiere) {
+        info!("{} look like the same content and none is a {}, recording only {}", ids, wanted, items[0].video_id);
+        return items.into_iter().take(1).collect();
+    }
+    let kept: Vec<LiveStream> = items
+        .into_iter()
+        .zip(premieres)
+        .filter(|(_, premiere)| *premiere == want_premiere)
+        .map(|(item, _)| item)
+        .collect();
+    info!("{} look like the same content, recording only the {} {}", ids, wanted, video_ids(&kept));
+    kept
+}
+
+fn video_ids(items: &[LiveStream]) -> String {
+    items.iter().map(|item| item.video_id.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+/// Downloads the VODs of streams on every YouTube channel that ended
+/// earlier today in the configured timezone. Meant to run once, at startup.
+pub async fn backfill(config: Arc<Config>, context: Context) -> TickSummary {
+    let started = Instant::now();
+    let mut summary = TickSummary::default();
+    let today = config.timezone.date_stamp(Utc::now());
+    for channel in config.channels.iter().filter(|channel| Platform::of(channel) == Platform::YouTube) {
+        let config = config.for_channel(channel);
+        let found = match YouTubeProvider.streams(&config, &context.resolver, channel, EventType::Completed).await {
+            Ok(found) => found,
+            Err(err) => {
+                warn!("Backfill of {} failed: {}", channel, err);
+                context.error(format!("{}: backfill failed: {}", channel, err));
+                summary.add(&Err(err));
+                continue;
+            }
+        };
+        for item in found {
+            let ended = youtube::fetch_live_details(&config.api_key, &item.video_id)
+                .await
+                .and_then(|details| details.actual_end_time);
+            if ended.is_none_or(|ended| config.timezone.date_stamp(ended) != today) {
+                continue;
+            }
+            info!("Backfill: {} \"{}\" ended today, downloading its VOD", item.video_id, item.title);
+            let outcome = decide(config.clone(), context.clone(), item, RecordingKind::Vod, channel).await;
+            summary.add(&outcome);
+        }
+    }
+    summary.elapsed = started.elapsed();
+    summary
+}
+
+/// With `--upcoming-lead`, notes what YouTube `channel` has scheduled, now
+/// and then, and has yt-dlp wait for any stream about to start.
+async fn watch_upcoming(config: Arc<Config>, context: &Context, channel: &String) -> Result<ChannelOutcome> {
+    let Some(lead) = config.upcoming_lead() else {
+        return Ok(ChannelOutcome::NotLive);
+    };
+    if Platform::of(channel) != Platform::YouTube {
+        return Ok(ChannelOutcome::NotLive);
+    }
+    if context.upcoming.stale(channel) {
+        let mut streams = Vec::new();
+        for item in YouTubeProvider.streams(&config, &context.resolver, channel, EventType::Upcoming).await? {
+            let starts_at = youtube::fetch_live_details(&config.api_key, &item.video_id)
+                .await
+                .and_then(|details| details.scheduled_start_time);
+            match starts_at {
+                Some(starts_at) => streams.push(Scheduled { item, starts_at }),
+                None => debug!("{}: {} is upcoming without a planned start", channel, item.video_id)
+            }
+        }
+        for stream in context.upcoming.set(channel, streams) {
+            info!(
+                "{}: {} \"{}\" is scheduled for {}",
+                channel, stream.item.video_id, stream.item.title, stream.starts_at.to_rfc3339()
+            );
+        }
+    }
+
+    let outcome = start_due(config.clone(), context, channel).await;
+    // the next poll would come too late for the next stream
+    if let Some(wait) = context.upcoming.wake_in(channel, Utc::now(), lead, config.channel_interval(channel)) {
+        debug!("{}: next scheduled stream is due in {:?}, before the next poll", channel, wait);
+        let (context, channel) = (context.clone(), channel.clone());
+        tokio::spawn(async move {
+            tokio::time::sleep(wait).await;
+            if let Err(err) = start_due(config, &context, &channel).await {
+                warn!("{}: starting a scheduled stream failed: {}", channel, err);
+            }
+        });
+    }
+    outcome
+}
+
+/// Has yt-dlp wait for the streams `channel` has scheduled that are about
+/// to start.
+async fn start_due(config: Arc<Config>, context: &Context, channel: &str) -> Result<ChannelOutcome> {
+    let Some(lead) = config.upcoming_lead() else {
+        return Ok(ChannelOutcome::NotLive);
+    };
+    let mut outcome = ChannelOutcome::NotLive;
+    for stream in context.upcoming.take_due(channel, Utc::now(), lead) {
+        info!("{} \"{}\" is about to start, waiting for it", stream.item.video_id, stream.item.title);
+        outcome = decide(config.clone(), context.clone(), stream.item, RecordingKind::Live, channel).await?;
+    }
+    Ok(outcome)
+}
+
+/// Decides whether a found stream should be recorded, and starts it.
+async fn decide(
+    config: Arc<Config>,
+    context: Context,
+    item: LiveStream,
+    kind: RecordingKind,
+    channel: &str
+) -> Result<ChannelOutcome> {
+    if context.recordings.is_closed() {
+        return Ok(ChannelOutcome::Skipped);
+    }
+    let provider = provider::provider_of(&item);
+    let video_id = &item.video_id;
+    let link = &item.link;
+    let scheduled = item.upcoming;
+    let mut options = config.record_options();
+    if scheduled {
+        options.wait_for_video = Some(upcoming::WAIT_RETRY_SECS);
+        // nothing is written while it waits
+        options.stall_timeout = None;
+    }
+    if kind == RecordingKind::Vod {
+        let archive = options.output_dir.join(VOD_ARCHIVE);
+        let in_history = context.history.as_ref().is_some_and(|history| history.has_vod(video_id));
+        if in_history || ytdlp::in_download_archive(&archive, video_id) {
+            debug!("VOD {} already downloaded", video_id);
+            return Ok(ChannelOutcome::NotLive);
+        }
+        options.download_archive = Some(archive);
+    }
+
+    let dedup = config.dedup_strategy;
+    let is_running = (dedup.uses_registry() && context.recordings.contains(video_id))
+        || (dedup.uses_processes() && recorded_elsewhere(&options.program, link));
+
+    if is_running {
+        return Ok(ChannelOutcome::AlreadyRecording);
+    }
+    if kind == RecordingKind::Live && !scheduled {
+        context.events.publish(Event::LiveDetected {
+            video_id: video_id.clone(),
+            channel_title: item.channel_title.clone(),
+            title: item.title.clone()
+        });
+    }
+
+    let existing = ytdlp::existing_recordings(&options.output_dir, video_id);
+
+    if ytdlp::should_skip(config.on_existing, &existing) {
+        debug!("Skipping {}, already recorded as {:?}", video_id, existing);
+        return Ok(ChannelOutcome::Skipped);
+    }
+
+    let details = provider.details(&config, video_id).await;
+    if let Some(reason) = config.filters().check(&item, details.as_ref()) {
+        info!("Skipping {} \"{}\": {}", video_id, item.title, reason);
+        return Ok(ChannelOutcome::Skipped);
+    }
+    if !enough_space(&config, &context, &options.output_dir) {
+        return Ok(ChannelOutcome::Skipped);
+    }
+
+    if let Some(hook) = &config.pre_hook {
+        let mut env = vec![
+            ("VIDEO_ID".to_owned(), video_id.clone()),
+            ("CHANNEL_TITLE".to_owned(), item.channel_title.clone()),
+            ("OUTPUT_PATH".to_owned(), intended_output(&options, &item).to_string_lossy().into_owned())
+        ];
+        env.extend(options.env.iter().cloned());
+        match hooks::run(hook, &env, Duration::from_secs(config.pre_hook_timeout)).await {
+            Ok(status) if status.success() => {},
+            Ok(status) => {
+                info!("Skipping {} \"{}\": pre-hook exited with {}", video_id, item.title, status);
+                return Ok(ChannelOutcome::Skipped);
+            },
+            Err(err) => {
+                warn!("Skipping {} \"{}\": pre-hook failed: {}", video_id, item.title, err);
+                return Ok(ChannelOutcome::Skipped);
+            }
+        }
+    }
+
+    Ok(start_recording(config, context, channel, item, kind, options, details))
+}
+
+/// Name of the processes running `program`, without any directory or
+/// `.exe`, so `yt-dlp` also finds `yt-dlp.exe`.
+fn process_name(program: &str) -> &str {
+    Path::new(program).file_stem().and_then(|stem| stem.to_str()).unwrap_or(program)
+}
+
+/// Whether some yt-dlp process on the machine, ours or not, has `link` on
+/// its command line.
+fn recorded_elsewhere(program: &str, link: &String) -> bool {
+    System::new_all()
+        .processes_by_name(process_name(program))
+        .any(|process| process.cmd().contains(link))
+}
+
+/// Where yt-dlp's default template will put the recording, without the
+/// extension, which is only known once the download starts.
+fn intended_output(options: &RecordOptions, item: &LiveStream) -> PathBuf {
+    options.output_dir.join(format!("{} [{}]", item.title, item.video_id))
+}
+
+/// Registers `handle` within the configured slots, logging any recording
+/// it preempts. Returns the outcome if it wasn't admitted, `Queued` when
+/// every slot is taken.
+fn admit(config: &Config, context: &Context, handle: RecordingHandle) -> Option<ChannelOutcome> {
+    let video_id = handle.info.video_id.clone();
+    let priority = handle.priority;
+    match context.recordings.admit(handle, config.slots()) {
+        Admission::Admitted => None,
+        Admission::Preempted(victim) => {
+            warn!(
+                "Preempting {} \"{}\" to record {} at priority {}",
+                victim.video_id, victim.title, video_id, priority
+            );
+            None
+        },
+        Admission::AlreadyRecording => Some(ChannelOutcome::AlreadyRecording),
+        Admission::Retired(reason) => {
+            debug!("Not recording {} again, {:?}", video_id, reason);
+            Some(ChannelOutcome::Skipped)
+        },
+        Admission::Full => {
+            info!("Not recording {} yet, all slots for priority {} are taken", video_id, priority);
+            Some(ChannelOutcome::Queued)
+        },
+        Admission::Closed => {
+            debug!("Not recording {}, shutting down", video_id);
+            Some(ChannelOutcome::Skipped)
+        }
+    }
+}
+
+/// Registers the recording and runs it in the background until yt-dlp exits.
+fn start_recording(
+    config: Arc<Config>,
+    context: Context,
+    channel: &str,
+    item: LiveStream,
+    kind: RecordingKind,
+    options: RecordOptions,
+    details: Option<StreamDetails>
+) -> ChannelOutcome {
+    let video_id = item.video_id.clone();
+    let priority = config.priority(channel);
+    let handle = RecordingHandle::new(RecordingInfo {
+        video_id: video_id.clone(),
+        kind,
+        channel_title: item.channel_title.clone(),
+        title: item.title.clone(),
+        started_at: Utc::now().to_rfc3339(),
+        progress: None
+    }, priority);
+    let stop = handle.stop_flag();
+    match admit(&config, &context, handle) {
+        Some(ChannelOutcome::Queued) => {
+            let ahead = context.queue.push(config.clone(), channel, item, kind, priority);
+            info!("Queued {} for the next free slot, {} waiting ahead of it", video_id, ahead);
+            return ChannelOutcome::Queued;
+        },
+        Some(outcome) => return outcome,
+        None => {}
+    }
+    context.queue.remove(&video_id);
+
+    let details = details.unwrap_or_default();
+    let mut metadata = RecordingMetadata::new(&item, kind);
+    metadata.tags = config.tags.clone();
+    metadata.prefer_hdr = options.prefer_hdr;
+    metadata.projection = details.projection;
+    metadata.dimension = details.dimension;
+    if let Some(projection) = &metadata.projection {
+        info!("{} is a {} projection stream", video_id, projection);
+    }
+    metadata.concurrent_viewers = details.concurrent_viewers;
+    if let Some(viewers) = &metadata.concurrent_viewers {
+        info!("{} concurrent viewers at start of {}", viewers, video_id);
+    }
+    if let Err(err) = metadata.write(&options.output_dir) {
+        warn!("Failed to write metadata for {}: {}", video_id, err);
+    }
+
+    let metadata = Arc::new(Mutex::new(metadata));
+    let on_youtube = Platform::of(&item.link) == Platform::YouTube;
+    let sampler = config.viewer_sample_interval.filter(|_| on_youtube).map(|secs| tokio::spawn(metadata::sample_viewers(
+        config.api_key.clone(),
+        options.output_dir.clone(),
+        metadata.clone(),
+        Duration::from_secs(secs)
+    )));
+
+    match kind {
+        RecordingKind::Live => info!("Recording {} \"{}\"...", video_id, item.title),
+        RecordingKind::Vod => info!("Downloading VOD of finished stream {} \"{}\"...", video_id, item.title)
+    }
+    context.events.publish(Event::RecordingStarted {
+        video_id: video_id.clone(),
+        kind,
+        channel_title: item.channel_title.clone(),
+        title: item.title.clone()
+    });
+
+    run_in_background(config, context, video_id, item.link, options, stop, sampler);
+
+    ChannelOutcome::Started
+}
+
+/// Keeps the registry and the event stream up to date with the progress
+/// of `video_id`.
+fn progress_sink(context: &Context, video_id: &str) -> ProgressSink {
+    let context = context.clone();
+    let video_id = video_id.to_owned();
+    Arc::new(move |progress| {
+        debug!("{}: {}", video_id, progress);
+        context.recordings.set_progress(&video_id, progress.clone());
+        context.events.publish(Event::Progress { video_id: video_id.clone(), progress });
+    })
+}
+
+/// Times yt-dlp is restarted after dying while its stream is still live.
+const MAX_RESTARTS: u32 = 5;
+
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Whether yt-dlp stopped with a failure rather than finishing or being
+/// unable to start at all.
+fn died(status: &Result<Option<i32>>) -> bool {
+    matches!(status, Ok(code) if *code != Some(0))
+}
+
+/// Runs yt-dlp for a registered recording until it exits, restarting it
+/// if it dies while the stream is still live, then removes it from the
+/// registry and notes the outcome.
+fn run_in_background(
+    config: Arc<Config>,
+    context: Context,
+    video_id: String,
+    link: String,
+    options: RecordOptions,
+    stop: Arc<AtomicBool>,
+    sampler: Option<JoinHandle<()>>
+) {
+    tokio::spawn(async move {
+        let output_dir = options.output_dir.clone();
+        let sample = options.max_duration;
+        let started = Instant::now();
+        let started_at = SystemTime::now();
+        if let Some((history, info)) = context.history.as_ref().zip(context.recordings.get(&video_id)) {
+            if let Err(err) = history.save(HistoryEntry::started(&info, &output_dir)) {
+                warn!("Failed to note the recording of {} in the history: {}", video_id, err);
+            }
+        }
+        let mut restarts = 0;
+        let status = loop {
+            let (record_id, record_link, options, run_stop) = (video_id.clone(), link.clone(), options.clone(), stop.clone());
+            let program = options.program.clone();
+            let spawner = ProcessSpawner {
+                spawn_retries: config.spawn_retries,
+                nice: config.nice,
+                progress: options.structured_progress.then(|| progress_sink(&context, &video_id)),
+                grace: Duration::from_secs(config.shutdown_grace)
+            };
+            let status = tokio::task::spawn_blocking(move || {
+                ytdlp::record(&spawner, &options, &record_id, record_link, &run_stop)
+            })
+                .await
+                .unwrap_or_else(|err| Err(RecorderError::Spawn { program, source: io::Error::other(err) }));
+            let done = !died(&status)
+                || restarts == MAX_RESTARTS
+                || stop.load(Ordering::SeqCst)
+                || sample.is_some_and(|sample| started.elapsed() >= sample);
+            if done || !Platform::of(&link).provider().still_live(&config, &link, &video_id).await {
+                break status;
+            }
+            restarts += 1;
+            warn!(
+                "yt-dlp died with {:?} while {} is still live, restarting ({}/{})",
+                status, video_id, restarts, MAX_RESTARTS
+            );
+            tokio::time::sleep(RESTART_DELAY).await;
+        };
+        let duration = started.elapsed();
+        if sample.is_some_and(|sample| duration >= sample) {
+            info!("Sample of {} complete, not recording it again", video_id);
+            context.recordings.retire(&video_id, Retired::Sampled);
+        }
+
+        if let Some(sampler) = sampler {
+            sampler.abort();
+        }
+        if config.writes_subtitles() {
+            if let Err(err) = metadata::add_subtitles(&output_dir, &video_id) {
+                debug!("Captions of {} not listed in a sidecar: {}", video_id, err);
+            }
+        }
+
+        let (code, failure) = match status {
+            Ok(code) => {
+                info!("Recording of {} exited with status {:?}", video_id, code);
+                let failed = code != Some(0) && !stop.load(Ordering::SeqCst);
+                (code, failed.then(|| format!("yt-dlp exited with status {:?}", code)))
+            },
+            Err(RecorderError::DiskFull(dir)) => {
+                error!("DISK FULL: stopped recording {} to keep what was written, no space left in {}", video_id, dir.display());
+                context.error(format!("recording of {} stopped, no space left in {}", video_id, dir.display()));
+                free_space(&config, &context, &dir);
+                (None, Some(format!("no space left in {}", dir.display())))
+            },
+            Err(err @ RecorderError::AuthRequired { .. }) => {
+                warn!("Skipping {}", err);
+                context.error(err.to_string());
+                (None, Some(err.to_string()))
+            },
+            Err(err @ RecorderError::NotMerged { .. }) => {
+                error!("{}, trying again on the next poll", err);
+                context.error(err.to_string());
+                (None, Some(err.to_string()))
+            },
+            Err(err) => {
+                warn!("Recording of {} failed: {}", video_id, err);
+                context.error(format!("recording of {} failed: {}", video_id, err));
+                (None, Some(err.to_string()))
+            }
+        };
+        if let Some(history) = &context.history {
+            let status = match &failure {
+                Some(_) => Status::Failed,
+                None if stop.load(Ordering::SeqCst) => Status::Interrupted,
+                None => Status::Completed
+            };
+            if let Err(err) = history.finish(&video_id, status, ytdlp::existing_recordings(&output_dir, &video_id)) {
+                warn!("Failed to note the end of {} in the history: {}", video_id, err);
+            }
+        }
+        let succeeded = failure.is_none() && !stop.load(Ordering::SeqCst);
+        context.events.publish(Event::RecordingStopped {
+            video_id: video_id.clone(),
+            exit_code: code,
+            files: report::recorded_files(ytdlp::existing_recordings(&output_dir, &video_id)),
+            error: failure
+        });
+        // the slot frees as soon as yt-dlp is done, what's left runs on its own
+        if let Some(info) = context.recordings.remove(&video_id) {
+            context.stats.recording_finished(info, code, ytdlp::existing_recordings(&output_dir, &video_id));
+        }
+        let job = finish(config, context.clone(), video_id.clone(), output_dir, started_at, duration, succeeded);
+        context.post_jobs.spawn(&video_id, job);
+        start_queued(&context).await;
+    });
+}
+
+/// Merges, post-processes and lists in the manifest a recording whose
+/// yt-dlp exited.
+async fn finish(
+    config: Arc<Config>,
+    context: Context,
+    video_id: String,
+    output_dir: PathBuf,
+    started_at: SystemTime,
+    duration: Duration,
+    succeeded: bool
+) {
+    let merged = if config.merge_segments {
+        merge_if_ended(&config, &context, &video_id, &output_dir).await
+    } else {
+        None
+    };
+    if succeeded {
+        // segments wait until they are merged into one file
+        let files = match merged {
+            Some(merged) => vec![merged],
+            None if config.merge_segments && !merge::segments(&output_dir, &video_id).is_empty() => Vec::new(),
+            None => ytdlp::recordings_since(&output_dir, &video_id, started_at)
+        };
+        post_process(&config, &context, &video_id, files).await;
+    }
+    if let Some(format) = config.manifest_format {
+        update_manifest(&context, format, &output_dir, &video_id, duration);
+    }
+}
+
+/// Starts the next queued stream now that a slot is free, dropping those
+/// that went off air while they waited.
+async fn start_queued(context: &Context) {
+    if context.recordings.is_closed() {
+        return;
+    }
+    while let Some(waiting) = context.queue.pop() {
+        let (video_id, title) = (waiting.item.video_id.clone(), waiting.item.title.clone());
+        let provider = provider::provider_of(&waiting.item);
+        if waiting.is_on_air() && !provider.still_live(&waiting.config, &waiting.item.link, &video_id).await {
+            info!("{} \"{}\" is no longer live, dropped from the queue", video_id, title);
+            continue;
+        }
+        info!("Slot free, starting queued {} \"{}\"", video_id, title);
+        match decide(waiting.config, context.clone(), waiting.item, waiting.kind, &waiting.channel).await {
+            Ok(outcome) => debug!("{}: {:?}", video_id, outcome),
+            Err(err) => warn!("Starting queued {} failed: {}", video_id, err)
+        }
+        return;
+    }
+}
+
+/// Describes the finished recording of `video_id` for media servers.
+/// Direct recordings have no sidecar to describe them and are left out.
+fn update_manifest(context: &Context, format: ManifestFormat, dir: &Path, video_id: &String, duration: Duration) {
+    let entry = match manifest::entry_for(dir, video_id, duration) {
+        Ok(entry) if !entry.files.is_empty() => entry,
+        Ok(_) => return,
+        Err(err) => {
+            debug!("No manifest entry for {}: {}", video_id, err);
+            return;
+        }
+    };
+    if let Err(err) = manifest::update(format, dir, &entry) {
+        warn!("Failed to update the manifest for {}: {}", video_id, err);
+        context.error(format!("manifest update for {} failed: {}", video_id, err));
+    }
+}
+
+/// Remuxes, hooks and uploads `files`, reporting steps that failed.
+async fn post_process(config: &Config, context: &Context, video_id: &str, files: Vec<PathBuf>) {
+    let post = config.post_process();
+    if post.is_empty() || files.is_empty() {
+        return;
+    }
+    for failure in postprocess::run(&post, video_id, files).await {
+        context.error(format!("{} of {} failed: {}", failure.step, failure.file.display(), failure.error));
+        context.events.publish(Event::PostProcessFailed {
+            video_id: video_id.to_owned(),
+            step: failure.step,
+            file: failure.file,
+            error: failure.error
+        });
+    }
+}
+
+/// Joins the segments of `video_id` once the API confirms its stream has
+/// ended, returning the merged file. While it's still live the next tick
+/// records another segment, and the merge waits for that one to finish.
+async fn merge_if_ended(config: &Config, context: &Context, video_id: &String, output_dir: &Path) -> Option<PathBuf> {
+    let segments = merge::segments(output_dir, video_id);
+    if segments.is_empty() || config.api_key.is_empty() {
+        return None;
+    }
+    let ended = youtube::fetch_live_details(&config.api_key, video_id)
+        .await
+        .is_some_and(|details| details.actual_end_time.is_some());
+    if !ended {
+        debug!("{} hasn't ended yet, not merging its {} segments", video_id, segments.len());
+        return None;
+    }
+    match merge::merge(&merge::ffmpeg_program(), video_id, &segments, config.delete_segments).await {
+        Ok(merged) => Some(merged),
+        Err(err) => {
+            warn!("Merging segments of {} failed: {}", video_id, err);
+            context.error(format!("merging segments of {} failed: {}", video_id, err));
+            None
+        }
+    }
+}
+
+/// Records the configured URLs and video IDs that are live and aren't
+/// already running. yt-dlp is asked first, so targets that are offline
+/// cost one probe a tick rather than a failed recording.
+async fn start_direct(config: &Arc<Config>, context: &Context, summary: &mut TickSummary) {
+    let options = config.record_options();
+    let mut candidates = Vec::new();
+    for (key, link) in config.direct_targets() {
+        summary.checked += 1;
+        if context.recordings.contains(&key) {
+            summary.live += 1;
+            summary.already_recording += 1;
+        } else {
+            candidates.push((key, link));
+        }
+    }
+    let probes = future::join_all(candidates.iter().map(|(_, link)| ytdlp::probe(&options, link))).await;
+
+    for ((key, link), probe) in candidates.into_iter().zip(probes) {
+        let probe = match probe {
+            Ok(Some(probe)) => probe,
+            Ok(None) => {
+                debug!("{} is not live", link);
+                continue;
+            },
+            Err(err) => {
+                warn!("{}: probe failed: {}", link, err);
+                context.error(format!("{}: probe failed: {}", link, err));
+                summary.errors += 1;
+                continue;
+            }
+        };
+        summary.live += 1;
+        if !enough_space(config, context, &options.output_dir) {
+            summary.skipped += 1;
+            continue;
+        }
+        let handle = RecordingHandle::new(RecordingInfo {
+            video_id: key.clone(),
+            kind: RecordingKind::Live,
+            channel_title: probe.uploader.clone().unwrap_or_default(),
+            title: probe.title.clone(),
+            started_at: Utc::now().to_rfc3339(),
+            progress: None
+        }, config.priority(&key));
+        let stop = handle.stop_flag();
+        match admit(config, context, handle) {
+            Some(ChannelOutcome::AlreadyRecording) => {
+                summary.already_recording += 1;
+                continue;
+            },
+            Some(_) => {
+                summary.skipped += 1;
+                continue;
+            },
+            None => {}
+        }
+
+        let mut options = options.clone();
+        if key == link {
+            // the file name carries yt-dlp's own ID, unknown up front
+            options.stall_timeout = None;
+        }
+        info!("Recording {} \"{}\" directly...", link, probe.title);
+        summary.started += 1;
+        context.events.publish(Event::RecordingStarted {
+            video_id: key.clone(),
+            kind: RecordingKind::Live,
+            channel_title: probe.uploader.unwrap_or_default(),
+            title: probe.title
+        });
+        run_in_background(config.clone(), context.clone(), key, link, options, stop, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use crate::cli::Args;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scheduled_ticks_keep_firing_after_a_panic() {
+        use std::sync::atomic::AtomicUsize;
+        use tokio_cron_scheduler::{Job, JobScheduler};
+
+        let context = Context::default();
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let (job_context, job_ticks, job_fired) = (context.clone(), ticks.clone(), fired.clone());
+        let job = Job::new_repeated_async(Duration::from_secs(1), move |_, _| {
+            let (context, ticks, fired) = (job_context.clone(), job_ticks.clone(), job_fired.clone());
+            Box::pin(async move {
+                let tick = ticks.fetch_add(1, Ordering::SeqCst);
+                catch_panic(async {
+                    if tick == 0 {
+                        // poisons the lock every later tick takes
+                        let _held = context.last_polled.0.lock().unwrap_or_else(PoisonError::into_inner);
+                        panic!("injected");
+                    }
+                    context.last_polled.due("@someone", Duration::ZERO, Duration::from_secs(1));
+                    fired.lock().unwrap_or_else(PoisonError::into_inner).push(tick);
+                }).await;
+            })
+        }).unwrap();
+        let mut scheduler = JobScheduler::new().await.unwrap();
+        scheduler.add(job).await.unwrap();
+        scheduler.start().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(3500)).await;
+        scheduler.shutdown().await.unwrap();
+
+        assert!(context.last_polled.0.is_poisoned());
+        let fired = fired.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        assert!(ticks.load(Ordering::SeqCst) >= 2 && !fired.is_empty() && !fired.contains(&0), "fired {:?}", fired);
+    }
+
+    #[test]
+    fn heartbeat_reports_last_poll_age() {
+        assert_eq!(heartbeat_line(None, 0), "heartbeat: alive, no poll yet, 0 active recordings");
+        assert_eq!(
+            heartbeat_line(Some(Duration::from_millis(12_400)), 2),
+            "heartbeat: alive, last poll 12s ago, 2 active recordings"
+        );
+    }
+
+    #[test]
+    fn only_failed_exits_count_as_deaths() {
+        assert!(died(&Ok(Some(1))));
+        assert!(died(&Ok(None)));
+        assert!(!died(&Ok(Some(0))));
+        assert!(!died(&Err(RecorderError::Scheduler("x".to_owned()))));
+
+        assert_eq!(process_name("yt-dlp"), "yt-dlp");
+        assert_eq!(process_name("/opt/tools/yt-dlp.exe"), "yt-dlp");
+    }
+
+    #[test]
+    fn errors_of_duplicates_are_kept_next_to_the_one_that_started() {
+        let failed = || Err(RecorderError::Scheduler("x".to_owned()));
+        let (outcome, errors) = pick_outcome(vec![failed(), Ok(ChannelOutcome::Started), failed()]);
+        assert!(matches!(outcome, Ok(ChannelOutcome::Started)));
+        assert_eq!(errors.len(), 2);
+
+        let (outcome, errors) = pick_outcome(vec![failed(), Ok(ChannelOutcome::Skipped)]);
+        assert!(outcome.is_err());
+        assert!(errors.is_empty());
+    }
+
+    /// Lookups that never finish, like on a network that swallows requests.
+    struct Hung;
+
+    impl Provider for Hung {
+        fn lookup<'a>(
+            &'a self,
+            _config: &'a Config,
+            _resolver: &'a ChannelResolver,
+            _channel: &'a str
+        ) -> futures::future::BoxFuture<'a, Result<provider::Found>> {
+            Box::pin(future::pending())
+        }
+
+        fn still_live<'a>(&'a self, _config: &'a Config, _link: &'a str, _video_id: &'a str) -> futures::future::BoxFuture<'a, bool> {
+            Box::pin(future::ready(false))
+        }
+    }
+
+    #[tokio::test]
+    async fn lookups_past_the_tick_timeout_count_as_errors() {
+        let args = Args::parse_from(["recorder", "--api-key", "key", "-c", "@first", "-c", "@second"]);
+        let config = Arc::new(Config::from_args(args));
+        let started = Instant::now();
+        let summary = tick_with(config.clone(), Context::default(), &config.channels, Duration::from_millis(200), |_| &Hung).await;
+        assert!(started.elapsed() < Duration::from_secs(2), "tick took {:?}", started.elapsed());
+        assert_eq!(summary.checked, 2);
+        assert_eq!(summary.errors, 2);
+    }
+}
@@ -0,0 +1,335 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+    time::{Duration, Instant}
+};
+
+use log::warn;
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    config::SharedConfig,
+    error::Result,
+    events::{Event, EventBus, Stamped},
+    youtube
+};
+
+const REDACTED: &str = "<redacted>";
+
+/// Longest a notification request may take.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Discord rejects messages longer than this.
+const DISCORD_MAX_CHARS: usize = 2000;
+
+/// What a notifier can be told about.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash
+)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyEvent {
+    LiveDetected,
+    RecordingStarted,
+    RecordingCompleted,
+    RecordingFailed,
+    QuotaExceeded,
+    RecordingsDeleted,
+    PostProcessFailed
+}
+
+impl NotifyEvent {
+    fn label(&self) -> &'static str {
+        match self {
+            NotifyEvent::LiveDetected => "Live stream detected",
+            NotifyEvent::RecordingStarted => "Recording started",
+            NotifyEvent::RecordingCompleted => "Recording completed",
+            NotifyEvent::RecordingFailed => "Recording failed",
+            NotifyEvent::QuotaExceeded => "API quota exceeded",
+            NotifyEvent::RecordingsDeleted => "Old recordings deleted",
+            NotifyEvent::PostProcessFailed => "Post-processing failed"
+        }
+    }
+
+    /// How long the same event about the same video stays quiet after a
+    /// notification, so every poll of a live channel or a direct target
+    /// that keeps failing doesn't send one.
+    fn quiet_period(&self) -> Option<Duration> {
+        match self {
+            NotifyEvent::LiveDetected => Some(Duration::from_secs(24 * 3600)),
+            NotifyEvent::RecordingFailed | NotifyEvent::QuotaExceeded | NotifyEvent::PostProcessFailed => Some(Duration::from_secs(3600)),
+            NotifyEvent::RecordingStarted | NotifyEvent::RecordingCompleted | NotifyEvent::RecordingsDeleted => None
+        }
+    }
+}
+
+/// Where notifications go.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    PartialEq
+)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Sink {
+    /// POSTs the event as JSON, with the message in `message`
+    Webhook { url: String },
+    Discord { webhook_url: String },
+    Telegram { bot_token: String, chat_id: String },
+    /// Full topic URL, e.g. `https://ntfy.sh/my-recorder`
+    Ntfy { url: String }
+}
+
+impl Sink {
+    /// The URL notifications are sent to.
+    fn url(&self) -> String {
+        match self {
+            Sink::Webhook { url } | Sink::Ntfy { url } => url.clone(),
+            Sink::Discord { webhook_url } => webhook_url.clone(),
+            Sink::Telegram { bot_token, .. } => format!("https://api.telegram.org/bot{}/sendMessage", bot_token)
+        }
+    }
+
+    async fn send(&self, client: &reqwest::Client, kind: NotifyEvent, message: &str, event: &Stamped) -> Result<()> {
+        let request = client.post(self.url()).timeout(SEND_TIMEOUT);
+        let request = match self {
+            Sink::Webhook { .. } => request.json(&json!({ "event": kind, "message": message, "data": event })),
+            Sink::Discord { .. } => {
+                let content: String = message.chars().take(DISCORD_MAX_CHARS).collect();
+                request.json(&json!({ "content": content }))
+            },
+            Sink::Telegram { chat_id, .. } => request.json(&json!({ "chat_id": chat_id, "text": message })),
+            Sink::Ntfy { .. } => request.header("Title", kind.label()).body(message.to_owned())
+        };
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// A notification sink and the events it wants, from a `[notifiers.<name>]`
+/// table in the config file.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    PartialEq
+)]
+pub struct Notifier {
+    #[serde(flatten)]
+    pub sink: Sink,
+    /// Every event if left empty
+    #[serde(default)]
+    pub events: BTreeSet<NotifyEvent>
+}
+
+impl Notifier {
+    pub fn wants(&self, kind: NotifyEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+
+    /// Checks the URL it sends to.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        let url = self.sink.url();
+        match reqwest::Url::parse(&url) {
+            Ok(parsed) if ["http", "https"].contains(&parsed.scheme()) => Ok(()),
+            Ok(_) => Err(format!("{} isn't an http(s) URL", url)),
+            Err(err) => Err(format!("{}: {}", url, err))
+        }
+    }
+
+    /// Copy with the URLs and tokens, which grant posting, hidden.
+    pub fn redacted(&self) -> Notifier {
+        let sink = match &self.sink {
+            Sink::Webhook { .. } => Sink::Webhook { url: REDACTED.to_owned() },
+            Sink::Discord { .. } => Sink::Discord { webhook_url: REDACTED.to_owned() },
+            Sink::Telegram { chat_id, .. } => Sink::Telegram { bot_token: REDACTED.to_owned(), chat_id: chat_id.clone() },
+            Sink::Ntfy { .. } => Sink::Ntfy { url: REDACTED.to_owned() }
+        };
+        Notifier { sink, events: self.events.clone() }
+    }
+}
+
+fn mebibytes(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// What `event` counts as, the video it's about and the message to send,
+/// or `None` for events nobody is notified about.
+pub fn describe(event: &Event) -> Option<(NotifyEvent, String, String)> {
+    match event {
+        Event::LiveDetected { video_id, channel_title, title } => Some((
+            NotifyEvent::LiveDetected,
+            video_id.clone(),
+            format!("{} is live: {}\n{}", channel_title, title, youtube::youtube_live_link(video_id))
+        )),
+        Event::RecordingStarted { video_id, channel_title, title, .. } => Some((
+            NotifyEvent::RecordingStarted,
+            video_id.clone(),
+            if channel_title.is_empty() {
+                format!("Recording {}", title)
+            } else {
+                format!("Recording {}: {}", channel_title, title)
+            }
+        )),
+        Event::RecordingStopped { video_id, error: Some(error), .. } => Some((
+            NotifyEvent::RecordingFailed,
+            video_id.clone(),
+            format!("Recording of {} failed: {}", video_id, error)
+        )),
+        Event::RecordingStopped { video_id, files, .. } => {
+            let files: Vec<String> = files
+                .iter()
+                .map(|file| format!("{} ({})", file.path.display(), mebibytes(file.size)))
+                .collect();
+            let message = if files.is_empty() {
+                format!("Recording of {} completed", video_id)
+            } else {
+                format!("Recording of {} completed: {}", video_id, files.join(", "))
+            };
+            Some((NotifyEvent::RecordingCompleted, video_id.clone(), message))
+        },
+        Event::QuotaExceeded { message } => Some((
+            NotifyEvent::QuotaExceeded,
+            String::new(),
+            format!("YouTube API quota exceeded: {}", message)
+        )),
+        Event::RecordingsDeleted { dir, files } => {
+            let names: Vec<String> = files
+                .iter()
+                .map(|file| file.file_name().unwrap_or(file.as_os_str()).to_string_lossy().into_owned())
+                .collect();
+            Some((
+                NotifyEvent::RecordingsDeleted,
+                dir.display().to_string(),
+                format!("Deleted {} files from {}: {}", files.len(), dir.display(), names.join(", "))
+            ))
+        },
+        Event::PostProcessFailed { video_id, step, file, error } => Some((
+            NotifyEvent::PostProcessFailed,
+            video_id.clone(),
+            format!("The {} of {} failed: {}", step, file.display(), error)
+        )),
+        Event::Tick { .. } | Event::Progress { .. } | Event::Error { .. } => None
+    }
+}
+
+/// Remembers what was sent to keep repeats within their quiet period out.
+#[derive(Default)]
+pub struct Throttle {
+    sent: HashMap<(NotifyEvent, String), Instant>
+}
+
+impl Throttle {
+    /// Whether to send `kind` about `subject` at `now`, noting it if so.
+    pub fn allows(&mut self, kind: NotifyEvent, subject: &str, now: Instant) -> bool {
+        let Some(quiet) = kind.quiet_period() else {
+            return true;
+        };
+        let key = (kind, subject.to_owned());
+        if self.sent.get(&key).is_some_and(|at| now.duration_since(*at) < quiet) {
+            return false;
+        }
+        self.sent.insert(key, now);
+        true
+    }
+}
+
+/// Sends every event on `bus` to the notifiers of the current config that
+/// want it, until the process exits. A failed send is logged and dropped.
+pub async fn run(bus: EventBus, shared: Arc<SharedConfig>) {
+    let mut receiver = bus.subscribe();
+    let client = match youtube::client_builder(&shared.current().client_options()).build() {
+        Ok(client) => client,
+        Err(err) => {
+            warn!("Notifications disabled, can't set up an HTTP client: {}", err);
+            return;
+        }
+    };
+    let mut throttle = Throttle::default();
+
+    loop {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("Notifications fell behind, {} events dropped", missed);
+                continue;
+            },
+            Err(RecvError::Closed) => return
+        };
+        let Some((kind, subject, message)) = describe(&event.event) else {
+            continue;
+        };
+        let config = shared.current();
+        if !config.notifiers.values().any(|notifier| notifier.wants(kind)) || !throttle.allows(kind, &subject, Instant::now()) {
+            continue;
+        }
+        for (name, notifier) in config.notifiers.iter().filter(|(_, notifier)| notifier.wants(kind)) {
+            if let Err(err) = notifier.sink.send(&client, kind, &message, &event).await {
+                warn!("Notifying {} of {:?} failed: {}", name, kind, err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::RecordedFile;
+
+    #[test]
+    fn events_are_described_and_repeats_held_back() {
+        let stopped = Event::RecordingStopped {
+            video_id: "abc".to_owned(),
+            exit_code: Some(0),
+            files: vec![RecordedFile { path: "Stream [abc].mp4".into(), size: 3 * 1024 * 1024 }],
+            error: None
+        };
+        let (kind, subject, message) = describe(&stopped).unwrap();
+        assert_eq!((kind, subject.as_str()), (NotifyEvent::RecordingCompleted, "abc"));
+        assert_eq!(message, "Recording of abc completed: Stream [abc].mp4 (3.0 MiB)");
+        let failed = Event::RecordingStopped { video_id: "abc".to_owned(), exit_code: Some(1), files: Vec::new(), error: Some("boom".to_owned()) };
+        assert_eq!(describe(&failed).unwrap().0, NotifyEvent::RecordingFailed);
+        assert!(describe(&Event::Error { message: "x".to_owned() }).is_none());
+
+        let mut throttle = Throttle::default();
+        let now = Instant::now();
+        assert!(throttle.allows(NotifyEvent::LiveDetected, "abc", now));
+        assert!(!throttle.allows(NotifyEvent::LiveDetected, "abc", now + Duration::from_secs(60)));
+        assert!(throttle.allows(NotifyEvent::LiveDetected, "def", now));
+        assert!(throttle.allows(NotifyEvent::QuotaExceeded, "", now));
+        assert!(throttle.allows(NotifyEvent::QuotaExceeded, "", now + Duration::from_secs(3600)));
+        assert!(throttle.allows(NotifyEvent::RecordingCompleted, "abc", now));
+        assert!(throttle.allows(NotifyEvent::RecordingCompleted, "abc", now));
+    }
+
+    #[test]
+    fn notifier_tables_parse_and_redact() {
+        let notifier: Notifier = toml::from_str(r#"
+            kind = "telegram"
+            bot_token = "123:secret"
+            chat_id = "42"
+            events = ["live_detected", "recording_failed"]
+        "#).unwrap();
+        assert!(notifier.wants(NotifyEvent::LiveDetected));
+        assert!(!notifier.wants(NotifyEvent::RecordingStarted));
+        assert_eq!(notifier.sink.url(), "https://api.telegram.org/bot123:secret/sendMessage");
+        assert!(notifier.validate().is_ok());
+        assert!(!serde_json::to_string(&notifier.redacted()).unwrap().contains("secret"));
+
+        let notifier: Notifier = toml::from_str("kind = \"ntfy\"\nurl = \"ntfy.sh/topic\"").unwrap();
+        assert!(notifier.wants(NotifyEvent::QuotaExceeded));
+        assert!(notifier.validate().is_err());
+    }
+}
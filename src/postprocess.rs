@@ -0,0 +1,348 @@
+use std::{
+    fmt,
+    fs,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{Mutex, PoisonError},
+    time::Duration
+};
+
+use clap::ValueEnum;
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use tokio::{process::Command, task::JoinHandle};
+
+use crate::{
+    error::{RecorderError, Result},
+    hooks,
+    merge
+};
+
+/// Wait before retrying a failed step, times the attempt number.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Container finished recordings are remuxed into.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    Mp4,
+    Mkv
+}
+
+impl Container {
+    fn extension(self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Mkv => "mkv"
+        }
+    }
+}
+
+/// Where finished recordings are copied to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Upload {
+    /// `s3://bucket/prefix`, through the AWS CLI, which also talks to
+    /// other S3-compatible stores with `--s3-endpoint`
+    S3(String),
+    /// `remote:path`, through rclone
+    Rclone(String)
+}
+
+impl Upload {
+    /// Parses an `--upload` destination.
+    pub fn parse(dest: &str) -> std::result::Result<Upload, String> {
+        if let Some(bucket) = dest.strip_prefix("s3://") {
+            if bucket.is_empty() {
+                return Err("s3:// needs a bucket".to_owned());
+            }
+            return Ok(Upload::S3(dest.trim_end_matches('/').to_owned()));
+        }
+        match dest.split_once(':') {
+            Some((remote, _)) if !remote.is_empty() => Ok(Upload::Rclone(dest.to_owned())),
+            _ => Err(format!("{} is neither s3://bucket/prefix nor an rclone remote:path", dest))
+        }
+    }
+
+    /// What copies files to the destination.
+    pub fn program(&self) -> &'static str {
+        match self {
+            Upload::S3(_) => "aws",
+            Upload::Rclone(_) => "rclone"
+        }
+    }
+
+    /// The program and arguments copying `file` to the destination.
+    fn command(&self, file: &Path, s3_endpoint: Option<&str>) -> (&'static str, Vec<String>) {
+        let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+        let path = file.to_string_lossy().into_owned();
+        match self {
+            Upload::S3(url) => {
+                let mut args = vec!["s3".to_owned(), "cp".to_owned(), path, format!("{}/{}", url, file_name)];
+                if let Some(endpoint) = s3_endpoint {
+                    args.push("--endpoint-url".to_owned());
+                    args.push(endpoint.to_owned());
+                }
+                (self.program(), args)
+            },
+            Upload::Rclone(dest) => (self.program(), vec!["copy".to_owned(), path, dest.clone()])
+        }
+    }
+}
+
+impl fmt::Display for Upload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Upload::S3(dest) | Upload::Rclone(dest) => f.write_str(dest)
+        }
+    }
+}
+
+/// A stage of the pipeline.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Step {
+    Remux,
+    Hook,
+    Upload
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Step::Remux => "remux",
+            Step::Hook => "post-hook",
+            Step::Upload => "upload"
+        })
+    }
+}
+
+/// What happens to each finished recording, in this order.
+#[derive(Debug, Clone)]
+pub struct PostProcess {
+    pub remux: Option<Container>,
+    /// Shell command run with `VIDEO_ID` and `RECORDING_PATH` set
+    pub hook: Option<String>,
+    pub hook_timeout: Duration,
+    pub upload: Option<Upload>,
+    /// Passed to the AWS CLI as `--endpoint-url`
+    pub s3_endpoint: Option<String>,
+    /// More attempts at a step that failed
+    pub retries: u32,
+    /// Extra environment for the hook
+    pub env: Vec<(String, String)>
+}
+
+impl PostProcess {
+    pub fn is_empty(&self) -> bool {
+        self.remux.is_none() && self.hook.is_none() && self.upload.is_none()
+    }
+}
+
+/// A step that failed for good on one file.
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub step: Step,
+    pub file: PathBuf,
+    pub error: String
+}
+
+/// `file` with the extension of `container`, or `None` if it has it already.
+fn remuxed_path(file: &Path, container: Container) -> Option<PathBuf> {
+    let extension = container.extension();
+    (file.extension().and_then(|ext| ext.to_str()) != Some(extension)).then(|| file.with_extension(extension))
+}
+
+/// Copies the streams of `file` into `output` without re-encoding, then
+/// deletes `file`.
+async fn remux(file: &Path, output: &Path, container: Container) -> Result<()> {
+    let ffmpeg = merge::ffmpeg_program();
+    let mut command = Command::new(&ffmpeg);
+    command.args(["-hide_banner", "-loglevel", "error", "-y", "-i"]).arg(file).args(["-c", "copy"]);
+    if container == Container::Mp4 {
+        command.args(["-movflags", "+faststart"]);
+    }
+    let status = command
+        .arg(output)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .status()
+        .await
+        .map_err(|source| RecorderError::Spawn { program: ffmpeg.clone(), source })?;
+    if !status.success() {
+        let _ = fs::remove_file(output);
+        return Err(io::Error::other(format!("{} exited with {}", ffmpeg, status)).into());
+    }
+    fs::remove_file(file)?;
+    Ok(())
+}
+
+async fn upload(upload: &Upload, file: &Path, s3_endpoint: Option<&str>) -> Result<()> {
+    let (program, args) = upload.command(file, s3_endpoint);
+    let status = Command::new(program)
+        .args(&args)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .status()
+        .await
+        .map_err(|source| RecorderError::Spawn { program: program.to_owned(), source })?;
+    if !status.success() {
+        return Err(io::Error::other(format!("{} exited with {}", program, status)).into());
+    }
+    Ok(())
+}
+
+/// Runs `step` until it succeeds or has failed `retries` more times,
+/// waiting longer after each failure.
+async fn with_retries<F, Fut>(retries: u32, step: Step, file: &Path, mut run: F) -> std::result::Result<(), String>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>>
+{
+    let mut attempt = 0;
+    loop {
+        match run().await {
+            Ok(()) => return Ok(()),
+            Err(err) if attempt < retries => {
+                attempt += 1;
+                warn!("{} of {} failed, trying again ({}/{}): {}", step, file.display(), attempt, retries, err);
+                tokio::time::sleep(RETRY_DELAY * attempt).await;
+            },
+            Err(err) => return Err(err.to_string())
+        }
+    }
+}
+
+/// Remuxes, hands to the hook and uploads each of `files` of `video_id`.
+/// A file whose step fails for good skips the steps after it. Returns
+/// what failed.
+pub async fn run(post: &PostProcess, video_id: &str, files: Vec<PathBuf>) -> Vec<Failure> {
+    let mut failures = Vec::new();
+    for mut file in files {
+        let mut fail = |step, file: &Path, error| failures.push(Failure { step, file: file.to_owned(), error });
+
+        if let Some((container, output)) = post.remux.and_then(|container| Some((container, remuxed_path(&file, container)?))) {
+            match with_retries(post.retries, Step::Remux, &file, || remux(&file, &output, container)).await {
+                Ok(()) => {
+                    info!("Remuxed {} into {}", file.display(), output.display());
+                    file = output;
+                },
+                Err(error) => {
+                    fail(Step::Remux, &file, error);
+                    continue;
+                }
+            }
+        }
+
+        if let Some(hook) = &post.hook {
+            let mut env = vec![
+                ("VIDEO_ID".to_owned(), video_id.to_owned()),
+                ("RECORDING_PATH".to_owned(), file.to_string_lossy().into_owned())
+            ];
+            env.extend(post.env.iter().cloned());
+            let result = with_retries(post.retries, Step::Hook, &file, || async {
+                match hooks::run(hook, &env, post.hook_timeout).await? {
+                    status if status.success() => Ok(()),
+                    status => Err(io::Error::other(format!("exited with {}", status)).into())
+                }
+            }).await;
+            if let Err(error) = result {
+                fail(Step::Hook, &file, error);
+                continue;
+            }
+        }
+
+        if let Some(dest) = &post.upload {
+            let s3_endpoint = post.s3_endpoint.as_deref();
+            match with_retries(post.retries, Step::Upload, &file, || upload(dest, &file, s3_endpoint)).await {
+                Ok(()) => info!("Uploaded {} to {}", file.display(), dest),
+                Err(error) => fail(Step::Upload, &file, error)
+            }
+        }
+    }
+    failures
+}
+
+/// What finished recordings still go through after their slot was freed,
+/// so `--once` can wait for it and shutdown can give up on it.
+#[derive(Default)]
+pub struct Jobs {
+    running: Mutex<Vec<(String, JoinHandle<()>)>>
+}
+
+impl Jobs {
+    /// Runs `job` for `video_id` in the background.
+    pub fn spawn<F: Future<Output = ()> + Send + 'static>(&self, video_id: &str, job: F) {
+        let mut running = self.running.lock().unwrap_or_else(PoisonError::into_inner);
+        running.retain(|(_, job)| !job.is_finished());
+        running.push((video_id.to_owned(), tokio::spawn(job)));
+    }
+
+    /// The videos whose job is still running.
+    pub fn pending(&self) -> Vec<String> {
+        let mut running = self.running.lock().unwrap_or_else(PoisonError::into_inner);
+        running.retain(|(_, job)| !job.is_finished());
+        running.iter().map(|(video_id, _)| video_id.clone()).collect()
+    }
+
+    /// Waits until every job, including those started meanwhile, is done.
+    pub async fn finished(&self) {
+        while !self.pending().is_empty() {
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+    }
+
+    /// Stops every job where it is, killing the programs it runs, and
+    /// returns the videos they were for.
+    pub fn abort_all(&self) -> Vec<String> {
+        let mut running = self.running.lock().unwrap_or_else(PoisonError::into_inner);
+        running
+            .drain(..)
+            .filter(|(_, job)| !job.is_finished())
+            .map(|(video_id, job)| {
+                job.abort();
+                video_id
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn destinations_and_remuxed_names() {
+        let file = Path::new("/rec/Stream 1.5 [abc].ts");
+        let s3 = Upload::parse("s3://archive/streams/").unwrap();
+        assert_eq!(s3, Upload::S3("s3://archive/streams".to_owned()));
+        assert_eq!(s3.command(file, Some("https://minio.local")), ("aws", vec![
+            "s3".to_owned(),
+            "cp".to_owned(),
+            "/rec/Stream 1.5 [abc].ts".to_owned(),
+            "s3://archive/streams/Stream 1.5 [abc].ts".to_owned(),
+            "--endpoint-url".to_owned(),
+            "https://minio.local".to_owned()
+        ]));
+        let rclone = Upload::parse("gdrive:Streams").unwrap();
+        assert_eq!(rclone.command(file, None).1, vec!["copy", "/rec/Stream 1.5 [abc].ts", "gdrive:Streams"]);
+        assert!(Upload::parse("s3://").is_err());
+        assert!(Upload::parse("/mnt/backup").is_err());
+
+        assert_eq!(remuxed_path(file, Container::Mp4), Some(PathBuf::from("/rec/Stream 1.5 [abc].mp4")));
+        assert_eq!(remuxed_path(Path::new("/rec/Stream [abc].mkv"), Container::Mkv), None);
+    }
+
+    #[tokio::test]
+    async fn jobs_can_be_abandoned() {
+        let jobs = Jobs::default();
+        jobs.spawn("done", async {});
+        jobs.spawn("slow", tokio::time::sleep(Duration::from_secs(60)));
+        tokio::task::yield_now().await;
+        assert!(jobs.pending().contains(&"slow".to_owned()));
+        assert!(tokio::time::timeout(Duration::from_millis(50), jobs.finished()).await.is_err());
+        assert_eq!(jobs.abort_all(), ["slow"]);
+        jobs.finished().await;
+    }
+}
@@ -0,0 +1,292 @@
+use futures::future::BoxFuture;
+use log::info;
+
+use crate::{
+    config::Config,
+    error::{RecorderError, Result},
+    filters,
+    registry::RecordingKind,
+    resolver::{self, ChannelResolver},
+    stream::{LiveStream, StreamDetails},
+    twitch,
+    youtube::{self, EventType},
+    ytdlp
+};
+
+/// Where a monitored channel streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    YouTube,
+    Twitch,
+    /// Any page yt-dlp can tell is live
+    Generic
+}
+
+fn host(url: &str) -> Option<&str> {
+    let rest = url.strip_prefix("https://").or_else(|| url.strip_prefix("http://"))?;
+    Some(rest.split(['/', '?', '#']).next().unwrap_or_default())
+}
+
+impl Platform {
+    /// Where `channel` streams: Twitch for `twitch.tv` URLs, generic for
+    /// other http(s) URLs outside YouTube, and YouTube for the rest,
+    /// handles, channel IDs and names included. Works on recording links too.
+    pub fn of(channel: &str) -> Platform {
+        if twitch::login(channel).is_some() {
+            return Platform::Twitch;
+        }
+        match host(channel) {
+            Some(host) if !(host == "youtube.com" || host.ends_with(".youtube.com") || host == "youtu.be") => Platform::Generic,
+            _ => Platform::YouTube
+        }
+    }
+
+    pub fn provider(self) -> &'static dyn Provider {
+        match self {
+            Platform::YouTube => &YouTubeProvider,
+            Platform::Twitch => &TwitchProvider,
+            Platform::Generic => &GenericProvider
+        }
+    }
+}
+
+/// The stream to record on a channel, with other detections of the same
+/// content after it, and whether it's live or a VOD.
+pub type Found = Option<(Vec<LiveStream>, RecordingKind)>;
+
+/// Finds live streams on one platform for the monitor, which then records
+/// them with the same scheduling, dedup and notifications whatever the
+/// platform.
+pub trait Provider: Sync {
+    /// Finds the stream to record on `channel`, if any.
+    fn lookup<'a>(
+        &'a self,
+        config: &'a Config,
+        resolver: &'a ChannelResolver,
+        channel: &'a str
+    ) -> BoxFuture<'a, Result<Found>>;
+
+    /// The API's details of `video_id`, for filters and metadata, on
+    /// platforms that have them.
+    fn details<'a>(&'a self, _config: &'a Config, _video_id: &'a str) -> BoxFuture<'a, Option<StreamDetails>> {
+        Box::pin(async { None })
+    }
+
+    /// Whether `video_id`, recorded from `link`, is still on air. `false`
+    /// when that can't be told.
+    fn still_live<'a>(&'a self, config: &'a Config, link: &'a str, video_id: &'a str) -> BoxFuture<'a, bool>;
+}
+
+/// The provider that found `stream`.
+pub fn provider_of(stream: &LiveStream) -> &'static dyn Provider {
+    Platform::of(&stream.link).provider()
+}
+
+/// A live stream found outside YouTube. Its channel ID is the channel's
+/// URL, which is also what yt-dlp records.
+fn live_on(channel_url: String, video_id: String, channel_title: String, title: String) -> LiveStream {
+    LiveStream {
+        video_id,
+        channel_id: channel_url.clone(),
+        channel_title,
+        title,
+        description: String::new(),
+        upcoming: false,
+        link: channel_url
+    }
+}
+
+/// Whether `found`, on air at `link`, is the stream recorded as
+/// `video_id`. Direct recordings of a URL are registered under the URL.
+fn same_stream(found: &str, link: &str, video_id: &str) -> bool {
+    found == video_id || link == video_id
+}
+
+/// The YouTube Data API, or the channel's page with `--detection-method scrape`.
+pub struct YouTubeProvider;
+
+impl YouTubeProvider {
+    /// What to look `channel` up by: its ID through the API, or as
+    /// configured when the detection method doesn't use the API.
+    async fn channel_id(config: &Config, resolver: &ChannelResolver, channel: &str) -> Result<String> {
+        if config.detection_method.uses_api() {
+            Ok(resolver.resolve(&config.api_key, channel).await?.id)
+        } else {
+            Ok(resolver::identifier(channel).to_owned())
+        }
+    }
+
+    /// Streams of `event_type` on `channel` found by the configured
+    /// detection method.
+    pub async fn streams(
+        &self,
+        config: &Config,
+        resolver: &ChannelResolver,
+        channel: &str,
+        event_type: EventType
+    ) -> Result<Vec<LiveStream>> {
+        let channel_id = Self::channel_id(config, resolver, channel).await?;
+        let cookies = config.scrape_cookies();
+        let cookies = cookies.as_deref();
+        let found = youtube::fetch_streams(&config.api_key, &channel_id, event_type, config.detection_method, &config.search, cookies).await?;
+        Ok(found.items.into_iter().map(LiveStream::from).collect())
+    }
+
+    /// Finds the stream to record on `channel`, if any, using only API
+    /// calls or, with scraping, the channel's page.
+    async fn find(&self, config: &Config, resolver: &ChannelResolver, channel: &str) -> Result<Found> {
+        let api_key = &config.api_key;
+        let channel_id = &Self::channel_id(config, resolver, channel).await?;
+        let method = config.detection_method;
+        let cookies = config.scrape_cookies();
+        let cookies = cookies.as_deref();
+        let mut search = youtube::fetch_streams(api_key, channel_id, EventType::Live, method, &config.search, cookies).await?;
+
+        if search.items.is_empty() {
+            let mut candidates = config.fallback_channels.clone();
+            if config.follow_linked && method.uses_api() {
+                candidates.extend(youtube::fetch_linked_channels(api_key, channel_id).await?);
+            }
+
+            for candidate in candidates {
+                search = youtube::fetch_streams(api_key, &candidate, EventType::Live, method, &config.search, cookies).await?;
+                if !search.items.is_empty() {
+                    info!("{} is not live, recording from linked channel {}", channel, candidate);
+                    break;
+                }
+            }
+        }
+
+        let mut kind = RecordingKind::Live;
+        if search.items.is_empty() && config.include_completed {
+            search = youtube::fetch_streams(api_key, channel_id, EventType::Completed, method, &config.search, cookies).await?;
+            kind = RecordingKind::Vod;
+        }
+
+        let streams = filters::duplicates_of_first(search.items.into_iter().map(LiveStream::from).collect());
+        Ok((!streams.is_empty()).then_some((streams, kind)))
+    }
+}
+
+impl Provider for YouTubeProvider {
+    fn lookup<'a>(
+        &'a self,
+        config: &'a Config,
+        resolver: &'a ChannelResolver,
+        channel: &'a str
+    ) -> BoxFuture<'a, Result<Found>> {
+        Box::pin(self.find(config, resolver, channel))
+    }
+
+    fn details<'a>(&'a self, config: &'a Config, video_id: &'a str) -> BoxFuture<'a, Option<StreamDetails>> {
+        Box::pin(async move { youtube::fetch_video(&config.api_key, video_id).await.map(StreamDetails::from) })
+    }
+
+    fn still_live<'a>(&'a self, config: &'a Config, _link: &'a str, video_id: &'a str) -> BoxFuture<'a, bool> {
+        Box::pin(async move {
+            if config.api_key.is_empty() {
+                return false;
+            }
+            youtube::fetch_live_details(&config.api_key, video_id)
+                .await
+                .is_some_and(|details| details.actual_start_time.is_some() && details.actual_end_time.is_none())
+        })
+    }
+}
+
+/// The Helix API with `--twitch-client-id` and `--twitch-client-secret`,
+/// otherwise yt-dlp, as for any other page.
+pub struct TwitchProvider;
+
+fn login(channel: &str) -> Result<&str> {
+    twitch::login(channel).ok_or_else(|| RecorderError::ChannelNotFound(channel.to_owned()))
+}
+
+impl Provider for TwitchProvider {
+    fn lookup<'a>(
+        &'a self,
+        config: &'a Config,
+        resolver: &'a ChannelResolver,
+        channel: &'a str
+    ) -> BoxFuture<'a, Result<Found>> {
+        Box::pin(async move {
+            let login = login(channel)?;
+            let Some(credentials) = config.twitch_credentials() else {
+                return GenericProvider.lookup(config, resolver, &twitch::channel_link(login)).await;
+            };
+            Ok(twitch::fetch_stream(&credentials, login).await?.map(|stream| {
+                let found = live_on(twitch::channel_link(&stream.user_login), stream.id, stream.user_name, stream.title);
+                (vec![found], RecordingKind::Live)
+            }))
+        })
+    }
+
+    fn still_live<'a>(&'a self, config: &'a Config, link: &'a str, video_id: &'a str) -> BoxFuture<'a, bool> {
+        Box::pin(async move {
+            let (Some(credentials), Ok(login)) = (config.twitch_credentials(), login(link)) else {
+                return GenericProvider.still_live(config, link, video_id).await;
+            };
+            match twitch::fetch_stream(&credentials, login).await {
+                Ok(stream) => stream.is_some_and(|stream| same_stream(&stream.id, link, video_id)),
+                Err(_) => false
+            }
+        })
+    }
+}
+
+/// Asks yt-dlp whether the page is live, costing one yt-dlp run per poll.
+pub struct GenericProvider;
+
+impl Provider for GenericProvider {
+    fn lookup<'a>(
+        &'a self,
+        config: &'a Config,
+        _resolver: &'a ChannelResolver,
+        channel: &'a str
+    ) -> BoxFuture<'a, Result<Found>> {
+        Box::pin(async move {
+            Ok(ytdlp::probe(&config.record_options(), channel).await?.map(|probe| {
+                let found = live_on(channel.to_owned(), probe.id, probe.uploader.unwrap_or_default(), probe.title);
+                (vec![found], RecordingKind::Live)
+            }))
+        })
+    }
+
+    fn still_live<'a>(&'a self, config: &'a Config, link: &'a str, video_id: &'a str) -> BoxFuture<'a, bool> {
+        Box::pin(async move {
+            match ytdlp::probe(&config.record_options(), link).await {
+                Ok(probe) => probe.is_some_and(|probe| same_stream(&probe.id, link, video_id)),
+                Err(_) => false
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_and_items_are_told_apart_by_platform() {
+        assert_eq!(Platform::of("@someone"), Platform::YouTube);
+        assert_eq!(Platform::of("UCxxxxxxxxxxxxxxxxxxxxxx"), Platform::YouTube);
+        assert_eq!(Platform::of("https://www.youtube.com/@someone"), Platform::YouTube);
+        assert_eq!(Platform::of("https://www.youtube.com/watch?v=abc"), Platform::YouTube);
+        assert_eq!(Platform::of("twitch.tv/someone"), Platform::Twitch);
+        assert_eq!(Platform::of("https://www.twitch.tv/someone"), Platform::Twitch);
+        assert_eq!(Platform::of("https://kick.com/someone"), Platform::Generic);
+
+        let found = live_on("https://www.twitch.tv/someone".to_owned(), "42".to_owned(), "Someone".to_owned(), "Speedrun".to_owned());
+        assert_eq!(found.link, "https://www.twitch.tv/someone");
+        assert_eq!(Platform::of(&found.link), Platform::Twitch);
+        let item: youtube::Item = serde_json::from_value(serde_json::json!({
+            "id": { "videoId": "abc" },
+            "snippet": { "channelId": "UCxxxxxxxxxxxxxxxxxxxxxx", "title": "", "description": "", "channelTitle": "", "liveBroadcastContent": "live" }
+        })).unwrap();
+        let found = LiveStream::from(item);
+        assert_eq!(found.link, "https://www.youtube.com/watch?v=abc");
+        assert_eq!(Platform::of(&found.link), Platform::YouTube);
+        assert!(same_stream("7", "https://kick.com/someone", "https://kick.com/someone"));
+        assert!(!same_stream("7", "https://kick.com/someone", "6"));
+    }
+}
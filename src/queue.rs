@@ -0,0 +1,167 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::{
+    config::Config,
+    registry::RecordingKind,
+    stream::LiveStream
+};
+
+/// A live stream found while every recording slot for its priority was
+/// taken, waiting for one to free.
+#[derive(Clone)]
+pub struct Waiting {
+    /// The settings of `channel` when the stream was last found
+    pub config: Arc<Config>,
+    pub channel: String,
+    pub item: LiveStream,
+    pub kind: RecordingKind,
+    pub priority: i32,
+    pub queued_at: String
+}
+
+impl Waiting {
+    /// Whether this was found live, rather than as a VOD or scheduled.
+    pub fn is_on_air(&self) -> bool {
+        self.kind == RecordingKind::Live && !self.item.upcoming
+    }
+}
+
+/// A waiting stream, as shown on the status page.
+#[derive(
+    Serialize,
+    Debug,
+    Clone,
+    PartialEq
+)]
+pub struct QueuedRecording {
+    pub video_id: String,
+    pub channel_title: String,
+    pub title: String,
+    pub priority: i32,
+    pub queued_at: String
+}
+
+/// Streams waiting for a recording slot with `--max-recordings`, taken
+/// highest priority first, then in the order they were found.
+#[derive(Default)]
+pub struct Queue {
+    waiting: Mutex<Vec<Waiting>>
+}
+
+impl Queue {
+    /// Queues `item` of `channel`, or refreshes its entry if it's waiting
+    /// already. Returns how many streams wait before it.
+    pub fn push(&self, config: Arc<Config>, channel: &str, item: LiveStream, kind: RecordingKind, priority: i32) -> usize {
+        let video_id = item.video_id.clone();
+        let mut waiting = self.waiting.lock().unwrap_or_else(PoisonError::into_inner);
+        match waiting.iter_mut().find(|entry| entry.item.video_id == video_id) {
+            Some(entry) => {
+                entry.config = config;
+                entry.item = item;
+                entry.priority = priority;
+            },
+            None => waiting.push(Waiting {
+                config,
+                channel: channel.to_owned(),
+                item,
+                kind,
+                priority,
+                queued_at: Utc::now().to_rfc3339()
+            })
+        }
+        // stable, so streams of one priority stay in the order they came
+        waiting.sort_by_key(|entry| -entry.priority);
+        waiting.iter().position(|entry| entry.item.video_id == video_id).unwrap_or(0)
+    }
+
+    /// Takes the stream to record next, if any is waiting.
+    pub fn pop(&self) -> Option<Waiting> {
+        let mut waiting = self.waiting.lock().unwrap_or_else(PoisonError::into_inner);
+        (!waiting.is_empty()).then(|| waiting.remove(0))
+    }
+
+    pub fn remove(&self, video_id: &str) -> Option<Waiting> {
+        let mut waiting = self.waiting.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = waiting.iter().position(|entry| entry.item.video_id == video_id)?;
+        Some(waiting.remove(index))
+    }
+
+    /// Drops the streams of `channel` that a poll no longer found among
+    /// `live`, returning them. VODs and scheduled streams, which a poll
+    /// doesn't list, stay.
+    pub fn retain_live(&self, channel: &str, live: &[&str]) -> Vec<Waiting> {
+        let mut waiting = self.waiting.lock().unwrap_or_else(PoisonError::into_inner);
+        let (ended, kept) = waiting.drain(..).partition(|entry| {
+            entry.channel == channel
+                && entry.is_on_air()
+                && !live.contains(&entry.item.video_id.as_str())
+        });
+        *waiting = kept;
+        ended
+    }
+
+    pub fn list(&self) -> Vec<QueuedRecording> {
+        self.waiting
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|entry| QueuedRecording {
+                video_id: entry.item.video_id.clone(),
+                channel_title: entry.item.channel_title.clone(),
+                title: entry.item.title.clone(),
+                priority: entry.priority,
+                queued_at: entry.queued_at.clone()
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.waiting.lock().unwrap_or_else(PoisonError::into_inner).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Args;
+    use clap::Parser;
+
+    fn item(video_id: &str) -> LiveStream {
+        serde_json::from_value::<crate::youtube::Item>(serde_json::json!({
+            "id": { "videoId": video_id },
+            "snippet": {
+                "channelId": "UC1",
+                "title": "Stream",
+                "description": "",
+                "channelTitle": "Channel",
+                "liveBroadcastContent": "live"
+            }
+        })).unwrap().into()
+    }
+
+    #[test]
+    fn higher_priorities_jump_the_queue() {
+        let config = Arc::new(Config::from_args(Args::parse_from(["recorder", "--api-key", "key", "-c", "@a"])));
+        let queue = Queue::default();
+        assert_eq!(queue.push(config.clone(), "@a", item("first"), RecordingKind::Live, 0), 0);
+        assert_eq!(queue.push(config.clone(), "@b", item("second"), RecordingKind::Live, 0), 1);
+        assert_eq!(queue.push(config.clone(), "@c", item("urgent"), RecordingKind::Live, 5), 0);
+        assert_eq!(queue.push(config.clone(), "@a", item("first"), RecordingKind::Live, 0), 1);
+        assert_eq!(queue.len(), 3);
+
+        let ended = queue.retain_live("@b", &[]);
+        assert_eq!(ended.len(), 1);
+        assert_eq!(queue.retain_live("@a", &["first"]).len(), 0);
+        assert_eq!(queue.list().iter().map(|queued| queued.video_id.as_str()).collect::<Vec<_>>(), ["urgent", "first"]);
+        assert_eq!(queue.pop().unwrap().item.video_id, "urgent");
+        assert!(queue.remove("first").is_some());
+        assert!(queue.pop().is_none() && queue.is_empty());
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError}
+};
+
+use chrono::{DateTime, Datelike, Days, Duration, NaiveDate, Utc};
+use log::{info, warn};
+
+/// The `nth` Sunday of `month`.
+fn nth_sunday(year: i32, month: u32, nth: u64) -> NaiveDate {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+    let to_sunday = (7 - first.weekday().num_days_from_sunday() as u64) % 7;
+    first + Days::new(to_sunday + 7 * (nth - 1))
+}
+
+/// Hours Pacific time is behind UTC at `now`: 7 from 2:00 on the second
+/// Sunday of March to 2:00 on the first Sunday of November, 8 otherwise.
+fn pacific_offset(now: DateTime<Utc>) -> i64 {
+    let year = now.year();
+    let summer = nth_sunday(year, 3, 2).and_hms_opt(10, 0, 0).unwrap().and_utc();
+    let winter = nth_sunday(year, 11, 1).and_hms_opt(9, 0, 0).unwrap().and_utc();
+    if summer <= now && now < winter { 7 } else { 8 }
+}
+
+/// When the Data API quota next resets: midnight Pacific time.
+pub fn next_reset(now: DateTime<Utc>) -> DateTime<Utc> {
+    let local = now - Duration::hours(pacific_offset(now));
+    let midnight = local.date_naive().succ_opt().unwrap().and_hms_opt(0, 0, 0).unwrap().and_utc();
+    // the clocks may change between now and midnight, never at it
+    midnight + Duration::hours(pacific_offset(midnight + Duration::hours(pacific_offset(now))))
+}
+
+/// How YouTube channels are polled against `--quota-budget`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pace {
+    /// At their configured intervals
+    Normal,
+    /// This many times less often, so what's left lasts until the reset
+    Slowed(f64),
+    /// Not at all until the reset
+    Spent
+}
+
+#[derive(Default)]
+struct State {
+    resets_at: Option<DateTime<Utc>>,
+    /// Units used when the current quota day began
+    used_before: u64,
+    pace: Option<Pace>,
+    /// API keys Google turned away, until the quota resets
+    exhausted: HashMap<String, DateTime<Utc>>
+}
+
+impl State {
+    /// Starts a new quota day if the last one is over.
+    fn roll(&mut self, used: u64, now: DateTime<Utc>) {
+        if self.resets_at.is_some_and(|at| now < at) {
+            return;
+        }
+        if self.resets_at.is_some() {
+            self.used_before = used;
+        }
+        self.resets_at = Some(next_reset(now));
+        self.exhausted.retain(|_, until| now < *until);
+    }
+}
+
+/// The quota spent since the last reset, out of `--quota-budget`, and the
+/// API keys that ran out.
+#[derive(Default)]
+pub struct QuotaBudget {
+    state: Mutex<State>
+}
+
+impl QuotaBudget {
+    /// How to poll now that `used` units were spent in all, when polling
+    /// at the configured intervals spends `per_day` a day. Polls slow down
+    /// once they would overrun `budget` before the reset, and stop when
+    /// it's spent. Also returns whether polls just stopped.
+    pub fn pace(&self, budget: u64, per_day: u64, used: u64, now: DateTime<Utc>) -> (Pace, bool) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.roll(used, now);
+        let spent = used.saturating_sub(state.used_before);
+        let left = budget.saturating_sub(spent);
+        let resets_at = state.resets_at.unwrap();
+        let pace = if left == 0 {
+            Pace::Spent
+        } else {
+            let needed = per_day as f64 * (resets_at - now).num_seconds().max(1) as f64 / 86_400.0;
+            match needed / left as f64 {
+                factor if factor > 1.0 => Pace::Slowed(factor),
+                _ => Pace::Normal
+            }
+        };
+
+        let previous = state.pace.replace(pace);
+        let stopped = pace == Pace::Spent && previous != Some(Pace::Spent);
+        match pace {
+            Pace::Spent if stopped => {
+                warn!("{} of {} quota units spent, no YouTube polls until {}", spent, budget, resets_at);
+            },
+            // only logged when it changes by a whole step, not every tick
+            Pace::Slowed(factor) if !matches!(previous, Some(Pace::Slowed(before)) if before.floor() == factor.floor()) => {
+                info!("{} of {} quota units spent, polling YouTube channels {:.1}x less often until {}", spent, budget, factor, resets_at);
+            },
+            Pace::Normal if previous.is_some_and(|previous| previous != Pace::Normal) => {
+                info!("Back within the quota budget, polling YouTube channels as configured");
+            },
+            _ => ()
+        }
+        (pace, stopped)
+    }
+
+    /// Stops polls with `key`, which Google said is out of quota, until
+    /// the reset. Returns when that is, or `None` if they were stopped.
+    pub fn exhaust(&self, key: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.exhausted.get(key).is_some_and(|until| now < *until) {
+            return None;
+        }
+        let until = next_reset(now);
+        state.exhausted.insert(key.to_owned(), until);
+        Some(until)
+    }
+
+    /// Whether polls with `key` wait for the quota to reset.
+    pub fn exhausted(&self, key: &str, now: DateTime<Utc>) -> bool {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner).exhausted.get(key).is_some_and(|until| now < *until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn polls_slow_down_and_stop_within_the_budget() {
+        let utc = |y, mo, d, h, mi| Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap();
+        assert_eq!(next_reset(utc(2026, 1, 15, 12, 0)), utc(2026, 1, 16, 8, 0));
+        assert_eq!(next_reset(utc(2026, 7, 1, 6, 59)), utc(2026, 7, 1, 7, 0));
+        // 1:00 PST on the day the clocks go forward
+        assert_eq!(next_reset(utc(2026, 3, 8, 9, 0)), utc(2026, 3, 9, 7, 0));
+        assert_eq!(next_reset(utc(2026, 11, 1, 8, 0)), utc(2026, 11, 2, 8, 0));
+
+        // noon Pacific, half the day left
+        let noon = utc(2026, 1, 15, 20, 0);
+        let budget = QuotaBudget::default();
+        assert_eq!(budget.pace(10_000, 10_000, 0, noon), (Pace::Normal, false));
+        assert_eq!(budget.pace(10_000, 10_000, 4_000, noon), (Pace::Normal, false));
+        assert_eq!(budget.pace(10_000, 10_000, 7_500, noon), (Pace::Slowed(2.0), false));
+        assert_eq!(budget.pace(10_000, 10_000, 10_000, noon), (Pace::Spent, true));
+        assert_eq!(budget.pace(10_000, 10_000, 10_050, noon), (Pace::Spent, false));
+        assert_eq!(budget.pace(10_000, 10_000, 10_100, utc(2026, 1, 16, 8, 0)), (Pace::Normal, false));
+
+        assert_eq!(budget.exhaust("key", noon), Some(utc(2026, 1, 16, 8, 0)));
+        assert_eq!(budget.exhaust("key", noon), None);
+        assert!(budget.exhausted("key", noon) && !budget.exhausted("other", noon));
+        assert!(!budget.exhausted("key", utc(2026, 1, 16, 8, 0)));
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        Arc,
+        Mutex,
+        PoisonError,
+        atomic::{AtomicBool, Ordering}
+    }
+};
+
+use clap::ValueEnum;
+use serde::{Serialize, Deserialize};
+
+use crate::ytdlp::Progress;
+
+pub type VideoId = String;
+
+/// How to tell a video is already being recorded.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupStrategy {
+    /// Only this process's own recordings
+    Registry,
+    /// Any yt-dlp process on the machine recording the same link
+    Process,
+    Both
+}
+
+impl DedupStrategy {
+    pub fn uses_registry(&self) -> bool {
+        *self != DedupStrategy::Process
+    }
+
+    pub fn uses_processes(&self) -> bool {
+        *self != DedupStrategy::Registry
+    }
+}
+
+/// Recording priority by channel (or direct target); unlisted ones are 0.
+pub type Priorities = BTreeMap<String, i32>;
+
+/// Parses a `--priority CHANNEL=N` argument.
+pub fn parse_priority(arg: &str) -> Result<(String, i32), String> {
+    let (channel, priority) = arg.split_once('=').ok_or_else(|| format!("expected CHANNEL=N, got {}", arg))?;
+    let priority = priority.parse().map_err(|_| format!("priority must be a whole number, got {}", priority))?;
+    Ok((channel.to_owned(), priority))
+}
+
+/// How many recordings may run at once. With a limit, the last `reserved`
+/// slots only go to recordings with a positive priority, and `preempt`
+/// lets a recording stop a lower-priority one when every slot is taken.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Slots {
+    pub max: Option<usize>,
+    pub reserved: usize,
+    pub preempt: bool
+}
+
+#[derive(Debug)]
+pub enum Admission {
+    Admitted,
+    AlreadyRecording,
+    /// Admitted after asking this lower-priority recording to stop
+    Preempted(Box<RecordingInfo>),
+    /// Every slot open to this priority is taken
+    Full,
+    /// Done with earlier, so not restarted
+    Retired(Retired),
+    /// Shutting down, nothing new starts
+    Closed
+}
+
+/// Why a video won't be recorded again by this process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retired {
+    /// Stopped by the operator
+    Cancelled,
+    /// Its `--sample-minutes` were recorded
+    Sampled
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq
+)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingKind {
+    /// Captured while the stream is on air
+    Live,
+    /// Downloaded after the stream ended
+    Vod
+}
+
+#[derive(
+    Serialize,
+    Debug,
+    Clone
+)]
+pub struct RecordingInfo {
+    pub video_id: VideoId,
+    pub kind: RecordingKind,
+    pub channel_title: String,
+    pub title: String,
+    pub started_at: String,
+    /// Latest from yt-dlp, with `--structured-progress`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<Progress>
+}
+
+pub struct RecordingHandle {
+    pub info: RecordingInfo,
+    pub priority: i32,
+    stop: Arc<AtomicBool>
+}
+
+impl RecordingHandle {
+    pub fn new(info: RecordingInfo, priority: i32) -> RecordingHandle {
+        RecordingHandle {
+            info,
+            priority,
+            stop: Arc::new(AtomicBool::new(false))
+        }
+    }
+
+    /// Flag the recording checks to know it should finalize and exit.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    pub fn request_stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+
+    fn stopping(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+}
+
+/// Everything this process is currently recording, keyed by video ID.
+#[derive(Default)]
+pub struct ActiveRecordings {
+    recordings: Mutex<HashMap<VideoId, RecordingHandle>>,
+    retired: Mutex<HashMap<VideoId, Retired>>,
+    closed: AtomicBool
+}
+
+impl ActiveRecordings {
+    /// Registers `handle` unless its video is already being recorded.
+    pub fn insert(&self, handle: RecordingHandle) -> bool {
+        matches!(self.admit(handle, Slots::default()), Admission::Admitted)
+    }
+
+    /// Registers `handle` if `slots` leave room for its priority. Recordings
+    /// already asked to stop don't hold a slot.
+    pub fn admit(&self, handle: RecordingHandle, slots: Slots) -> Admission {
+        let mut recordings = self.recordings.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.is_closed() {
+            return Admission::Closed;
+        }
+        if recordings.contains_key(&handle.info.video_id) {
+            return Admission::AlreadyRecording;
+        }
+        if let Some(retired) = self.retired.lock().unwrap_or_else(PoisonError::into_inner).get(&handle.info.video_id) {
+            return Admission::Retired(*retired);
+        }
+
+        let mut admission = Admission::Admitted;
+        if let Some(max) = slots.max {
+            let running = recordings.values().filter(|running| !running.stopping()).count();
+            let open = if handle.priority > 0 { max } else { max.saturating_sub(slots.reserved) };
+            if running >= open {
+                let victim = recordings
+                    .values()
+                    .filter(|running| !running.stopping() && running.priority < handle.priority)
+                    .min_by(|a, b| a.priority.cmp(&b.priority).then_with(|| b.info.started_at.cmp(&a.info.started_at)));
+                match victim {
+                    // one stop must be enough to make room
+                    Some(victim) if slots.preempt && running < open + 1 => {
+                        victim.request_stop();
+                        admission = Admission::Preempted(Box::new(victim.info.clone()));
+                    },
+                    _ => return Admission::Full
+                }
+            }
+        }
+        recordings.insert(handle.info.video_id.clone(), handle);
+        admission
+    }
+
+    pub fn remove(&self, video_id: &VideoId) -> Option<RecordingInfo> {
+        self.recordings.lock().unwrap_or_else(PoisonError::into_inner).remove(video_id).map(|handle| handle.info)
+    }
+
+    pub fn set_progress(&self, video_id: &VideoId, progress: Progress) {
+        if let Some(handle) = self.recordings.lock().unwrap_or_else(PoisonError::into_inner).get_mut(video_id) {
+            handle.info.progress = Some(progress);
+        }
+    }
+
+    pub fn get(&self, video_id: &VideoId) -> Option<RecordingInfo> {
+        self.recordings.lock().unwrap_or_else(PoisonError::into_inner).get(video_id).map(|handle| handle.info.clone())
+    }
+
+    pub fn contains(&self, video_id: &VideoId) -> bool {
+        self.recordings.lock().unwrap_or_else(PoisonError::into_inner).contains_key(video_id)
+    }
+
+    pub fn list(&self) -> Vec<RecordingInfo> {
+        self.recordings.lock().unwrap_or_else(PoisonError::into_inner).values().map(|handle| handle.info.clone()).collect()
+    }
+
+    /// Asks the recording of `video_id` to stop, so yt-dlp finalizes its
+    /// file, and keeps it from being started again by later ticks. Returns
+    /// what was asked, or `None` if it isn't being recorded.
+    pub fn cancel(&self, video_id: &VideoId) -> Option<RecordingInfo> {
+        let recordings = self.recordings.lock().unwrap_or_else(PoisonError::into_inner);
+        let handle = recordings.get(video_id)?;
+        handle.request_stop();
+        self.retire(video_id, Retired::Cancelled);
+        Some(handle.info.clone())
+    }
+
+    /// Keeps `video_id` from being recorded again.
+    pub fn retire(&self, video_id: &VideoId, reason: Retired) {
+        self.retired.lock().unwrap_or_else(PoisonError::into_inner).insert(video_id.clone(), reason);
+    }
+
+    /// Asks every recording to stop and admits no more, returning how
+    /// many were asked.
+    pub fn shutdown_all(&self) -> usize {
+        let recordings = self.recordings.lock().unwrap_or_else(PoisonError::into_inner);
+        self.closed.store(true, Ordering::SeqCst);
+        for handle in recordings.values() {
+            handle.request_stop();
+        }
+        recordings.len()
+    }
+
+    /// Whether [`ActiveRecordings::shutdown_all`] was called.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handle(video_id: &str, priority: i32, started_at: &str) -> RecordingHandle {
+        RecordingHandle::new(RecordingInfo {
+            video_id: video_id.to_owned(),
+            kind: RecordingKind::Live,
+            channel_title: String::new(),
+            title: String::new(),
+            started_at: started_at.to_owned(),
+            progress: None
+        }, priority)
+    }
+
+    #[test]
+    fn reserved_slots_and_preemption() {
+        let recordings = ActiveRecordings::default();
+        let slots = Slots { max: Some(2), reserved: 1, preempt: false };
+        assert!(matches!(recordings.admit(handle("a", 0, "1"), slots), Admission::Admitted));
+        assert!(matches!(recordings.admit(handle("b", 0, "2"), slots), Admission::Full));
+        assert!(matches!(recordings.admit(handle("c", 5, "3"), slots), Admission::Admitted));
+        assert!(matches!(recordings.admit(handle("d", 9, "4"), slots), Admission::Full));
+
+        let slots = Slots { preempt: true, ..slots };
+        match recordings.admit(handle("d", 9, "4"), slots) {
+            Admission::Preempted(victim) => assert_eq!(victim.video_id, "a"),
+            other => panic!("expected preemption, got {:?}", other)
+        }
+        assert!(matches!(recordings.admit(handle("e", 1, "5"), slots), Admission::Full));
+        assert!(recordings.contains(&"a".to_owned()));
+
+        assert!(recordings.cancel(&"c".to_owned()).is_some());
+        recordings.remove(&"c".to_owned());
+        assert!(matches!(recordings.admit(handle("c", 5, "6"), slots), Admission::Retired(Retired::Cancelled)));
+        assert!(recordings.cancel(&"zzz".to_owned()).is_none());
+
+        assert_eq!(parse_priority("@fav=10"), Ok(("@fav".to_owned(), 10)));
+        assert!(parse_priority("@fav").is_err());
+        assert!(parse_priority("@fav=high").is_err());
+    }
+
+    #[test]
+    fn nothing_is_admitted_after_shutdown() {
+        let recordings = ActiveRecordings::default();
+        let slots = Slots { max: Some(1), reserved: 0, preempt: false };
+        assert!(matches!(recordings.admit(handle("a", 0, "1"), slots), Admission::Admitted));
+        assert!(matches!(recordings.admit(handle("queued", 0, "2"), slots), Admission::Full));
+        assert_eq!(recordings.shutdown_all(), 1);
+        // the stopping recording no longer holds its slot, but the queued one still can't start
+        assert!(matches!(recordings.admit(handle("queued", 0, "3"), slots), Admission::Closed));
+        recordings.remove(&"a".to_owned());
+        assert!(matches!(recordings.admit(handle("queued", 0, "4"), slots), Admission::Closed));
+        assert!(recordings.is_closed() && recordings.list().is_empty());
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant}
+};
+
+use chrono::Utc;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::{
+    config::SharedConfig,
+    registry::{ActiveRecordings, RecordingInfo},
+    youtube
+};
+
+#[derive(
+    Serialize,
+    Debug,
+    Clone
+)]
+pub struct RecordedFile {
+    pub path: PathBuf,
+    pub size: u64
+}
+
+/// A recording that finished during the report period.
+#[derive(
+    Serialize,
+    Debug,
+    Clone
+)]
+pub struct FinishedRecording {
+    #[serde(flatten)]
+    pub info: RecordingInfo,
+    pub finished_at: String,
+    pub exit_code: Option<i32>,
+    pub files: Vec<RecordedFile>
+}
+
+#[derive(
+    Serialize,
+    Debug,
+    Clone
+)]
+pub struct ErrorEntry {
+    pub at: String,
+    pub message: String
+}
+
+/// Outcome of the latest poll, for the status page.
+#[derive(
+    Serialize,
+    Debug,
+    Clone
+)]
+pub struct PollResult {
+    pub at: String,
+    pub checked: usize,
+    pub live: usize,
+    pub started: usize,
+    pub already_recording: usize,
+    pub skipped: usize,
+    pub queued: usize,
+    pub errors: usize,
+    pub elapsed_ms: u128
+}
+
+/// Errors kept for the status page, however long since the last report.
+const RECENT_ERRORS: usize = 20;
+
+#[derive(Default)]
+struct Period {
+    recordings: Vec<FinishedRecording>,
+    errors: Vec<ErrorEntry>,
+    quota_at_start: u64
+}
+
+/// Counters collected between reports, shared by polls and recordings.
+#[derive(Default)]
+pub struct Stats {
+    period: Mutex<Period>,
+    last_poll: Mutex<Option<Instant>>,
+    last_result: Mutex<Option<PollResult>>,
+    recent_errors: Mutex<VecDeque<ErrorEntry>>
+}
+
+/// `paths` with their current sizes.
+pub fn recorded_files(paths: Vec<PathBuf>) -> Vec<RecordedFile> {
+    paths
+        .into_iter()
+        .map(|path| RecordedFile {
+            size: fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0),
+            path
+        })
+        .collect()
+}
+
+impl Stats {
+    pub fn recording_finished(&self, info: RecordingInfo, exit_code: Option<i32>, files: Vec<PathBuf>) {
+        let files = recorded_files(files);
+        self.period.lock().unwrap_or_else(PoisonError::into_inner).recordings.push(FinishedRecording {
+            info,
+            finished_at: Utc::now().to_rfc3339(),
+            exit_code,
+            files
+        });
+    }
+
+    pub fn error(&self, message: String) {
+        let entry = ErrorEntry {
+            at: Utc::now().to_rfc3339(),
+            message
+        };
+        let mut recent = self.recent_errors.lock().unwrap_or_else(PoisonError::into_inner);
+        if recent.len() == RECENT_ERRORS {
+            recent.pop_front();
+        }
+        recent.push_back(entry.clone());
+        self.period.lock().unwrap_or_else(PoisonError::into_inner).errors.push(entry);
+    }
+
+    pub fn poll_finished(&self, result: PollResult) {
+        *self.last_poll.lock().unwrap_or_else(PoisonError::into_inner) = Some(Instant::now());
+        *self.last_result.lock().unwrap_or_else(PoisonError::into_inner) = Some(result);
+    }
+
+    pub fn last_poll_result(&self) -> Option<PollResult> {
+        self.last_result.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// The latest errors, oldest first.
+    pub fn recent_errors(&self) -> Vec<ErrorEntry> {
+        self.recent_errors.lock().unwrap_or_else(PoisonError::into_inner).iter().cloned().collect()
+    }
+
+    /// How long ago the last poll finished, or `None` before the first one.
+    pub fn since_last_poll(&self) -> Option<Duration> {
+        self.last_poll.lock().unwrap_or_else(PoisonError::into_inner).map(|at| at.elapsed())
+    }
+
+    /// Ends the current period, returning what was collected during it.
+    fn take(&self) -> (Vec<FinishedRecording>, Vec<ErrorEntry>, u64) {
+        let mut period = self.period.lock().unwrap_or_else(PoisonError::into_inner);
+        let quota = youtube::quota_used();
+        let taken = std::mem::replace(&mut *period, Period {
+            quota_at_start: quota,
+            ..Period::default()
+        });
+        (taken.recordings, taken.errors, quota - taken.quota_at_start)
+    }
+}
+
+#[derive(
+    Serialize,
+    Debug
+)]
+pub struct Report {
+    pub generated_at: String,
+    pub channels: Vec<String>,
+    pub active: Vec<RecordingInfo>,
+    pub recordings: Vec<FinishedRecording>,
+    pub errors: Vec<ErrorEntry>,
+    pub quota_used: u64
+}
+
+impl Report {
+    pub fn collect(channels: Vec<String>, recordings: &ActiveRecordings, stats: &Stats) -> Report {
+        let (finished, errors, quota_used) = stats.take();
+        Report {
+            generated_at: Utc::now().to_rfc3339(),
+            channels,
+            active: recordings.list(),
+            recordings: finished,
+            errors,
+            quota_used
+        }
+    }
+
+    pub fn markdown(&self) -> String {
+        let mut out = format!("## Report {}\n\n", self.generated_at);
+        out.push_str(&format!("Channels monitored: {}\n\n", self.channels.join(", ")));
+        out.push_str(&format!("Estimated quota used: {} units\n\n", self.quota_used));
+
+        out.push_str(&format!("### Recordings ({})\n\n", self.recordings.len()));
+        for recording in &self.recordings {
+            out.push_str(&format!(
+                "- {} \"{}\" ({}), exit {:?}\n",
+                recording.info.video_id,
+                recording.info.title,
+                recording.info.channel_title,
+                recording.exit_code
+            ));
+            for file in &recording.files {
+                out.push_str(&format!("  - {} ({} bytes)\n", file.path.display(), file.size));
+            }
+        }
+        for active in &self.active {
+            out.push_str(&format!("- {} \"{}\" ({}), still recording\n", active.video_id, active.title, active.channel_title));
+        }
+
+        out.push_str(&format!("\n### Errors ({})\n\n", self.errors.len()));
+        for error in &self.errors {
+            out.push_str(&format!("- {} {}\n", error.at, error.message));
+        }
+        out.push('\n');
+        out
+    }
+
+    /// Appends the report to `path`: a markdown section for `.md` files, a
+    /// JSON line otherwise.
+    pub fn append_to(&self, path: &Path) -> io::Result<()> {
+        let text = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("md") => self.markdown(),
+            _ => format!("{}\n", serde_json::to_string(self)?)
+        };
+        OpenOptions::new().create(true).append(true).open(path)?.write_all(text.as_bytes())
+    }
+}
+
+/// Writes a report every `every` until the process exits.
+pub async fn write_reports(
+    path: PathBuf,
+    every: Duration,
+    shared: Arc<SharedConfig>,
+    recordings: Arc<ActiveRecordings>,
+    stats: Arc<Stats>
+) {
+    let mut interval = tokio::time::interval(every);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        let report = Report::collect(shared.current().channels.clone(), &recordings, &stats);
+        match report.append_to(&path) {
+            Ok(()) => info!("Wrote report to {}", path.display()),
+            Err(err) => warn!("Failed to write report to {}: {}", path.display(), err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::RecordingKind;
+
+    #[test]
+    fn period_is_reset_after_a_report() {
+        let stats = Stats::default();
+        stats.recording_finished(RecordingInfo {
+            video_id: "abc".to_owned(),
+            kind: RecordingKind::Live,
+            channel_title: "Channel".to_owned(),
+            title: "Stream".to_owned(),
+            started_at: "2024-03-01T20:00:00Z".to_owned(),
+            progress: None
+        }, Some(0), vec![PathBuf::from("missing.mp4")]);
+        stats.error("quota exceeded".to_owned());
+
+        let report = Report::collect(vec!["chan".to_owned()], &ActiveRecordings::default(), &stats);
+        let markdown = report.markdown();
+        assert!(markdown.contains("- abc \"Stream\" (Channel), exit Some(0)"));
+        assert!(markdown.contains("  - missing.mp4 (0 bytes)"));
+        assert!(markdown.contains("quota exceeded"));
+
+        let next = Report::collect(vec!["chan".to_owned()], &ActiveRecordings::default(), &stats);
+        assert!(next.recordings.is_empty() && next.errors.is_empty());
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError}
+};
+
+use serde::Serialize;
+
+use crate::{
+    error::{RecorderError, Result},
+    youtube::{self, ChannelFilter}
+};
+
+#[derive(
+    Serialize,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq
+)]
+pub struct ResolvedChannel {
+    pub id: String,
+    pub title: String
+}
+
+/// What to look a channel up by: the `@handle`, channel ID or name in a
+/// `youtube.com/@handle`, `/channel/UC...`, `/user/name` or `/c/name` URL,
+/// or `channel` itself when it isn't one.
+pub fn identifier(channel: &str) -> &str {
+    let path = channel.split_once("://").map_or(channel, |(_, rest)| rest);
+    let Some((host, path)) = path.split_once('/') else { return channel };
+    if !(host == "youtube.com" || host.ends_with(".youtube.com")) {
+        return channel;
+    }
+    let mut segments = path.split(['?', '#']).next().unwrap_or_default().split('/');
+    match (segments.next(), segments.next()) {
+        (Some(handle), _) if handle.starts_with('@') => handle,
+        (Some("channel" | "user" | "c"), Some(name)) if !name.is_empty() => name,
+        _ => channel
+    }
+}
+
+/// How `channel` should be looked up: `@handle`, a `UC...` channel ID, or
+/// a bare name that may be either a legacy username or a handle.
+pub fn filters_for(channel: &str) -> Vec<ChannelFilter> {
+    if channel.starts_with('@') {
+        vec![ChannelFilter::Handle]
+    } else if channel.len() == 24 && channel.starts_with("UC") {
+        vec![ChannelFilter::Id]
+    } else {
+        vec![ChannelFilter::Username, ChannelFilter::Handle]
+    }
+}
+
+/// Picks the single channel among `matches`, or reports why there isn't one.
+fn pick(channel: &str, mut matches: Vec<(String, String)>) -> Result<ResolvedChannel> {
+    matches.sort();
+    matches.dedup_by(|a, b| a.0 == b.0);
+    match matches.len() {
+        0 => Err(RecorderError::ChannelNotFound(channel.to_owned())),
+        1 => {
+            let (id, title) = matches.remove(0);
+            Ok(ResolvedChannel { id, title })
+        },
+        _ => Err(RecorderError::AmbiguousChannel {
+            channel: channel.to_owned(),
+            candidates: matches.into_iter().map(|(id, title)| format!("{} ({})", id, title)).collect()
+        })
+    }
+}
+
+/// Resolves usernames, handles and channel URLs to channel IDs,
+/// remembering successful lookups for the life of the process.
+#[derive(Default)]
+pub struct ChannelResolver {
+    cache: Mutex<HashMap<String, ResolvedChannel>>
+}
+
+impl ChannelResolver {
+    pub async fn resolve(&self, api_key: &str, channel: &str) -> Result<ResolvedChannel> {
+        if let Some(resolved) = self.cache.lock().unwrap_or_else(PoisonError::into_inner).get(channel) {
+            return Ok(resolved.clone());
+        }
+
+        let lookup = identifier(channel).to_owned();
+        let mut matches = Vec::new();
+        for filter in filters_for(&lookup) {
+            matches.extend(youtube::fetch_channels(api_key, filter, &lookup).await?);
+        }
+        let resolved = pick(channel, matches)?;
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner).insert(channel.to_owned(), resolved.clone());
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_urls_are_reduced_to_what_they_name() {
+        assert_eq!(identifier("https://www.youtube.com/@somehandle/live"), "@somehandle");
+        assert_eq!(identifier("youtube.com/channel/UCxxxxxxxxxxxxxxxxxxxxxx?si=abc"), "UCxxxxxxxxxxxxxxxxxxxxxx");
+        assert_eq!(identifier("https://m.youtube.com/user/legacy"), "legacy");
+        assert_eq!(identifier("@somehandle"), "@somehandle");
+        assert_eq!(identifier("https://example.com/@somehandle"), "https://example.com/@somehandle");
+        assert_eq!(filters_for(identifier("https://youtube.com/channel/UCxxxxxxxxxxxxxxxxxxxxxx")), vec![ChannelFilter::Id]);
+    }
+
+    #[test]
+    fn same_channel_by_username_and_handle_is_not_ambiguous() {
+        let found = ("UC1".to_owned(), "One".to_owned());
+        assert_eq!(pick("one", vec![found.clone(), found.clone()]).unwrap().id, "UC1");
+        assert!(matches!(pick("one", vec![]), Err(RecorderError::ChannelNotFound(_))));
+        assert!(matches!(
+            pick("one", vec![found, ("UC2".to_owned(), "Other".to_owned())]),
+            Err(RecorderError::AmbiguousChannel { .. })
+        ));
+    }
+}
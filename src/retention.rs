@@ -0,0 +1,318 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime}
+};
+
+use log::info;
+#[cfg(not(unix))]
+use sysinfo::{DiskExt, System, SystemExt};
+
+use crate::{
+    history::HistoryEntry,
+    metadata::RecordingMetadata,
+    registry::ActiveRecordings,
+    ytdlp
+};
+
+/// Every file belonging to one recording: the media, yt-dlp's metadata
+/// files, our `.meta.json` sidecar and the extras directory.
+#[derive(Debug, Default)]
+struct Group {
+    files: Vec<PathBuf>,
+    size: u64,
+    /// Last change to the media files, which sidecar updates don't count as
+    newest: Option<SystemTime>,
+    partial: bool,
+    /// Whether our `.meta.json` sidecar is among the files
+    has_sidecar: bool
+}
+
+/// Video ID of a file written by this tool: the last `[id]` in a yt-dlp
+/// file name, or the stem of a `{id}.meta.json` sidecar or `{id}.extras`
+/// directory.
+fn video_id_of(name: &str) -> Option<&str> {
+    if let Some(video_id) = name.strip_suffix(".meta.json").or_else(|| name.strip_suffix(".extras")) {
+        return Some(video_id);
+    }
+    let start = name.rfind('[')? + 1;
+    let end = start + name[start..].find(']')?;
+    Some(&name[start..end])
+}
+
+/// The videos the history lists in `dir`, by the IDs in their file names
+/// as well, which for direct recordings aren't what they're listed under.
+pub fn recorded_in(dir: &Path, entries: Vec<HistoryEntry>) -> HashSet<String> {
+    let mut recorded = HashSet::new();
+    for entry in entries.into_iter().filter(|entry| entry.output_dir == dir) {
+        for file in &entry.files {
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            recorded.extend(video_id_of(&name).map(str::to_owned));
+        }
+        recorded.insert(entry.video_id);
+    }
+    recorded
+}
+
+fn is_tagged(dir: &Path, video_id: &str) -> bool {
+    fs::read(dir.join(format!("{}.meta.json", video_id)))
+        .ok()
+        .and_then(|json| serde_json::from_slice::<RecordingMetadata>(&json).ok())
+        .is_some_and(|metadata| !metadata.tags.is_empty())
+}
+
+/// How much of an output directory to keep. Only recordings this tool made,
+/// known by their `.meta.json` sidecar or from the history, are deleted,
+/// and never while in progress, whether registered or with partial files
+/// left, nor tagged ones with `keep_tagged`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Retention {
+    /// Delete the oldest recordings once the directory holds more
+    pub max_total_bytes: Option<u64>,
+    /// Delete recordings last written longer ago
+    pub max_age: Option<Duration>,
+    pub keep_tagged: bool
+}
+
+impl Retention {
+    pub fn is_empty(&self) -> bool {
+        self.max_total_bytes.is_none() && self.max_age.is_none()
+    }
+
+    /// Applies the policy to `dir`, returning the deleted files. `recorded`
+    /// are the videos the history lists there, sidecar or not.
+    pub fn enforce(&self, dir: &Path, active: &ActiveRecordings, recorded: &HashSet<String>) -> io::Result<Vec<PathBuf>> {
+        let mut deleted = Vec::new();
+        if let Some(max_age) = self.max_age {
+            deleted.extend(enforce_max_age(dir, max_age, self.keep_tagged, active, recorded)?);
+        }
+        if let Some(limit) = self.max_total_bytes {
+            deleted.extend(enforce_limit(dir, limit, self.keep_tagged, active, recorded)?);
+        }
+        Ok(deleted)
+    }
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .map(|entries| entries.filter_map(|entry| entry.ok()?.metadata().ok()).filter(|meta| meta.is_file()).map(|meta| meta.len()).sum())
+        .unwrap_or(0)
+}
+
+fn remove(path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// The total size of `dir` and its finished recordings that may be
+/// deleted, oldest first.
+fn deletable(dir: &Path, keep_tagged: bool, active: &ActiveRecordings, recorded: &HashSet<String>) -> io::Result<(u64, Vec<(String, Group)>)> {
+    let mut total = 0;
+    let mut groups: HashMap<String, Group> = HashMap::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let extras = meta.is_dir() && name.ends_with(".extras");
+        if !meta.is_file() && !extras {
+            continue;
+        }
+        let size = if extras { dir_size(&entry.path()) } else { meta.len() };
+        total += size;
+
+        let Some(video_id) = video_id_of(&name) else { continue };
+        let group = groups.entry(video_id.to_owned()).or_default();
+        group.has_sidecar |= name.ends_with(".meta.json");
+        group.size += size;
+        if !extras && !ytdlp::is_sidecar(&name) {
+            group.newest = group.newest.max(meta.modified().ok());
+        }
+        group.partial |= ytdlp::is_partial(&name);
+        group.files.push(entry.path());
+    }
+
+    let mut candidates: Vec<(String, Group)> = groups
+        .into_iter()
+        .filter(|(video_id, group)| group.has_sidecar || recorded.contains(video_id))
+        .filter(|(video_id, group)| !group.partial && !active.contains(video_id))
+        .filter(|(video_id, _)| !(keep_tagged && is_tagged(dir, video_id)))
+        .collect();
+    candidates.sort_by_key(|(_, group)| group.newest);
+    Ok((total, candidates))
+}
+
+/// Deletes the oldest finished recordings in `dir` until everything in it
+/// fits in `limit` bytes, see [`Retention`]. Returns the deleted files.
+pub fn enforce_limit(
+    dir: &Path,
+    limit: u64,
+    keep_tagged: bool,
+    active: &ActiveRecordings,
+    recorded: &HashSet<String>
+) -> io::Result<Vec<PathBuf>> {
+    let (mut total, candidates) = deletable(dir, keep_tagged, active, recorded)?;
+    let mut deleted = Vec::new();
+    for (video_id, group) in candidates {
+        if total <= limit {
+            break;
+        }
+        for file in group.files {
+            remove(&file)?;
+            info!("Disk limit reached, deleted {} of {}", file.display(), video_id);
+            deleted.push(file);
+        }
+        total -= group.size;
+    }
+    Ok(deleted)
+}
+
+/// Deletes the finished recordings in `dir` whose media files weren't
+/// written to for `max_age`, see [`Retention`]. Returns the deleted files.
+pub fn enforce_max_age(
+    dir: &Path,
+    max_age: Duration,
+    keep_tagged: bool,
+    active: &ActiveRecordings,
+    recorded: &HashSet<String>
+) -> io::Result<Vec<PathBuf>> {
+    let (_, candidates) = deletable(dir, keep_tagged, active, recorded)?;
+    let cutoff = SystemTime::now() - max_age;
+    let mut deleted = Vec::new();
+    for (video_id, group) in candidates.into_iter().filter(|(_, group)| group.newest.is_some_and(|newest| newest < cutoff)) {
+        for file in group.files {
+            remove(&file)?;
+            info!("Past the age limit, deleted {} of {}", file.display(), video_id);
+            deleted.push(file);
+        }
+    }
+    Ok(deleted)
+}
+
+/// Bytes free for unprivileged use on the volume holding `dir`.
+#[cfg(unix)]
+pub fn available_space(dir: &Path) -> Option<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Bytes free on the disk mounted closest to `dir`.
+#[cfg(not(unix))]
+pub fn available_space(dir: &Path) -> Option<u64> {
+    let dir = dir.canonicalize().ok()?;
+    let mut system = System::new();
+    system.refresh_disks_list();
+    system
+        .disks()
+        .iter()
+        .filter(|disk| dir.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{RecordingHandle, RecordingInfo, RecordingKind};
+
+    /// Writes a recording with its sidecar, as if recorded by this tool.
+    fn recording(dir: &Path, name: &str, video_id: &str, age_secs: u64) {
+        write_aged(&dir.join(format!("{} [{}].mp4", name, video_id)), 100, age_secs);
+        fs::write(dir.join(format!("{}.meta.json", video_id)), b"{}").unwrap();
+    }
+
+    fn write_aged(path: &Path, bytes: usize, age_secs: u64) {
+        fs::write(path, vec![0; bytes]).unwrap();
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(age_secs)).unwrap();
+    }
+
+    #[test]
+    fn oldest_untagged_finished_recordings_go_first() {
+        let dir = std::env::temp_dir().join(format!("ytlr-retention-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        write_aged(&dir.join("Oldest [aaaaaaaaaaa].mp4"), 100, 300);
+        fs::write(dir.join("aaaaaaaaaaa.meta.json"), br#"{"video_id":"aaaaaaaaaaa","kind":"live","title":"","channel_id":"","channel_title":"","started_at":"","concurrent_viewers":null,"viewer_samples":[],"tags":{"keep":"yes"}}"#).unwrap();
+        write_aged(&dir.join("Older [bbbbbbbbbbb].mp4"), 100, 200);
+        write_aged(&dir.join("Newer [ccccccccccc].mp4"), 100, 100);
+        write_aged(&dir.join("Recording [ddddddddddd].mp4.part"), 100, 400);
+        write_aged(&dir.join("Notes [draft].txt"), 100, 500);
+        write_aged(&dir.join("Not ours [eeeeeeeeeee].mp4"), 100, 600);
+        let recorded: HashSet<String> = ["bbbbbbbbbbb", "ccccccccccc", "ddddddddddd"].map(String::from).into();
+
+        let deleted = enforce_limit(&dir, 700, true, &ActiveRecordings::default(), &recorded).unwrap();
+        assert_eq!(deleted, vec![dir.join("Older [bbbbbbbbbbb].mp4")]);
+
+        let deleted = enforce_limit(&dir, 500, false, &ActiveRecordings::default(), &recorded).unwrap();
+        assert_eq!(deleted.len(), 2);
+        assert!(dir.join("Newer [ccccccccccc].mp4").exists());
+        assert!(dir.join("Recording [ddddddddddd].mp4.part").exists());
+        assert!(dir.join("Notes [draft].txt").exists());
+        assert!(dir.join("Not ours [eeeeeeeeeee].mp4").exists());
+    }
+
+    #[test]
+    fn recordings_past_the_age_limit_are_deleted() {
+        let dir = std::env::temp_dir().join(format!("ytlr-retention-age-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        write_aged(&dir.join("Old [aaaaaaaaaaa].mp4"), 100, 3 * 86400);
+        write_aged(&dir.join("Old [aaaaaaaaaaa].info.json"), 10, 3 * 86400);
+        fs::create_dir_all(dir.join("aaaaaaaaaaa.extras")).unwrap();
+        fs::write(dir.join("aaaaaaaaaaa.extras/Old [aaaaaaaaaaa].live_chat.json"), b"[]").unwrap();
+        write_aged(&dir.join("Recent [bbbbbbbbbbb].mp4"), 100, 3600);
+        write_aged(&dir.join("Live [ccccccccccc].mp4"), 100, 3 * 86400);
+        let active = ActiveRecordings::default();
+        active.insert(RecordingHandle::new(RecordingInfo {
+            video_id: "ccccccccccc".to_owned(),
+            kind: RecordingKind::Live,
+            channel_title: String::new(),
+            title: String::new(),
+            started_at: String::new(),
+            progress: None
+        }, 0));
+
+        let retention = Retention { max_total_bytes: None, max_age: Some(Duration::from_secs(86400)), keep_tagged: false };
+        let mut deleted = retention.enforce(&dir, &active, &["aaaaaaaaaaa", "bbbbbbbbbbb", "ccccccccccc"].map(String::from).into()).unwrap();
+        deleted.sort();
+        assert_eq!(deleted, vec![dir.join("Old [aaaaaaaaaaa].info.json"), dir.join("Old [aaaaaaaaaaa].mp4"), dir.join("aaaaaaaaaaa.extras")]);
+        assert!(!dir.join("aaaaaaaaaaa.extras").exists());
+        assert!(dir.join("Recent [bbbbbbbbbbb].mp4").exists());
+        assert!(dir.join("Live [ccccccccccc].mp4").exists());
+    }
+
+    #[test]
+    fn recordings_from_other_platforms_are_pruned_too() {
+        let dir = std::env::temp_dir().join(format!("ytlr-retention-ids-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        // Twitch stream IDs are numbers of any length
+        recording(&dir, "Speedrun", "314159265358", 3 * 86400);
+        write_aged(&dir.join("Kick stream [42].mp4"), 100, 3 * 86400);
+        write_aged(&dir.join("Holiday [2019].mp4"), 100, 3 * 86400);
+
+        let retention = Retention { max_total_bytes: None, max_age: Some(Duration::from_secs(86400)), keep_tagged: false };
+        let mut deleted = retention.enforce(&dir, &ActiveRecordings::default(), &["42".to_owned()].into()).unwrap();
+        deleted.sort();
+        assert_eq!(deleted, vec![
+            dir.join("314159265358.meta.json"),
+            dir.join("Kick stream [42].mp4"),
+            dir.join("Speedrun [314159265358].mp4")
+        ]);
+        assert!(dir.join("Holiday [2019].mp4").exists());
+    }
+}
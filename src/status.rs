@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{
+    config::SharedConfig,
+    metrics::Metrics,
+    queue::{Queue, QueuedRecording},
+    registry::{ActiveRecordings, RecordingInfo},
+    report::{ErrorEntry, PollResult, Stats},
+    ytdlp
+};
+
+/// How often the status page reloads itself.
+const REFRESH_SECS: u32 = 30;
+
+/// A recording in progress, as shown on the status page.
+#[derive(
+    Serialize,
+    Debug,
+    Clone
+)]
+pub struct ActiveRecording {
+    #[serde(flatten)]
+    pub info: RecordingInfo,
+    pub elapsed_secs: u64,
+    /// Written so far, partial files included
+    pub bytes: u64
+}
+
+/// Everything the status page shows, as served by `GET /status`.
+#[derive(
+    Serialize,
+    Debug
+)]
+pub struct Status {
+    pub generated_at: String,
+    pub channels: Vec<String>,
+    pub active: Vec<ActiveRecording>,
+    /// Waiting for a free slot, next first
+    pub queued: Vec<QueuedRecording>,
+    pub last_poll: Option<PollResult>,
+    pub recent_errors: Vec<ErrorEntry>
+}
+
+/// Where the status page and metrics get their data.
+#[derive(Clone)]
+pub struct StatusSource {
+    pub shared: Arc<SharedConfig>,
+    pub recordings: Arc<ActiveRecordings>,
+    pub stats: Arc<Stats>,
+    pub metrics: Arc<Metrics>,
+    pub queue: Arc<Queue>
+}
+
+impl StatusSource {
+    pub fn collect(&self) -> Status {
+        let config = self.shared.current();
+        let now = Utc::now();
+        let mut active: Vec<ActiveRecording> = self.recordings
+            .list()
+            .into_iter()
+            .map(|info| ActiveRecording {
+                elapsed_secs: DateTime::parse_from_rfc3339(&info.started_at)
+                    .map(|started| (now - started.with_timezone(&Utc)).num_seconds().max(0) as u64)
+                    .unwrap_or(0),
+                bytes: config.output_dirs().iter().map(|dir| ytdlp::recorded_bytes(dir, &info.video_id)).sum(),
+                info
+            })
+            .collect();
+        active.sort_by(|a, b| a.info.started_at.cmp(&b.info.started_at));
+        Status {
+            generated_at: now.to_rfc3339(),
+            channels: config.channels.clone(),
+            active,
+            queued: self.queue.list(),
+            last_poll: self.stats.last_poll_result(),
+            recent_errors: self.stats.recent_errors()
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn duration(secs: u64) -> String {
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+impl Status {
+    /// A small self-refreshing page, readable on a phone.
+    pub fn html(&self) -> String {
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><meta name=\"viewport\" content=\"width=device-width\">\
+             <meta http-equiv=\"refresh\" content=\"{}\"><title>Recorder status</title></head><body>\n",
+            REFRESH_SECS
+        );
+        out.push_str(&format!("<h1>Recording {}</h1>\n<ul>\n", self.active.len()));
+        for recording in &self.active {
+            out.push_str(&format!(
+                "<li>{} &ldquo;{}&rdquo; ({}), {}, {:.1} MiB</li>\n",
+                escape(&recording.info.video_id),
+                escape(&recording.info.title),
+                escape(&recording.info.channel_title),
+                duration(recording.elapsed_secs),
+                recording.bytes as f64 / (1024.0 * 1024.0)
+            ));
+        }
+        out.push_str(&format!("</ul>\n<h2>Queued ({})</h2>\n<ol>\n", self.queued.len()));
+        for queued in &self.queued {
+            out.push_str(&format!(
+                "<li>{} &ldquo;{}&rdquo; ({}), priority {}, since {}</li>\n",
+                escape(&queued.video_id),
+                escape(&queued.title),
+                escape(&queued.channel_title),
+                queued.priority,
+                escape(&queued.queued_at)
+            ));
+        }
+        out.push_str("</ol>\n<h2>Last poll</h2>\n");
+        match &self.last_poll {
+            Some(poll) => out.push_str(&format!(
+                "<p>{}: {} checked, {} live, {} started, {} already recording, {} skipped, {} queued, {} errors in {} ms</p>\n",
+                escape(&poll.at), poll.checked, poll.live, poll.started, poll.already_recording, poll.skipped, poll.queued, poll.errors, poll.elapsed_ms
+            )),
+            None => out.push_str("<p>No poll yet</p>\n")
+        }
+        out.push_str(&format!("<h2>Channels ({})</h2>\n<ul>\n", self.channels.len()));
+        for channel in &self.channels {
+            out.push_str(&format!("<li>{}</li>\n", escape(channel)));
+        }
+        out.push_str(&format!("</ul>\n<h2>Recent errors ({})</h2>\n<ul>\n", self.recent_errors.len()));
+        for error in self.recent_errors.iter().rev() {
+            out.push_str(&format!("<li>{} {}</li>\n", escape(&error.at), escape(&error.message)));
+        }
+        out.push_str(&format!("</ul>\n<p><small>{}</small></p>\n</body></html>\n", escape(&self.generated_at)));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::RecordingKind;
+
+    #[test]
+    fn page_lists_recordings_and_escapes_titles() {
+        let status = Status {
+            generated_at: "now".to_owned(),
+            channels: vec!["@someone".to_owned()],
+            active: vec![ActiveRecording {
+                info: RecordingInfo {
+                    video_id: "abc".to_owned(),
+                    kind: RecordingKind::Live,
+                    channel_title: "Someone".to_owned(),
+                    title: "Q&A <live>".to_owned(),
+                    started_at: "2024-01-01T00:00:00Z".to_owned(),
+                    progress: None
+                },
+                elapsed_secs: 3725,
+                bytes: 5 * 1024 * 1024
+            }],
+            queued: vec![QueuedRecording {
+                video_id: "def".to_owned(),
+                channel_title: "Other".to_owned(),
+                title: "Next".to_owned(),
+                priority: 2,
+                queued_at: "2024-01-01T01:00:00Z".to_owned()
+            }],
+            last_poll: None,
+            recent_errors: Vec::new()
+        };
+        let page = status.html();
+        assert!(page.contains("<li>abc &ldquo;Q&amp;A &lt;live&gt;&rdquo; (Someone), 1:02:05, 5.0 MiB</li>"));
+        assert!(page.contains("<h2>Queued (1)</h2>\n<ol>\n<li>def &ldquo;Next&rdquo; (Other), priority 2, since 2024-01-01T01:00:00Z</li>"));
+        assert!(page.contains("<p>No poll yet</p>"));
+        assert!(page.contains("<li>@someone</li>"));
+    }
+}
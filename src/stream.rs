@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use crate::youtube::{self, Item, LiveStreamingDetails, VideoItem};
+
+/// A stream a provider found on a monitored channel, whatever the platform.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiveStream {
+    /// The platform's ID for it
+    pub video_id: String,
+    /// The channel's ID on YouTube, its URL elsewhere
+    pub channel_id: String,
+    pub channel_title: String,
+    pub title: String,
+    pub description: String,
+    /// Scheduled but not on air yet
+    pub upcoming: bool,
+    /// What yt-dlp records it from, which also tells the platform
+    pub link: String
+}
+
+/// What a platform's API tells about a stream besides finding it, for
+/// filters and metadata.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamDetails {
+    /// Scheduled length, if both ends are known
+    pub expected_duration: Option<Duration>,
+    pub premiere: bool,
+    pub concurrent_viewers: Option<String>,
+    /// `360` for 360°/VR streams
+    pub projection: Option<String>,
+    /// `3d` for stereoscopic streams
+    pub dimension: Option<String>
+}
+
+impl From<Item> for LiveStream {
+    fn from(item: Item) -> LiveStream {
+        LiveStream {
+            link: youtube::youtube_live_link(&item.id.video_id),
+            video_id: item.id.video_id,
+            channel_id: item.snippet.channel_id,
+            channel_title: item.snippet.channel_title,
+            title: item.snippet.title,
+            description: item.snippet.description,
+            upcoming: item.snippet.live_broadcast_content == "upcoming"
+        }
+    }
+}
+
+/// Scheduled length of the stream, if both ends are known.
+fn expected_duration(details: &LiveStreamingDetails) -> Option<Duration> {
+    let start = details.actual_start_time.or(details.scheduled_start_time)?;
+    let end = details.actual_end_time.or(details.scheduled_end_time)?;
+    (end - start).to_std().ok()
+}
+
+impl From<VideoItem> for StreamDetails {
+    fn from(video: VideoItem) -> StreamDetails {
+        let premiere = video.is_premiere();
+        let (projection, dimension) = video.content_details
+            .map(|content| (
+                content.projection.filter(|projection| projection != "rectangular"),
+                content.dimension.filter(|dimension| dimension != "2d")
+            ))
+            .unwrap_or_default();
+        let details = video.live_streaming_details;
+        StreamDetails {
+            expected_duration: details.as_ref().and_then(expected_duration),
+            premiere,
+            concurrent_viewers: details.and_then(|details| details.concurrent_viewers),
+            projection,
+            dimension
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::Duration
+};
+
+use chrono::{DateTime, Days, FixedOffset, Local, TimeZone, Utc};
+use serde::{Serialize, Serializer, Deserialize, Deserializer, de};
+
+/// Timezone used for date-based behavior: `local`, `utc`, or a fixed
+/// offset like `+02:00`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timezone {
+    Local,
+    Fixed(FixedOffset)
+}
+
+impl FromStr for Timezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Timezone, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "local" => Ok(Timezone::Local),
+            "utc" | "z" => Ok(Timezone::Fixed(FixedOffset::east_opt(0).unwrap())),
+            offset => offset
+                .parse::<FixedOffset>()
+                .map(Timezone::Fixed)
+                .map_err(|_| format!("expected local, utc or an offset like +02:00, got {}", s))
+        }
+    }
+}
+
+impl fmt::Display for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Timezone::Local => write!(f, "local"),
+            Timezone::Fixed(offset) => write!(f, "{}", offset)
+        }
+    }
+}
+
+impl Serialize for Timezone {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Timezone {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Timezone, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+fn next_midnight<Tz: TimeZone>(now: DateTime<Tz>) -> DateTime<Utc> {
+    let tomorrow = now.date_naive().checked_add_days(Days::new(1)).unwrap();
+    let midnight = tomorrow.and_hms_opt(0, 0, 0).unwrap();
+    match now.timezone().from_local_datetime(&midnight).earliest() {
+        Some(midnight) => midnight.with_timezone(&Utc),
+        // midnight skipped by a DST change, roll an hour later
+        None => (now.timezone().from_utc_datetime(&midnight) + chrono::Duration::hours(1)).with_timezone(&Utc)
+    }
+}
+
+impl Timezone {
+    /// `YYYY-MM-DD` of `now` in this timezone.
+    pub fn date_stamp(&self, now: DateTime<Utc>) -> String {
+        match self {
+            Timezone::Local => now.with_timezone(&Local).format("%Y-%m-%d").to_string(),
+            Timezone::Fixed(offset) => now.with_timezone(offset).format("%Y-%m-%d").to_string()
+        }
+    }
+
+    pub fn until_midnight(&self, now: DateTime<Utc>) -> Duration {
+        let midnight = match self {
+            Timezone::Local => next_midnight(now.with_timezone(&Local)),
+            Timezone::Fixed(offset) => next_midnight(now.with_timezone(offset))
+        };
+        (midnight - now).to_std().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn midnight_in_fixed_offset() {
+        let tz: Timezone = "+02:00".parse().unwrap();
+        let now = "2024-03-01T21:30:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(tz.date_stamp(now), "2024-03-01");
+        assert_eq!(tz.until_midnight(now), Duration::from_secs(30 * 60));
+
+        let now = "2024-03-01T22:30:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(tz.date_stamp(now), "2024-03-02");
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant}
+};
+
+use log::debug;
+use serde::Deserialize;
+
+use crate::{
+    error::{RecorderError, Result},
+    youtube
+};
+
+const TOKEN_URL: &str = "https://id.twitch.tv/oauth2/token";
+
+const STREAMS_URL: &str = "https://api.twitch.tv/helix/streams";
+
+/// Renews the app access token this long before Twitch says it expires.
+const TOKEN_MARGIN: Duration = Duration::from_secs(300);
+
+/// A registered Twitch application, for the Helix API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub client_id: String,
+    pub client_secret: String
+}
+
+/// The channel login in a `twitch.tv/<login>` URL, with or without scheme
+/// and `www.` or `m.`, or `None` if `channel` isn't one.
+pub fn login(channel: &str) -> Option<&str> {
+    let path = channel.split_once("://").map_or(channel, |(_, rest)| rest);
+    let (host, path) = path.split_once('/')?;
+    if !["twitch.tv", "www.twitch.tv", "m.twitch.tv"].contains(&host) {
+        return None;
+    }
+    let login = path.split(['/', '?', '#']).next().unwrap_or_default();
+    let valid = !login.is_empty() && login.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then_some(login)
+}
+
+pub fn channel_link(login: &str) -> String {
+    format!("https://www.twitch.tv/{}", login)
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64
+}
+
+/// A live stream, as Helix lists it.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Stream {
+    pub id: String,
+    pub user_login: String,
+    pub user_name: String,
+    pub title: String
+}
+
+#[derive(Deserialize)]
+struct StreamsResponse {
+    data: Vec<Stream>
+}
+
+/// The app access token and when to renew it, shared by every lookup.
+static TOKEN: Mutex<Option<(String, Instant)>> = Mutex::new(None);
+
+async fn app_token(credentials: &Credentials) -> Result<String> {
+    if let Some((token, renew_at)) = TOKEN.lock().unwrap_or_else(PoisonError::into_inner).as_ref() {
+        if Instant::now() < *renew_at {
+            return Ok(token.clone());
+        }
+    }
+    debug!("POST {}", TOKEN_URL);
+    let resp = youtube::client()
+        .post(TOKEN_URL)
+        .form(&[
+            ("client_id", credentials.client_id.as_str()),
+            ("client_secret", credentials.client_secret.as_str()),
+            ("grant_type", "client_credentials")
+        ])
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        return Err(RecorderError::Api(format!("Twitch turned down the app credentials with HTTP {}", resp.status())));
+    }
+    let token: TokenResponse = resp.json().await?;
+    let lifetime = Duration::from_secs(token.expires_in).saturating_sub(TOKEN_MARGIN);
+    *TOKEN.lock().unwrap_or_else(PoisonError::into_inner) = Some((token.access_token.clone(), Instant::now() + lifetime));
+    Ok(token.access_token)
+}
+
+fn parse_streams(body: &str) -> Result<Option<Stream>> {
+    let streams: StreamsResponse = serde_json::from_str(body)
+        .map_err(|err| RecorderError::Api(format!("unexpected Twitch streams response: {}", err)))?;
+    Ok(streams.data.into_iter().next())
+}
+
+/// The stream `login` has on air, if any. A token Twitch no longer
+/// accepts is dropped and the lookup made once more with a new one.
+pub async fn fetch_stream(credentials: &Credentials, login: &str) -> Result<Option<Stream>> {
+    let mut renewed = false;
+    loop {
+        let token = app_token(credentials).await?;
+        debug!("GET {}?user_login={}", STREAMS_URL, login);
+        let resp = youtube::client()
+            .get(STREAMS_URL)
+            .query(&[("user_login", login)])
+            .header("Client-Id", &credentials.client_id)
+            .bearer_auth(token)
+            .send()
+            .await?;
+        match resp.status() {
+            status if status.is_success() => return parse_streams(&resp.text().await?),
+            reqwest::StatusCode::UNAUTHORIZED if !renewed => {
+                TOKEN.lock().unwrap_or_else(PoisonError::into_inner).take();
+                renewed = true;
+            },
+            status => return Err(RecorderError::Api(format!("Twitch answered HTTP {} for {}", status, login)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_urls_and_streams_are_parsed() {
+        assert_eq!(login("https://www.twitch.tv/some_one"), Some("some_one"));
+        assert_eq!(login("twitch.tv/someone/videos?filter=archives"), Some("someone"));
+        assert_eq!(login("https://m.twitch.tv/someone"), Some("someone"));
+        assert_eq!(login("https://www.twitch.tv/"), None);
+        assert_eq!(login("https://www.youtube.com/@someone"), None);
+        assert_eq!(login("@someone"), None);
+
+        let live = r#"{"data":[{"id":"40952121085","user_id":"1","user_login":"someone","user_name":"Someone",
+            "type":"live","title":"Speedrun","started_at":"2024-01-01T00:00:00Z"}],"pagination":{}}"#;
+        let stream = parse_streams(live).unwrap().unwrap();
+        assert_eq!((stream.id.as_str(), stream.user_name.as_str()), ("40952121085", "Someone"));
+        assert_eq!(parse_streams(r#"{"data":[],"pagination":{}}"#).unwrap(), None);
+        assert!(parse_streams("{}").is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant}
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::stream::LiveStream;
+
+/// How often a channel with nothing live is searched for scheduled
+/// streams. Each search costs as much quota as a live search.
+pub const RECHECK: Duration = Duration::from_secs(30 * 60);
+
+/// Seconds yt-dlp waits between checks whether a scheduled stream began.
+pub const WAIT_RETRY_SECS: u64 = 15;
+
+/// A stream or premiere a channel has scheduled.
+#[derive(Debug, Clone)]
+pub struct Scheduled {
+    pub item: LiveStream,
+    pub starts_at: DateTime<Utc>
+}
+
+struct Watch {
+    checked: Instant,
+    streams: Vec<Scheduled>
+}
+
+/// The scheduled streams of every channel, with `--upcoming-lead`.
+#[derive(Default)]
+pub struct Upcoming {
+    channels: Mutex<HashMap<String, Watch>>
+}
+
+impl Upcoming {
+    /// Whether `channel` is due another search for scheduled streams.
+    pub fn stale(&self, channel: &str) -> bool {
+        self.channels.lock().unwrap_or_else(PoisonError::into_inner).get(channel).is_none_or(|watch| watch.checked.elapsed() >= RECHECK)
+    }
+
+    /// Remembers what `channel` has scheduled, as just found, returning
+    /// the streams it didn't list before.
+    pub fn set(&self, channel: &str, streams: Vec<Scheduled>) -> Vec<Scheduled> {
+        let mut channels = self.channels.lock().unwrap_or_else(PoisonError::into_inner);
+        let known: Vec<&String> = channels.get(channel).map_or(Vec::new(), |watch| watch.streams.iter().map(|stream| &stream.item.video_id).collect());
+        let new = streams.iter().filter(|stream| !known.contains(&&stream.item.video_id)).cloned().collect();
+        channels.insert(channel.to_owned(), Watch { checked: Instant::now(), streams });
+        new
+    }
+
+    /// When the next stream `channel` has scheduled starts.
+    pub fn next_start(&self, channel: &str) -> Option<DateTime<Utc>> {
+        self.channels.lock().unwrap_or_else(PoisonError::into_inner).get(channel)?.streams.iter().map(|stream| stream.starts_at).min()
+    }
+
+    /// Takes the streams of `channel` that start within `lead` of `now`.
+    /// Those more than `lead` overdue are dropped too: if they went live,
+    /// the live search finds them, and if not, they may never start.
+    pub fn take_due(&self, channel: &str, now: DateTime<Utc>, lead: Duration) -> Vec<Scheduled> {
+        let lead = chrono::Duration::from_std(lead).unwrap_or(chrono::Duration::zero());
+        let mut channels = self.channels.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(watch) = channels.get_mut(channel) else {
+            return Vec::new();
+        };
+        let (due, later): (Vec<Scheduled>, Vec<Scheduled>) = watch.streams.drain(..).partition(|stream| stream.starts_at - lead <= now);
+        watch.streams = later;
+        due.into_iter().filter(|stream| now < stream.starts_at + lead).collect()
+    }
+
+    /// How long from `now` until the lead time of the next stream `channel`
+    /// has scheduled begins, if that's before its next poll in `every`.
+    pub fn wake_in(&self, channel: &str, now: DateTime<Utc>, lead: Duration, every: Duration) -> Option<Duration> {
+        let lead = chrono::Duration::from_std(lead).unwrap_or(chrono::Duration::zero());
+        let wait = (self.next_start(channel)? - lead - now).to_std().ok()?;
+        (wait < every).then_some(wait)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduled(video_id: &str, starts_at: DateTime<Utc>) -> Scheduled {
+        let item = serde_json::from_value::<crate::youtube::Item>(serde_json::json!({
+            "id": { "videoId": video_id },
+            "snippet": {
+                "channelId": "UC1",
+                "title": "Premiere",
+                "description": "",
+                "channelTitle": "Channel",
+                "liveBroadcastContent": "upcoming"
+            }
+        })).unwrap().into();
+        Scheduled { item, starts_at }
+    }
+
+    #[test]
+    fn streams_come_due_within_their_lead_time() {
+        let now = Utc::now();
+        let lead = Duration::from_secs(600);
+        let upcoming = Upcoming::default();
+        assert!(upcoming.stale("@someone"));
+        let later = scheduled("later", now + chrono::Duration::hours(3));
+        assert_eq!(upcoming.set("@someone", vec![later.clone()]).len(), 1);
+        let new = upcoming.set("@someone", vec![
+            scheduled("soon", now + chrono::Duration::minutes(5)),
+            later,
+            scheduled("zombie", now - chrono::Duration::days(2))
+        ]);
+        assert_eq!(new.iter().map(|stream| stream.item.video_id.as_str()).collect::<Vec<_>>(), ["soon", "zombie"]);
+        assert!(!upcoming.stale("@someone"));
+        // already within its lead time, so taken by this poll
+        assert_eq!(upcoming.wake_in("@someone", now, lead, Duration::from_secs(60)), None);
+
+        let due = upcoming.take_due("@someone", now, lead);
+        assert_eq!(due.iter().map(|stream| stream.item.video_id.as_str()).collect::<Vec<_>>(), ["soon"]);
+        assert_eq!(upcoming.next_start("@someone"), Some(now + chrono::Duration::hours(3)));
+        assert_eq!(upcoming.wake_in("@someone", now, lead, Duration::from_secs(3600)), None);
+        assert_eq!(upcoming.wake_in("@someone", now, lead, Duration::from_secs(3 * 3600)), Some(Duration::from_secs(170 * 60)));
+        assert!(upcoming.take_due("@other", now, lead).is_empty());
+    }
+}
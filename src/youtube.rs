@@ -0,0 +1,1194 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io,
+    net::IpAddr,
+    path::Path,
+    sync::{
+        OnceLock,
+        atomic::{AtomicU64, Ordering}
+    },
+    time::Duration
+};
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use log::{Level, debug, info, log_enabled, warn};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+
+use crate::error::{RecorderError, Result};
+
+/// RFC 3339 timestamps as the API sends them, with any offset and
+/// fractional seconds, read into UTC. Ones that don't parse are left out
+/// rather than failing the whole response.
+mod rfc3339 {
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(time: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match time {
+            Some(time) => serializer.serialize_str(&time.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+            None => serializer.serialize_none()
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+        let raw = Option::<String>::deserialize(deserializer)?;
+        Ok(raw.and_then(|raw| DateTime::parse_from_rfc3339(&raw).ok()).map(|time| time.with_timezone(&Utc)))
+    }
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Default
+)]
+pub struct PageInfo {
+    #[serde(alias = "totalResults")]
+    pub total_results: i32,
+    #[serde(alias = "resultsPerPage")]
+    pub results_per_page: i32
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug
+)]
+pub struct UserRespItem {
+    pub kind: String,
+    pub etag: String,
+    pub id: String,
+    pub snippet: Option<ChannelSnippet>
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug
+)]
+pub struct ChannelSnippet {
+    pub title: String
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug
+)]
+pub struct UserResponse {
+    pub kind: String,
+    pub etag: String,
+    #[serde(alias = "pageInfo")]
+    pub page_info: PageInfo,
+    /// Left out of the response entirely when nothing matched
+    #[serde(default)]
+    pub items: Vec<UserRespItem>
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone
+)]
+pub struct Thumbnail {
+    pub url: String,
+    pub width: i32,
+    pub height: i32
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone
+)]
+pub struct Snippet {
+    #[serde(alias = "publishedAt", default, with = "rfc3339")]
+    pub published_at: Option<DateTime<Utc>>,
+    #[serde(alias = "channelId")]
+    pub channel_id: String,
+    pub title: String,
+    pub description: String,
+    #[serde(default)]
+    pub thumbnails: HashMap<String, Thumbnail>,
+    #[serde(alias = "channelTitle")]
+    pub channel_title: String,
+    #[serde(alias = "liveBroadcastContent")]
+    pub live_broadcast_content: String,
+    #[serde(alias = "publishTime", default, with = "rfc3339")]
+    pub publish_time: Option<DateTime<Utc>>
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone
+)]
+pub struct Id {
+    #[serde(default)]
+    pub kind: String,
+    #[serde(alias = "videoId")]
+    pub video_id: String
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone
+)]
+pub struct Item {
+    #[serde(default)]
+    pub kind: String,
+    #[serde(default)]
+    pub etag: String,
+    pub id: Id,
+    pub snippet: Snippet
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug
+)]
+pub struct YoutubeSearchListResponse {
+    #[serde(default)]
+    pub kind: String,
+    #[serde(default)]
+    pub etag: String,
+    #[serde(alias = "pageInfo", default)]
+    pub page_info: PageInfo,
+    #[serde(default)]
+    pub items: Vec<Item>
+}
+
+/// The parts of a search result the monitor actually reads. Fields left out
+/// of the response keep their defaults.
+pub const SEARCH_FIELDS: &str = "items(id/videoId,snippet(channelId,title,description,channelTitle,liveBroadcastContent))";
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug
+)]
+pub struct LiveStreamingDetails {
+    #[serde(alias = "actualStartTime", default, with = "rfc3339")]
+    pub actual_start_time: Option<DateTime<Utc>>,
+    #[serde(alias = "actualEndTime", default, with = "rfc3339")]
+    pub actual_end_time: Option<DateTime<Utc>>,
+    #[serde(alias = "scheduledStartTime", default, with = "rfc3339")]
+    pub scheduled_start_time: Option<DateTime<Utc>>,
+    #[serde(alias = "scheduledEndTime", default, with = "rfc3339")]
+    pub scheduled_end_time: Option<DateTime<Utc>>,
+    #[serde(alias = "concurrentViewers")]
+    pub concurrent_viewers: Option<String>
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug
+)]
+pub struct VideoItem {
+    pub kind: String,
+    pub etag: String,
+    pub id: String,
+    #[serde(alias = "liveStreamingDetails")]
+    pub live_streaming_details: Option<LiveStreamingDetails>,
+    #[serde(alias = "contentDetails")]
+    pub content_details: Option<VideoContentDetails>,
+    pub snippet: Option<Snippet>
+}
+
+impl VideoItem {
+    /// A premiere plays an uploaded video, so unlike a live stream its
+    /// length is known while it's on air.
+    pub fn is_premiere(&self) -> bool {
+        self.content_details
+            .as_ref()
+            .and_then(|content| content.duration.as_deref())
+            .is_some_and(|duration| duration != "P0D")
+    }
+}
+
+/// Format hints from `videos.list`. The API exposes 360° projection and
+/// 3D but nothing about HDR.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone
+)]
+pub struct VideoContentDetails {
+    /// `360` or `rectangular`
+    pub projection: Option<String>,
+    /// `2d` or `3d`
+    pub dimension: Option<String>,
+    /// `hd` or `sd`
+    pub definition: Option<String>,
+    /// ISO 8601 length, `P0D` while a live stream is on air
+    pub duration: Option<String>
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug
+)]
+pub struct VideoListResponse {
+    pub kind: String,
+    pub etag: String,
+    #[serde(default)]
+    pub items: Vec<VideoItem>
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug
+)]
+pub struct PlaylistItemContentDetails {
+    #[serde(alias = "videoId")]
+    pub video_id: String
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug
+)]
+pub struct PlaylistItem {
+    pub kind: String,
+    pub etag: String,
+    pub id: String,
+    #[serde(alias = "contentDetails")]
+    pub content_details: PlaylistItemContentDetails
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug
+)]
+pub struct PlaylistItemsResponse {
+    pub kind: String,
+    pub etag: String,
+    #[serde(alias = "pageInfo")]
+    pub page_info: PageInfo,
+    #[serde(default)]
+    pub items: Vec<PlaylistItem>
+}
+
+/// How live streams are found.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DetectionMethod {
+    /// `search.list`, 100 quota units per call
+    Search,
+    /// The channel's uploads playlist plus `videos.list`, 2 units per poll
+    Playlist,
+    /// The channel's `/live` page on youtube.com, no API key or quota needed
+    Scrape
+}
+
+impl DetectionMethod {
+    /// Quota units one lookup of a channel costs.
+    pub fn units_per_lookup(&self) -> u64 {
+        match self {
+            DetectionMethod::Search => 100,
+            DetectionMethod::Playlist => 2,
+            DetectionMethod::Scrape => 0
+        }
+    }
+
+    /// Shortest poll interval allowed without `--allow-aggressive-polling`.
+    pub fn default_interval_floor(&self) -> u64 {
+        match self {
+            DetectionMethod::Search => 5,
+            DetectionMethod::Playlist => 1,
+            // youtube.com rate-limits clients that fetch pages too often
+            DetectionMethod::Scrape => 30
+        }
+    }
+
+    /// Whether lookups go through the Data API, and so need a key.
+    pub fn uses_api(&self) -> bool {
+        *self != DetectionMethod::Scrape
+    }
+}
+
+/// Quota a project gets per day unless Google granted more.
+pub const DAILY_QUOTA: u64 = 10_000;
+
+/// Quota units a day spent polling `channels` channels every `interval`,
+/// not counting the few extra calls made for streams that are live.
+pub fn daily_quota_estimate(interval: Duration, channels: usize, method: DetectionMethod) -> u64 {
+    let ticks = 86_400 / interval.as_secs().max(1);
+    ticks * channels as u64 * method.units_per_lookup()
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug
+)]
+pub struct ChannelSectionContentDetails {
+    pub channels: Option<Vec<String>>
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug
+)]
+pub struct ChannelSection {
+    pub kind: String,
+    pub etag: String,
+    pub id: String,
+    #[serde(alias = "contentDetails")]
+    pub content_details: Option<ChannelSectionContentDetails>
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug
+)]
+pub struct ChannelSectionsResponse {
+    pub kind: String,
+    pub etag: String,
+    pub items: Vec<ChannelSection>
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug
+)]
+pub struct YoutubeErrorDetail {
+    pub message: Option<String>,
+    pub domain: Option<String>,
+    pub reason: Option<String>
+}
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug
+)]
+pub struct YoutubeError {
+    pub code: u16,
+    pub message: Option<String>,
+    pub errors: Option<Vec<YoutubeErrorDetail>>
+}
+
+/// Body of a failed Data API response.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug
+)]
+pub struct YoutubeErrorEnvelope {
+    pub error: YoutubeError
+}
+
+impl YoutubeErrorEnvelope {
+    /// The first `errors[].reason`, e.g. `quotaExceeded` or `keyInvalid`.
+    pub fn primary_reason(&self) -> Option<&str> {
+        self.error.errors.as_ref()?.iter().find_map(|detail| detail.reason.as_deref())
+    }
+}
+
+/// Optional parameters appended to the live search.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Default
+)]
+pub struct SearchOptions {
+    pub region_code: Option<String>,
+    pub language: Option<String>,
+    pub search_order: Option<SearchOrder>,
+    pub max_results: Option<u32>
+}
+
+/// Most results `search.list` returns per page.
+pub const MAX_RESULTS_LIMIT: u32 = 50;
+
+/// How `search.list` ranks the streams it returns.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SearchOrder {
+    Date,
+    Relevance,
+    #[value(name = "viewCount")]
+    ViewCount
+}
+
+impl SearchOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchOrder::Date => "date",
+            SearchOrder::Relevance => "relevance",
+            SearchOrder::ViewCount => "viewCount"
+        }
+    }
+}
+
+/// How a channel is looked up in `channels.list`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelFilter {
+    Username,
+    Handle,
+    Id
+}
+
+impl ChannelFilter {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChannelFilter::Username => "forUsername",
+            ChannelFilter::Handle => "forHandle",
+            ChannelFilter::Id => "id"
+        }
+    }
+}
+
+/// `endpoint` of the Data API with `params` as its query, every value
+/// encoded, so a channel name can't add parameters of its own.
+fn api_url<V: AsRef<str>>(endpoint: &str, params: &[(&str, V)]) -> String {
+    reqwest::Url::parse_with_params(&format!("{}/{}", api_base(), endpoint), params)
+        .expect("the API base is checked when the client is configured")
+        .into()
+}
+
+pub fn user_search(api_key: &str, filter: ChannelFilter, channel: &str) -> String {
+    api_url("channels", &[("key", api_key), (filter.as_str(), channel), ("part", "id,snippet")])
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventType {
+    Live,
+    Completed,
+    /// Scheduled streams and premieres that haven't started
+    Upcoming
+}
+
+impl EventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventType::Live => "live",
+            EventType::Completed => "completed",
+            EventType::Upcoming => "upcoming"
+        }
+    }
+}
+
+/// `url` restricted to `fields` with a partial response selector.
+pub fn with_fields(url: String, fields: &str) -> String {
+    match reqwest::Url::parse(&url) {
+        Ok(mut parsed) => {
+            parsed.query_pairs_mut().append_pair("fields", fields);
+            parsed.into()
+        },
+        Err(_) => url
+    }
+}
+
+pub fn video_search(api_key: &str, user_id: &str, event_type: EventType, options: &SearchOptions) -> String {
+    let mut params = vec![
+        ("part", "snippet".to_owned()),
+        ("channelId", user_id.to_owned()),
+        ("type", "video".to_owned()),
+        ("eventType", event_type.as_str().to_owned()),
+        ("key", api_key.to_owned())
+    ];
+    if let Some(region_code) = &options.region_code {
+        params.push(("regionCode", region_code.clone()));
+    }
+    if let Some(language) = &options.language {
+        params.push(("relevanceLanguage", language.clone()));
+    }
+    if let Some(order) = options.search_order {
+        params.push(("order", order.as_str().to_owned()));
+    }
+    if let Some(max_results) = options.max_results {
+        params.push(("maxResults", max_results.to_string()));
+    }
+    api_url("search", &params)
+}
+
+/// `url` with the API key replaced, safe to log.
+pub fn redact_url(url: &str) -> String {
+    match url.find("?key=").or_else(|| url.find("&key=")) {
+        Some(start) => {
+            let value = start + "?key=".len();
+            let end = url[value..].find('&').map(|end| value + end).unwrap_or(url.len());
+            format!("{}<redacted>{}", &url[..value], &url[end..])
+        },
+        None => url.to_owned()
+    }
+}
+
+pub fn video_details(api_key: &str, video_id: &str) -> String {
+    api_url("videos", &[("part", "liveStreamingDetails,contentDetails"), ("id", video_id), ("key", api_key)])
+}
+
+/// Sections of a channel page; "featured channels" sections list the channel
+/// IDs a creator links to.
+pub fn channel_sections(api_key: &str, channel_id: &str) -> String {
+    api_url("channelSections", &[("part", "contentDetails"), ("channelId", channel_id), ("key", api_key)])
+}
+
+/// How many of the newest uploads are checked per poll.
+const PLAYLIST_DEPTH: u32 = 5;
+
+/// Uploads playlist of a channel: its ID with `UC` replaced by `UU`.
+pub fn uploads_playlist(channel_id: &str) -> Option<String> {
+    channel_id.strip_prefix("UC").map(|rest| format!("UU{}", rest))
+}
+
+pub fn playlist_items(api_key: &str, playlist_id: &str) -> String {
+    let depth = PLAYLIST_DEPTH.to_string();
+    api_url("playlistItems", &[("part", "contentDetails"), ("playlistId", playlist_id), ("maxResults", &depth), ("key", api_key)])
+}
+
+pub fn videos_by_ids(api_key: &str, video_ids: &[String]) -> String {
+    api_url("videos", &[("part", "snippet,liveStreamingDetails"), ("id", &video_ids.join(",")), ("key", api_key)])
+}
+
+pub fn youtube_live_link(video_id: &String) -> String {
+    format!("https://www.youtube.com/watch?v={}", video_id)
+}
+
+/// The page youtube.com shows for what `channel` is streaming, given as an
+/// `@handle`, a channel ID or a custom name.
+pub fn live_page(channel: &str) -> String {
+    if channel.starts_with('@') {
+        format!("https://www.youtube.com/{}/live", channel)
+    } else if channel.len() == 24 && channel.starts_with("UC") {
+        format!("https://www.youtube.com/channel/{}/live", channel)
+    } else {
+        format!("https://www.youtube.com/c/{}/live", channel)
+    }
+}
+
+/// The JSON string value of the first `"key":"..."` in `page`.
+fn json_string(page: &str, key: &str) -> Option<String> {
+    let marker = format!("\"{}\":\"", key);
+    let start = page.find(&marker)? + marker.len();
+    let mut escaped = false;
+    let (end, _) = page[start..].char_indices().find(|&(_, c)| {
+        let closing = c == '"' && !escaped;
+        escaped = c == '\\' && !escaped;
+        closing
+    })?;
+    serde_json::from_str(&format!("\"{}\"", &page[start..start + end])).ok()
+}
+
+/// The stream on air in a channel's `/live` page, read from the player
+/// data embedded in it. Channels that aren't live get their channel page
+/// or an upcoming stream there instead.
+pub fn parse_live_page(page: &str) -> Option<Item> {
+    let details = &page[page.find("\"videoDetails\":")?..];
+    if !(details.contains("\"isLive\":true") || page.contains("\"isLiveNow\":true")) {
+        return None;
+    }
+    Some(Item {
+        kind: "youtube#searchResult".to_owned(),
+        etag: String::new(),
+        id: Id {
+            kind: "youtube#video".to_owned(),
+            video_id: json_string(details, "videoId")?
+        },
+        snippet: Snippet {
+            published_at: None,
+            channel_id: json_string(details, "channelId").unwrap_or_default(),
+            title: json_string(details, "title").unwrap_or_default(),
+            description: json_string(details, "shortDescription").unwrap_or_default(),
+            thumbnails: HashMap::new(),
+            channel_title: json_string(details, "author").unwrap_or_default(),
+            live_broadcast_content: "live".to_owned(),
+            publish_time: None
+        }
+    })
+}
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+pub const DEFAULT_API_BASE: &str = "https://www.googleapis.com/youtube/v3";
+
+static API_BASE: OnceLock<String> = OnceLock::new();
+
+/// Where the Data API is reached, without a trailing slash.
+fn api_base() -> &'static str {
+    API_BASE.get_or_init(|| DEFAULT_API_BASE.to_owned())
+}
+
+/// How the shared HTTP client binds and keeps connections around.
+#[derive(Debug, Default, Clone)]
+pub struct ClientOptions {
+    pub local_address: Option<IpAddr>,
+    /// How long an idle connection stays pooled, reqwest's default if unset
+    pub pool_idle_timeout: Option<Duration>,
+    /// Most idle connections pooled per host, unlimited if unset
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Data API mirror or proxy to use instead of [`DEFAULT_API_BASE`]
+    pub api_base_url: Option<String>
+}
+
+pub fn client_builder(options: &ClientOptions) -> reqwest::ClientBuilder {
+    let mut builder = reqwest::Client::builder().local_address(options.local_address);
+    if let Some(timeout) = options.pool_idle_timeout {
+        builder = builder.pool_idle_timeout(timeout);
+    }
+    if let Some(max) = options.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+    builder
+}
+
+/// Sets up the HTTP client used for every API call. Call once at startup,
+/// before any request.
+pub fn configure_client(options: &ClientOptions) -> Result<()> {
+    let client = client_builder(options).build()?;
+    if let Some(base) = &options.api_base_url {
+        API_BASE
+            .set(base.trim_end_matches('/').to_owned())
+            .map_err(|_| RecorderError::Config("API base URL configured twice".to_owned()))?;
+    }
+    CLIENT
+        .set(client)
+        .map_err(|_| RecorderError::Config("HTTP client configured twice".to_owned()))
+}
+
+/// The shared HTTP client, also used for other platforms' APIs.
+pub fn client() -> &'static reqwest::Client {
+    CLIENT.get_or_init(reqwest::Client::new)
+}
+
+/// Quota units spent by this process so far, estimated from the calls made.
+static QUOTA_USED: AtomicU64 = AtomicU64::new(0);
+
+/// Estimated quota cost of one call to `url`: searches cost 100 units,
+/// everything else this tool calls costs 1.
+pub fn quota_cost(url: &str) -> u64 {
+    if url.contains("/search?") { 100 } else { 1 }
+}
+
+pub fn quota_used() -> u64 {
+    QUOTA_USED.load(Ordering::Relaxed)
+}
+
+static API_CALLS: AtomicU64 = AtomicU64::new(0);
+static API_ERRORS: AtomicU64 = AtomicU64::new(0);
+static QUOTA_EXCEEDED: AtomicU64 = AtomicU64::new(0);
+
+/// API calls made by this process so far, for `/metrics`.
+#[derive(Debug, Clone, Copy)]
+pub struct ApiCounters {
+    pub calls: u64,
+    pub errors: u64,
+    pub quota_exceeded: u64
+}
+
+pub fn api_counters() -> ApiCounters {
+    ApiCounters {
+        calls: API_CALLS.load(Ordering::Relaxed),
+        errors: API_ERRORS.load(Ordering::Relaxed),
+        quota_exceeded: QUOTA_EXCEEDED.load(Ordering::Relaxed)
+    }
+}
+
+fn count_quota(url: &str) {
+    API_CALLS.fetch_add(1, Ordering::Relaxed);
+    QUOTA_USED.fetch_add(quota_cost(url), Ordering::Relaxed);
+}
+
+fn count_error(err: &RecorderError) {
+    API_ERRORS.fetch_add(1, Ordering::Relaxed);
+    if is_quota_exceeded(err) {
+        QUOTA_EXCEEDED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Attempts per API call when it keeps failing for a reason that may pass.
+const HTTP_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry of an API call, doubled for each one after.
+const HTTP_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Whether a failed call is worth repeating: network trouble, rate
+/// limiting and server errors, but not quota, key or decoding errors.
+fn is_retryable(err: &RecorderError) -> bool {
+    match err {
+        RecorderError::Http(err) => err.is_connect() || err.is_timeout() || err.is_body(),
+        RecorderError::ApiStatus { status, .. } => *status == 429 || *status >= 500,
+        _ => false
+    }
+}
+
+/// Whether a failed call was turned down for lack of quota.
+pub fn is_quota_exceeded(err: &RecorderError) -> bool {
+    matches!(err, RecorderError::ApiStatus { reason: Some(reason), .. } if reason == "quotaExceeded" || reason == "dailyLimitExceeded")
+}
+
+/// GETs `url` and decodes the JSON answer, retrying with exponential
+/// backoff while it fails in a way that may pass.
+async fn get_json<T: DeserializeOwned>(url: String) -> Result<T> {
+    let mut attempt = 1;
+    loop {
+        let result = get_json_once(&url).await;
+        if let Err(err) = &result {
+            count_error(err);
+        }
+        match result {
+            Err(err) if attempt < HTTP_ATTEMPTS && is_retryable(&err) => {
+                let delay = HTTP_BACKOFF * 2u32.pow(attempt - 1);
+                warn!("GET {} failed ({}), retry {}/{} in {:?}", redact_url(&url), err, attempt, HTTP_ATTEMPTS - 1, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            },
+            result => return result
+        }
+    }
+}
+
+async fn get_json_once<T: DeserializeOwned>(url: &str) -> Result<T> {
+    debug!("GET {}", redact_url(url));
+    count_quota(url);
+    // reqwest errors quote the URL, API key included; errors end up in the
+    // event stream and reports, so drop it
+    let resp = client().get(url).send().await.map_err(|err| err.without_url())?;
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(api_error(status.as_u16(), &resp.text().await.unwrap_or_default()));
+    }
+    Ok(resp.json::<T>().await.map_err(|err| err.without_url())?)
+}
+
+fn api_error(status: u16, body: &str) -> RecorderError {
+    let envelope = serde_json::from_str::<YoutubeErrorEnvelope>(body).ok();
+    RecorderError::ApiStatus {
+        status,
+        reason: envelope.as_ref().and_then(|envelope| envelope.primary_reason()).map(String::from),
+        message: envelope.and_then(|envelope| envelope.error.message)
+    }
+}
+
+/// Channels matching `channel` under `filter`, as `(id, title)` pairs.
+pub async fn fetch_channels(api_key: &str, filter: ChannelFilter, channel: &str) -> Result<Vec<(String, String)>> {
+    let user = get_json::<UserResponse>(user_search(api_key, filter, channel)).await?;
+    Ok(user.items
+        .into_iter()
+        .map(|item| (item.id, item.snippet.map(|snippet| snippet.title).unwrap_or_default()))
+        .collect())
+}
+
+/// Runs a search, asking only for `SEARCH_FIELDS` unless debug logging is on
+/// and the full response may be of interest.
+async fn search(url: String) -> Result<YoutubeSearchListResponse> {
+    if log_enabled!(Level::Debug) {
+        get_json(url).await
+    } else {
+        get_json(with_fields(url, SEARCH_FIELDS)).await
+    }
+}
+
+/// Drops results that aren't actually on air. The live search index is
+/// sometimes stale and still lists streams that already ended, with
+/// `liveBroadcastContent` set to `none`.
+pub fn only_live(mut search: YoutubeSearchListResponse) -> YoutubeSearchListResponse {
+    search.items.retain(|item| item.snippet.live_broadcast_content == "live");
+    search
+}
+
+pub async fn fetch_live(api_key: &str, channel_id: &str, options: &SearchOptions) -> Result<YoutubeSearchListResponse> {
+    let found = search(video_search(api_key, channel_id, EventType::Live, options)).await?;
+    let total = found.items.len();
+    let live = only_live(found);
+    if total > 0 && live.items.is_empty() {
+        info!("{}: stale search results, nothing live", channel_id);
+    }
+    Ok(live)
+}
+
+/// Streams of `channel_id` that already ended, most recent first.
+pub async fn fetch_completed(api_key: &str, channel_id: &str, options: &SearchOptions) -> Result<YoutubeSearchListResponse> {
+    search(video_search(api_key, channel_id, EventType::Completed, options)).await
+}
+
+/// Streams and premieres `channel_id` has scheduled.
+pub async fn fetch_upcoming(api_key: &str, channel_id: &str, options: &SearchOptions) -> Result<YoutubeSearchListResponse> {
+    search(video_search(api_key, channel_id, EventType::Upcoming, options)).await
+}
+
+/// Videos of `found` that are in the state `event_type` asks for, as search
+/// results so they go through the same path as `search.list` hits.
+pub fn playlist_matches(found: Vec<VideoItem>, event_type: EventType) -> Vec<Item> {
+    found
+        .into_iter()
+        .filter(|video| match event_type {
+            EventType::Live => video.snippet.as_ref().is_some_and(|snippet| snippet.live_broadcast_content == "live"),
+            EventType::Completed => video.snippet.as_ref().is_some_and(|snippet| snippet.live_broadcast_content == "none")
+                && video.live_streaming_details.as_ref().is_some_and(|details| details.actual_end_time.is_some()),
+            EventType::Upcoming => video.snippet.as_ref().is_some_and(|snippet| snippet.live_broadcast_content == "upcoming")
+        })
+        .filter_map(|video| Some(Item {
+            kind: "youtube#searchResult".to_owned(),
+            etag: video.etag,
+            id: Id {
+                kind: "youtube#video".to_owned(),
+                video_id: video.id
+            },
+            snippet: video.snippet?
+        }))
+        .collect()
+}
+
+/// The newest uploads of `channel_id`, with their broadcast state.
+pub async fn fetch_recent_uploads(api_key: &str, channel_id: &str) -> Result<Vec<VideoItem>> {
+    let playlist = uploads_playlist(channel_id)
+        .ok_or_else(|| RecorderError::Api(format!("{} is not a channel ID, can't find its uploads", channel_id)))?;
+    let uploads = get_json::<PlaylistItemsResponse>(playlist_items(api_key, &playlist)).await?;
+    let video_ids: Vec<String> = uploads.items.into_iter().map(|item| item.content_details.video_id).collect();
+    if video_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(get_json::<VideoListResponse>(videos_by_ids(api_key, &video_ids)).await?.items)
+}
+
+/// What `channel` streams right now according to its `/live` page, as
+/// search results. Costs no quota.
+pub async fn fetch_live_page(channel: &str, cookies: Option<&Path>) -> Result<YoutubeSearchListResponse> {
+    let url = live_page(channel);
+    debug!("GET {}", url);
+    let jar = match cookies {
+        Some(path) => fs::read_to_string(path).map_err(|err| io::Error::new(err.kind(), format!("cookies {}: {}", path.display(), err)))?,
+        None => String::new()
+    };
+    let resp = client()
+        .get(&url)
+        .header(reqwest::header::ACCEPT_LANGUAGE, "en")
+        .header(reqwest::header::COOKIE, cookie_header(&jar, Utc::now().timestamp()))
+        .send()
+        .await
+        .map_err(|err| err.without_url())?;
+    match resp.status() {
+        status if status.is_success() => Ok(search_results(parse_live_page(&resp.text().await?).into_iter().collect())),
+        reqwest::StatusCode::NOT_FOUND => Err(RecorderError::ChannelNotFound(channel.to_owned())),
+        status => Err(RecorderError::Api(format!("{} answered HTTP {}", url, status)))
+    }
+}
+
+/// The `Cookie` header for youtube.com from a Netscape cookie file, as
+/// yt-dlp reads it, leaving out cookies expired at `now`. Without a
+/// consent cookie of its own, one skipping the cookie consent interstitial
+/// served in some regions is added.
+fn cookie_header(jar: &str, now: i64) -> String {
+    let mut cookies: Vec<String> = jar
+        .lines()
+        .map(|line| line.strip_prefix("#HttpOnly_").unwrap_or(line))
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.trim_end_matches('\r').split('\t').collect();
+            let [domain, _, _, _, expires, name, value] = fields[..] else {
+                return None;
+            };
+            let domain = domain.trim_start_matches('.');
+            let for_youtube = domain == "youtube.com" || domain.ends_with(".youtube.com");
+            let expired = expires.parse::<i64>().is_ok_and(|expires| expires != 0 && expires < now);
+            (for_youtube && !expired).then(|| format!("{}={}", name, value))
+        })
+        .collect();
+    if !cookies.iter().any(|cookie| cookie.starts_with("SOCS=")) {
+        cookies.insert(0, "SOCS=CAI".to_owned());
+    }
+    cookies.join("; ")
+}
+
+fn search_results(items: Vec<Item>) -> YoutubeSearchListResponse {
+    YoutubeSearchListResponse {
+        kind: "youtube#searchListResponse".to_owned(),
+        etag: String::new(),
+        page_info: PageInfo::default(),
+        items
+    }
+}
+
+/// Live or finished streams of `channel_id` found with `method`. Scraping
+/// sends the YouTube `cookies` from a cookie file along, so members-only
+/// streams are found too.
+pub async fn fetch_streams(
+    api_key: &str,
+    channel_id: &str,
+    event_type: EventType,
+    method: DetectionMethod,
+    options: &SearchOptions,
+    cookies: Option<&Path>
+) -> Result<YoutubeSearchListResponse> {
+    match (method, event_type) {
+        (DetectionMethod::Search, EventType::Live) => fetch_live(api_key, channel_id, options).await,
+        (DetectionMethod::Search, EventType::Completed) => fetch_completed(api_key, channel_id, options).await,
+        (DetectionMethod::Search, EventType::Upcoming) => fetch_upcoming(api_key, channel_id, options).await,
+        (DetectionMethod::Playlist, event_type) => {
+            Ok(search_results(playlist_matches(fetch_recent_uploads(api_key, channel_id).await?, event_type)))
+        },
+        (DetectionMethod::Scrape, EventType::Live) => fetch_live_page(channel_id, cookies).await,
+        // the live page doesn't list finished or scheduled streams
+        (DetectionMethod::Scrape, EventType::Completed | EventType::Upcoming) => Ok(search_results(Vec::new()))
+    }
+}
+
+/// Channels `channel_id` features in its channel sections.
+pub async fn fetch_linked_channels(api_key: &str, channel_id: &str) -> Result<Vec<String>> {
+    let sections = get_json::<ChannelSectionsResponse>(channel_sections(api_key, channel_id)).await?;
+    Ok(sections.items
+        .into_iter()
+        .filter_map(|section| section.content_details?.channels)
+        .flatten()
+        .filter(|linked| linked != channel_id)
+        .collect())
+}
+
+/// Best-effort `videos.list` lookup; `None` on any failure, or without a
+/// key when detecting by scraping.
+pub async fn fetch_video(api_key: &str, video_id: &str) -> Option<VideoItem> {
+    if api_key.is_empty() {
+        return None;
+    }
+    get_json::<VideoListResponse>(video_details(api_key, video_id))
+        .await
+        .ok()?
+        .items
+        .into_iter()
+        .next()
+}
+
+pub async fn fetch_live_details(api_key: &str, video_id: &str) -> Option<LiveStreamingDetails> {
+    fetch_video(api_key, video_id).await?.live_streaming_details
+}
+
+pub async fn fetch_concurrent_viewers(api_key: &str, video_id: &str) -> Option<String> {
+    fetch_live_details(api_key, video_id).await?.concurrent_viewers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn live_page_yields_the_stream_on_air_only() {
+        let live = r#"<html><script>var ytInitialPlayerResponse = {"videoDetails":{"videoId":"abcdefghijk","title":"Q\u0026A \"live\"","channelId":"UCxxxxxxxxxxxxxxxxxxxxxx","shortDescription":"Ask away","isLiveContent":true,"author":"Some Channel","isLive":true}};</script>"#;
+        let item = parse_live_page(live).unwrap();
+        assert_eq!(item.id.video_id, "abcdefghijk");
+        assert_eq!(item.snippet.title, "Q&A \"live\"");
+        assert_eq!(item.snippet.channel_title, "Some Channel");
+        assert_eq!(item.snippet.channel_id, "UCxxxxxxxxxxxxxxxxxxxxxx");
+
+        let upcoming = live.replace(r#","isLive":true"#, r#","isUpcoming":true"#);
+        assert!(parse_live_page(&upcoming).is_none());
+        assert!(parse_live_page("<html>channel page</html>").is_none());
+        assert_eq!(live_page("@handle"), "https://www.youtube.com/@handle/live");
+        assert_eq!(live_page("UCxxxxxxxxxxxxxxxxxxxxxx"), "https://www.youtube.com/channel/UCxxxxxxxxxxxxxxxxxxxxxx/live");
+    }
+
+    #[test]
+    fn only_passing_failures_are_retried() {
+        assert!(is_retryable(&api_error(503, "")));
+        assert!(is_retryable(&api_error(429, "")));
+        assert!(!is_retryable(&api_error(403, r#"{"error":{"errors":[{"reason":"quotaExceeded"}]}}"#)));
+        let denied = |reason: &str| RecorderError::ApiStatus { status: 403, reason: Some(reason.to_owned()), message: None };
+        assert!(is_quota_exceeded(&denied("quotaExceeded")));
+        assert!(!is_quota_exceeded(&denied("keyInvalid")));
+        assert!(!is_retryable(&RecorderError::Api("no uploads".to_owned())));
+    }
+
+    #[test]
+    fn quota_estimate_scales_with_interval_and_method() {
+        assert_eq!(daily_quota_estimate(Duration::from_secs(10), 1, DetectionMethod::Search), 864_000);
+        assert_eq!(daily_quota_estimate(Duration::from_secs(600), 3, DetectionMethod::Playlist), 864);
+    }
+
+    #[test]
+    fn api_timestamps_parse_into_utc() {
+        let details: LiveStreamingDetails = serde_json::from_str(r#"{
+            "actualStartTime": "2024-03-01T20:00:05.123456Z",
+            "scheduledStartTime": "2024-03-01T21:00:00+01:00",
+            "scheduledEndTime": "not a time"
+        }"#).unwrap();
+        let start = details.actual_start_time.unwrap();
+        assert_eq!(start.to_rfc3339(), "2024-03-01T20:00:05.123456+00:00");
+        assert_eq!(details.scheduled_start_time.unwrap().to_rfc3339(), "2024-03-01T20:00:00+00:00");
+        assert_eq!(details.actual_end_time, None);
+        assert_eq!(details.scheduled_end_time, None);
+
+        let json = serde_json::to_value(&details).unwrap();
+        assert_eq!(json["actual_start_time"], "2024-03-01T20:00:05.123456Z");
+        assert_eq!(json["actual_end_time"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn search_url_with_locale_is_redacted() {
+        let options = SearchOptions {
+            region_code: Some("HU".to_owned()),
+            language: Some("hu".to_owned()),
+            ..SearchOptions::default()
+        };
+        let url = video_search("SECRET", "UC1", EventType::Live, &options);
+        assert!(url.ends_with("&key=SECRET&regionCode=HU&relevanceLanguage=hu"));
+        assert_eq!(
+            redact_url(&url),
+            "https://www.googleapis.com/youtube/v3/search?part=snippet&channelId=UC1&type=video&eventType=live&key=<redacted>&regionCode=HU&relevanceLanguage=hu"
+        );
+    }
+
+    #[test]
+    fn query_values_are_encoded() {
+        let url = user_search("KEY", ChannelFilter::Handle, "@a&part=x #1");
+        assert_eq!(url, "https://www.googleapis.com/youtube/v3/channels?key=KEY&forHandle=%40a%26part%3Dx+%231&part=id%2Csnippet");
+        let url = with_fields(video_details("KEY", "abc"), "items(id)");
+        assert!(url.ends_with("&id=abc&key=KEY&fields=items%28id%29"));
+    }
+
+    #[test]
+    fn search_order_and_max_results_are_appended() {
+        let options = SearchOptions {
+            search_order: Some(SearchOrder::ViewCount),
+            max_results: Some(50),
+            ..SearchOptions::default()
+        };
+        let url = video_search("KEY", "UC1", EventType::Live, &options);
+        assert!(url.ends_with("&key=KEY&order=viewCount&maxResults=50"));
+        let url = video_search("KEY", "UC1", EventType::Live, &SearchOptions::default());
+        assert!(url.ends_with("&key=KEY"));
+    }
+
+    #[test]
+    fn partial_search_response_deserializes() {
+        let body = r#"{
+            "items": [{
+                "id": { "videoId": "abc" },
+                "snippet": {
+                    "channelId": "UC1",
+                    "title": "Live now",
+                    "description": "",
+                    "channelTitle": "Channel",
+                    "liveBroadcastContent": "live"
+                }
+            }]
+        }"#;
+        let search: YoutubeSearchListResponse = serde_json::from_str(body).unwrap();
+        assert_eq!(search.items[0].id.video_id, "abc");
+        assert_eq!(search.items[0].snippet.live_broadcast_content, "live");
+    }
+
+    /// A live search answered from a stale index: the stream already ended.
+    const STALE_SEARCH: &str = r#"{
+        "kind": "youtube#searchListResponse",
+        "etag": "abc",
+        "pageInfo": { "totalResults": 1, "resultsPerPage": 5 },
+        "items": [{
+            "kind": "youtube#searchResult",
+            "etag": "def",
+            "id": { "kind": "youtube#video", "videoId": "ended" },
+            "snippet": {
+                "publishedAt": "2024-03-01T18:00:00Z",
+                "channelId": "UC1",
+                "title": "Yesterday's stream",
+                "description": "",
+                "thumbnails": {},
+                "channelTitle": "Channel",
+                "liveBroadcastContent": "none",
+                "publishTime": "2024-03-01T18:00:00Z"
+            }
+        }]
+    }"#;
+
+    #[test]
+    fn stale_live_results_are_dropped() {
+        let search: YoutubeSearchListResponse = serde_json::from_str(STALE_SEARCH).unwrap();
+        assert_eq!(search.items.len(), 1);
+        assert!(only_live(search).items.is_empty());
+    }
+
+    #[test]
+    fn playlist_uploads_are_checked_for_liveness() {
+        assert_eq!(uploads_playlist("UCabc").as_deref(), Some("UUabc"));
+
+        let body = r#"{
+            "kind": "youtube#videoListResponse",
+            "etag": "abc",
+            "items": [
+                {
+                    "kind": "youtube#video",
+                    "etag": "1",
+                    "id": "live",
+                    "snippet": {
+                        "channelId": "UC1",
+                        "title": "On air",
+                        "description": "",
+                        "channelTitle": "Channel",
+                        "liveBroadcastContent": "live"
+                    },
+                    "liveStreamingDetails": { "actualStartTime": "2024-03-01T20:00:00Z" }
+                },
+                {
+                    "kind": "youtube#video",
+                    "etag": "2",
+                    "id": "upload",
+                    "snippet": {
+                        "channelId": "UC1",
+                        "title": "Regular upload",
+                        "description": "",
+                        "channelTitle": "Channel",
+                        "liveBroadcastContent": "none"
+                    }
+                }
+            ]
+        }"#;
+        let found = serde_json::from_str::<VideoListResponse>(body).unwrap().items;
+        let live = playlist_matches(found, EventType::Live);
+        assert_eq!(live.len(), 1);
+        assert_eq!(live[0].id.video_id, "live");
+        assert_eq!(quota_cost(&playlist_items("k", "UU1")), 1);
+    }
+
+    #[test]
+    fn quota_error_reason_is_extracted() {
+        let body = r#"{
+            "error": {
+                "code": 403,
+                "message": "The request cannot be completed because you have exceeded your quota.",
+                "errors": [{
+                    "message": "The request cannot be completed because you have exceeded your quota.",
+                    "domain": "youtube.quota",
+                    "reason": "quotaExceeded"
+                }]
+            }
+        }"#;
+        assert_eq!(
+            api_error(403, body).to_string(),
+            "YouTube API returned HTTP 403 quotaExceeded: The request cannot be completed because you have exceeded your quota."
+        );
+        assert_eq!(api_error(502, "<html>").to_string(), "YouTube API returned HTTP 502 (no reason): no details");
+    }
+
+    #[test]
+    fn youtube_cookies_are_sent_with_page_requests() {
+        let jar = "# Netscape HTTP Cookie File\n\
+            .youtube.com\tTRUE\t/\tTRUE\t0\tPREF\tf6=40000000\n\
+            #HttpOnly_.youtube.com\tTRUE\t/\tTRUE\t1900000000\t__Secure-3PSID\tsecret\n\
+            .youtube.com\tTRUE\t/\tTRUE\t1000\tOLD\tgone\n\
+            .google.com\tTRUE\t/\tTRUE\t1900000000\tSID\tother\n\
+            broken line\n";
+        assert_eq!(cookie_header(jar, 1_700_000_000), "SOCS=CAI; PREF=f6=40000000; __Secure-3PSID=secret");
+        assert_eq!(cookie_header(".youtube.com\tTRUE\t/\tTRUE\t0\tSOCS\tCAE", 0), "SOCS=CAE");
+        assert_eq!(cookie_header("", 0), "SOCS=CAI");
+    }
+}
@@ -0,0 +1,1627 @@
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, BufReader},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{
+        Arc,
+        Mutex,
+        PoisonError,
+        atomic::{AtomicBool, Ordering}
+    },
+    thread,
+    time::{Duration, Instant, SystemTime}
+};
+
+use chrono::Utc;
+use clap::ValueEnum;
+use log::{debug, info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    error::{RecorderError, Result},
+    merge,
+    timezone::Timezone
+};
+
+/// What to do when a recording for the same video is already on disk.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnExisting {
+    /// Don't record again if a finished file is present
+    Skip,
+    /// Replace the existing file
+    Overwrite,
+    /// Continue a partially downloaded file
+    Resume,
+    /// Record into a new, numbered file next to the existing one
+    Suffix
+}
+
+impl OnExisting {
+    pub fn ytdlp_args(&self) -> Vec<String> {
+        match self {
+            OnExisting::Skip | OnExisting::Suffix => vec!["--no-overwrites".to_owned()],
+            OnExisting::Overwrite => vec!["--force-overwrites".to_owned(), "--no-continue".to_owned()],
+            OnExisting::Resume => vec!["--continue".to_owned()]
+        }
+    }
+}
+
+const SIDECAR_EXTENSIONS: &[&str] = &[".json", ".description", ".jpg", ".png", ".webp", ".nfo"];
+
+/// Caption formats yt-dlp writes with `--write-subs` and `--write-auto-subs`,
+/// and the live chat replay it saves as a caption track.
+const SUBTITLE_EXTENSIONS: &[&str] = &[".vtt", ".srt", ".ass", ".ttml", ".srv1", ".srv2", ".srv3", ".json3", ".live_chat.json"];
+
+/// Whether yt-dlp is still writing `name`: a `.part` download, one of its
+/// `.part-FragN` fragments, or the `.ytdl` state of a fragmented download.
+/// Titles like "Live.party" don't count.
+pub fn is_partial(name: &str) -> bool {
+    name.ends_with(".part") || name.contains(".part-Frag") || name.ends_with(".ytdl")
+}
+
+pub fn is_subtitle(name: &str) -> bool {
+    SUBTITLE_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+}
+
+pub fn is_sidecar(name: &str) -> bool {
+    SIDECAR_EXTENSIONS.iter().any(|ext| name.ends_with(ext)) || is_subtitle(name)
+}
+
+/// The caption track yt-dlp saves a stream's live chat as.
+const LIVE_CHAT: &str = "live_chat";
+
+/// Checks a `--sub-langs` entry loosely: a language code, or one of
+/// yt-dlp's patterns like `en.*`, `all` or `-live_chat`.
+pub fn parse_sub_lang(lang: &str) -> std::result::Result<String, String> {
+    let valid = !lang.is_empty() && lang.chars().all(|c| c.is_ascii_alphanumeric() || "-_.*".contains(c));
+    if valid {
+        Ok(lang.to_owned())
+    } else {
+        Err(format!("{} is not a subtitle language", lang))
+    }
+}
+
+fn file_name(path: &Path) -> &str {
+    path.file_name().and_then(|name| name.to_str()).unwrap_or("")
+}
+
+fn files_in(dir: &Path) -> Vec<PathBuf> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect(),
+        Err(_) => Vec::new()
+    }
+}
+
+/// Finished recordings of `video_id` in `dir`, matched by the `[id]` part of
+/// yt-dlp's default `%(title)s [%(id)s].%(ext)s` template. Partial downloads
+/// and metadata files are ignored.
+pub fn existing_recordings(dir: &Path, video_id: &String) -> Vec<PathBuf> {
+    let needle = format!("[{}]", video_id);
+    files_in(dir)
+        .into_iter()
+        .filter(|path| {
+            let name = file_name(path);
+            name.contains(&needle) && !is_partial(name) && !is_sidecar(name)
+        })
+        .collect()
+}
+
+/// Finished recordings of `video_id` in `dir` last written to at `since`
+/// or later.
+pub fn recordings_since(dir: &Path, video_id: &String, since: SystemTime) -> Vec<PathBuf> {
+    existing_recordings(dir, video_id)
+        .into_iter()
+        .filter(|path| modified(path).is_some_and(|modified| modified >= since))
+        .collect()
+}
+
+/// Bytes written for `video_id` in `dir` so far, partial files included.
+pub fn recorded_bytes(dir: &Path, video_id: &str) -> u64 {
+    let needle = format!("[{}]", video_id);
+    files_in(dir)
+        .into_iter()
+        .filter(|path| file_name(path).contains(&needle) && !is_sidecar(file_name(path)))
+        .filter_map(|path| fs::metadata(path).ok())
+        .map(|meta| meta.len())
+        .sum()
+}
+
+/// Directory in the output directory that `capture_extras` puts the live
+/// chat, description and thumbnail of `video_id` in.
+pub fn extras_dir(video_id: &str) -> String {
+    format!("{}.extras", video_id)
+}
+
+/// Caption files of `video_id` in `dir` and its [`extras_dir`], in name order.
+pub fn subtitle_files(dir: &Path, video_id: &str) -> Vec<PathBuf> {
+    let needle = format!("[{}]", video_id);
+    let mut subtitles: Vec<PathBuf> = files_in(dir)
+        .into_iter()
+        .chain(files_in(&dir.join(extras_dir(video_id))))
+        .filter(|path| {
+            let name = file_name(path);
+            name.contains(&needle) && is_subtitle(name) && !is_partial(name)
+        })
+        .collect();
+    subtitles.sort();
+    subtitles
+}
+
+/// Whether `video_id` is listed in a yt-dlp `--download-archive` file.
+pub fn in_download_archive(archive: &Path, video_id: &str) -> bool {
+    fs::read_to_string(archive)
+        .map(|archive| archive.lines().any(|line| line.split_whitespace().nth(1) == Some(video_id)))
+        .unwrap_or(false)
+}
+
+/// How to treat `.part` files left behind by an earlier crash.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RecoverParts {
+    /// Keep them so yt-dlp continues into them
+    Resume,
+    /// Delete them
+    Clean
+}
+
+/// Partial download files in `dir`, optionally only those of `video_id`.
+pub fn partial_files(dir: &Path, video_id: Option<&String>) -> Vec<PathBuf> {
+    files_in(dir)
+        .into_iter()
+        .filter(|path| {
+            let name = file_name(path);
+            is_partial(name) && video_id.is_none_or(|id| name.contains(&format!("[{}]", id)))
+        })
+        .collect()
+}
+
+pub fn recover_parts(dir: &Path, mode: RecoverParts) -> io::Result<()> {
+    for part in partial_files(dir, None) {
+        match mode {
+            RecoverParts::Resume => info!("Found partial recording {}, it will be resumed", part.display()),
+            RecoverParts::Clean => {
+                info!("Removing partial recording {}", part.display());
+                fs::remove_file(&part)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// After a clean yt-dlp exit, renames any non-empty `.part` file of
+/// `video_id` into its final name.
+pub fn finalize_parts(dir: &Path, video_id: &String) -> io::Result<Vec<PathBuf>> {
+    let mut finalized = Vec::new();
+
+    for part in partial_files(dir, Some(video_id)) {
+        let name = file_name(&part);
+        let target = match name.strip_suffix(".part") {
+            Some(target) => dir.join(target),
+            None => continue
+        };
+        if target.exists() || fs::metadata(&part)?.len() == 0 {
+            continue;
+        }
+        fs::rename(&part, &target)?;
+        finalized.push(target);
+    }
+    Ok(finalized)
+}
+
+pub fn default_program() -> String {
+    let mut yt_dlp: String = "yt-dlp".to_owned();
+
+    if cfg!(windows) {
+        yt_dlp.push_str(".exe");
+    }
+    yt_dlp
+}
+
+/// Where yt-dlp gets the cookies for members-only and paywalled streams.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cookies {
+    /// Netscape cookie file, passed as `--cookies`
+    File(PathBuf),
+    /// Browser to export from on every start, passed as `--cookies-from-browser`
+    Browser(String)
+}
+
+impl Cookies {
+    pub fn ytdlp_args(&self) -> Vec<String> {
+        match self {
+            Cookies::File(path) => vec!["--cookies".to_owned(), path.to_string_lossy().into_owned()],
+            Cookies::Browser(browser) => vec!["--cookies-from-browser".to_owned(), browser.clone()]
+        }
+    }
+}
+
+/// yt-dlp errors meaning the login session behind the cookies has expired.
+const AUTH_EXPIRED_MARKERS: &[&str] = &[
+    "cookies are no longer valid",
+    "sign in to confirm",
+    "join this channel to get access",
+    "members-only content",
+    "this video is available to this channel's members"
+];
+
+pub fn is_auth_expired(line: &str) -> bool {
+    let line = line.to_lowercase();
+    AUTH_EXPIRED_MARKERS.iter().any(|marker| line.contains(marker))
+}
+
+/// How yt-dlp and the ffmpeg it runs report a full disk.
+const DISK_FULL_MARKERS: &[&str] = &["no space left on device", "[errno 28]", "enospc", "disk quota exceeded"];
+
+pub fn is_disk_full(line: &str) -> bool {
+    let line = line.to_lowercase();
+    DISK_FULL_MARKERS.iter().any(|marker| line.contains(marker))
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordOptions {
+    pub program: String,
+    pub on_existing: OnExisting,
+    pub output_dir: PathBuf,
+    /// Start a new file at midnight in this timezone
+    pub roll_daily: Option<Timezone>,
+    /// Save description, info.json and thumbnail next to the recording
+    pub write_metadata_files: bool,
+    /// Save YouTube's auto-generated captions next to the recording
+    pub write_auto_subs: bool,
+    /// Caption languages to save, passed as `--sub-langs`
+    pub sub_langs: Vec<String>,
+    /// Save the live chat, description and thumbnail into [`extras_dir`]
+    pub capture_extras: bool,
+    /// Write straight into the final file instead of a `.part`
+    pub no_part: bool,
+    /// yt-dlp config file, passed as `--config-location`
+    pub config_location: Option<PathBuf>,
+    pub extra_args: Vec<String>,
+    /// yt-dlp `--download-archive` file, so finished downloads aren't repeated
+    pub download_archive: Option<PathBuf>,
+    /// Restart yt-dlp when its output hasn't grown for this long
+    pub stall_timeout: Option<Duration>,
+    pub cookies: Option<Cookies>,
+    /// How often to check for fresh cookies and restart yt-dlp with them
+    pub cookie_refresh: Option<Duration>,
+    /// Local address to bind to, passed as `--source-address`
+    pub source_address: Option<IpAddr>,
+    pub force_ipv4: bool,
+    pub force_ipv6: bool,
+    /// Extra environment for yt-dlp and the commands it `--exec`s
+    pub env: Vec<(String, String)>,
+    /// Sort formats so HDR variants win, passed as `-S hdr`
+    pub prefer_hdr: bool,
+    /// Download rate cap, passed as `--limit-rate`
+    pub limit_rate: Option<String>,
+    /// Have yt-dlp print progress as JSON lines, see [`Progress`]
+    pub structured_progress: bool,
+    /// Write containers that stay playable when cut off, see [`CRASH_SAFE_ARGS`]
+    pub crash_safe: bool,
+    /// Stop for good after recording this long
+    pub max_duration: Option<Duration>,
+    /// Only accept separate video and audio merged together, see [`check_merged`]
+    pub require_merge: bool,
+    /// Pick the format from yt-dlp's listing, see [`pick_format`]
+    pub smart_format: bool,
+    /// Highest resolution `smart_format` picks when there's a choice
+    pub max_height: Option<u32>,
+    /// yt-dlp format selector, passed as `-f`
+    pub format: Option<String>,
+    /// yt-dlp filename stem from [`parse_output_template`], instead of
+    /// `%(title)s [%(id)s]`
+    pub output_template: Option<String>,
+    /// Seconds between checks whether a scheduled stream has begun,
+    /// passed as `--wait-for-video`
+    pub wait_for_video: Option<u64>
+}
+
+/// Parses a `--channel-max-height CHANNEL=N` argument.
+pub fn parse_channel_height(arg: &str) -> std::result::Result<(String, u32), String> {
+    let (channel, height) = arg.split_once('=').ok_or_else(|| format!("expected CHANNEL=N, got {}", arg))?;
+    match height.parse() {
+        Ok(height) if height > 0 => Ok((channel.to_owned(), height)),
+        _ => Err(format!("height must be a positive whole number, got {}", height))
+    }
+}
+
+/// One entry of yt-dlp's format listing.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Format {
+    pub format_id: String,
+    pub height: Option<u32>,
+    pub vcodec: Option<String>,
+    pub acodec: Option<String>,
+    /// Total bitrate in kbit/s, breaking ties between formats of one height
+    pub tbr: Option<f64>
+}
+
+impl Format {
+    fn has_video(&self) -> bool {
+        self.height.is_some() && self.vcodec.as_deref() != Some("none")
+    }
+
+    fn has_audio(&self) -> bool {
+        self.acodec.as_deref() != Some("none")
+    }
+
+    /// `-f` selector for this format, adding the best audio to a video-only one.
+    pub fn selector(&self) -> String {
+        if self.has_audio() {
+            self.format_id.clone()
+        } else {
+            format!("{}+bestaudio", self.format_id)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Listing {
+    #[serde(default)]
+    formats: Vec<Format>
+}
+
+/// The video format to record: the tallest up to `max_height`, or the
+/// shortest there is when none fits, with the bitrate deciding between
+/// formats of one height. `video_only` leaves out pre-muxed formats.
+pub fn pick_format(formats: &[Format], max_height: Option<u32>, video_only: bool) -> Option<&Format> {
+    let rank = |format: &&Format| (format.height.unwrap_or(0), (format.tbr.unwrap_or(0.0) * 1000.0) as u64);
+    let video: Vec<&Format> = formats
+        .iter()
+        .filter(|format| format.has_video() && !(video_only && format.has_audio()))
+        .collect();
+    let fitting = video
+        .iter()
+        .copied()
+        .filter(|format| max_height.is_none_or(|max| format.height.unwrap_or(0) <= max))
+        .max_by_key(rank);
+    fitting.or_else(|| {
+        let shortest = video.iter().filter_map(|format| format.height).min()?;
+        video.iter().copied().filter(|format| format.height == Some(shortest)).max_by_key(rank)
+    })
+}
+
+/// How long a format listing is reused, sparing a yt-dlp run for every
+/// restart of the same recording.
+const FORMAT_CACHE_TTL: Duration = Duration::from_secs(300);
+
+static FORMAT_CACHE: Mutex<BTreeMap<String, (Instant, Vec<Format>)>> = Mutex::new(BTreeMap::new());
+
+/// Formats yt-dlp lists for `link`, from the cache while fresh.
+fn list_formats(options: &RecordOptions, link: &str) -> io::Result<Vec<Format>> {
+    if let Some((listed, formats)) = FORMAT_CACHE.lock().unwrap_or_else(PoisonError::into_inner).get(link) {
+        if listed.elapsed() < FORMAT_CACHE_TTL {
+            return Ok(formats.clone());
+        }
+    }
+    let output = Command::new(&options.program)
+        .args(connection_args(options))
+        .args(["-J", "--skip-download", "--no-warnings", link])
+        .envs(options.env.iter().cloned())
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("{} exited with {}", options.program, output.status)));
+    }
+    let listing: Listing = serde_json::from_slice(&output.stdout)?;
+
+    let mut cache = FORMAT_CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    cache.retain(|_, (listed, _)| listed.elapsed() < FORMAT_CACHE_TTL);
+    cache.insert(link.to_owned(), (Instant::now(), listing.formats.clone()));
+    Ok(listing.formats)
+}
+
+/// `options` with the format picked from yt-dlp's listing of `link`, with
+/// `smart_format`. Without a usable listing yt-dlp picks as usual.
+fn with_smart_format(options: &RecordOptions, video_id: &String, link: &str) -> RecordOptions {
+    let mut options = options.clone();
+    if !options.smart_format {
+        return options;
+    }
+    let formats = match list_formats(&options, link) {
+        Ok(formats) => formats,
+        Err(err) => {
+            warn!("Listing formats of {} failed, leaving the choice to yt-dlp: {}", video_id, err);
+            return options;
+        }
+    };
+    match pick_format(&formats, options.max_height, options.require_merge) {
+        Some(format) => {
+            let height = format.height.unwrap_or(0);
+            match options.max_height {
+                Some(max) if height > max => info!("{} has nothing up to {}p, recording {}p format {}", video_id, max, height, format.format_id),
+                _ => info!("Recording {} in {}p format {}", video_id, height, format.format_id)
+            }
+            options.format = Some(format.selector());
+        },
+        None => warn!("No video formats listed for {}, leaving the choice to yt-dlp", video_id)
+    }
+    options
+}
+
+/// What yt-dlp reports about a page, as far as live detection cares.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Probe {
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub uploader: Option<String>,
+    #[serde(default)]
+    pub is_live: Option<bool>,
+    #[serde(default)]
+    pub live_status: Option<String>,
+    /// For pages yt-dlp treats as playlists
+    #[serde(default)]
+    pub entries: Vec<Probe>
+}
+
+impl Probe {
+    fn is_on_air(&self) -> bool {
+        self.is_live == Some(true) || self.live_status.as_deref() == Some("is_live")
+    }
+}
+
+/// Longest a probe may take before the page counts as offline.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The stream on air according to yt-dlp's JSON dump of a page, or of the
+/// first entry of a playlist page.
+pub fn parse_probe(json: &[u8]) -> Option<Probe> {
+    let probe: Probe = serde_json::from_slice(json).ok()?;
+    let probe = match probe.entries.first() {
+        Some(first) => first.clone(),
+        None => probe
+    };
+    probe.is_on_air().then_some(probe)
+}
+
+/// Asks yt-dlp what's on air at `link`, without downloading anything.
+/// Offline pages make yt-dlp fail, which is `None` rather than an error.
+pub async fn probe(options: &RecordOptions, link: &str) -> Result<Option<Probe>> {
+    let output = tokio::process::Command::new(&options.program)
+        .args(connection_args(options))
+        .args(["-J", "--skip-download", "--no-warnings", "--playlist-items", "1", link])
+        .envs(options.env.iter().cloned())
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = match tokio::time::timeout(PROBE_TIMEOUT, output).await {
+        Ok(output) => output.map_err(|source| RecorderError::Spawn { program: options.program.clone(), source })?,
+        Err(_) => {
+            debug!("Probing {} timed out after {:?}", link, PROBE_TIMEOUT);
+            return Ok(None);
+        }
+    };
+    if !output.status.success() {
+        debug!("{} is not live: {}", link, String::from_utf8_lossy(&output.stderr).trim());
+        return Ok(None);
+    }
+    Ok(parse_probe(&output.stdout))
+}
+
+/// Where browser cookies are exported to for scrape detection, which
+/// can't read a browser's cookie store itself.
+pub fn browser_cookie_jar() -> PathBuf {
+    std::env::temp_dir().join(format!("auto-youtube-live-recorder-{}.cookies.txt", std::process::id()))
+}
+
+/// Exports the cookies of `browser` into the Netscape cookie file `jar`,
+/// readable only by the owner. Given no URL, yt-dlp exits with an error,
+/// but only after saving the jar, so it worked if the jar was written.
+pub async fn export_browser_cookies(program: &str, browser: &str, jar: &Path) -> Result<()> {
+    let mut file = fs::OpenOptions::new();
+    file.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        file.mode(0o600);
+    }
+    file.open(jar)?;
+    let output = tokio::process::Command::new(program)
+        .args(["--cookies-from-browser", browser, "--skip-download", "--cookies"])
+        .arg(jar)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|source| RecorderError::Spawn { program: program.to_owned(), source })?;
+    match fs::metadata(jar) {
+        Ok(meta) if meta.len() > 0 => Ok(()),
+        _ => Err(io::Error::other(format!("{} saved no cookies: {}", program, String::from_utf8_lossy(&output.stderr).trim())).into())
+    }
+}
+
+/// The formats yt-dlp picked, from its `Downloading 1 format(s): 299+140`
+/// line; a merge of separate streams is joined with `+`.
+pub fn chosen_format(line: &str) -> Option<&str> {
+    let (_, formats) = line.split_once(" format(s): ")?;
+    line.contains("Downloading ").then(|| formats.trim())
+}
+
+/// Prefix of the progress lines yt-dlp prints for `--progress-template`.
+const PROGRESS_MARKER: &str = "[recorder-progress] ";
+
+/// Least time between two progress updates handed on.
+const PROGRESS_EVERY: Duration = Duration::from_secs(1);
+
+/// One progress update from yt-dlp; live streams have no total size.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Default,
+    PartialEq
+)]
+pub struct Progress {
+    #[serde(default)]
+    pub status: String,
+    pub downloaded_bytes: Option<u64>,
+    pub total_bytes: Option<u64>,
+    pub total_bytes_estimate: Option<f64>,
+    /// Bytes per second
+    pub speed: Option<f64>,
+    pub fragment_index: Option<u64>
+}
+
+impl Progress {
+    pub fn parse(line: &str) -> Option<Progress> {
+        serde_json::from_str(line.strip_prefix(PROGRESS_MARKER)?).ok()
+    }
+
+    pub fn percent(&self) -> Option<f64> {
+        let total = self.total_bytes.map(|total| total as f64).or(self.total_bytes_estimate)?;
+        let downloaded = self.downloaded_bytes? as f64;
+        (total > 0.0).then(|| downloaded / total * 100.0)
+    }
+}
+
+fn megabytes(bytes: f64) -> String {
+    format!("{:.1} MB", bytes / 1e6)
+}
+
+impl std::fmt::Display for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(percent) = self.percent() {
+            write!(f, "{:.1}% of ", percent)?;
+        }
+        write!(f, "{}", megabytes(self.downloaded_bytes.unwrap_or(0) as f64))?;
+        if let Some(speed) = self.speed {
+            write!(f, " at {}/s", megabytes(speed))?;
+        }
+        Ok(())
+    }
+}
+
+/// Receives yt-dlp's progress while it runs.
+pub type ProgressSink = Arc<dyn Fn(Progress) + Send + Sync>;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Exit {
+    pub code: Option<i32>,
+    /// We stopped yt-dlp ourselves, see [`Stop`]
+    pub interrupted: bool,
+    /// yt-dlp reported that its login session expired
+    pub auth_expired: bool,
+    /// Writing failed for lack of space, so we stopped yt-dlp to keep what
+    /// it had written intact
+    pub disk_full: bool,
+    /// yt-dlp picked a single format with video and audio already muxed
+    pub unmerged: bool
+}
+
+/// Notices when the files of a recording stop growing, which means yt-dlp
+/// has wedged rather than merely slowed down.
+pub struct StallWatch {
+    dir: PathBuf,
+    needle: String,
+    timeout: Duration,
+    last_size: Cell<u64>,
+    last_change: Cell<Instant>,
+    tripped: Cell<bool>
+}
+
+impl StallWatch {
+    pub fn new(dir: &Path, video_id: &String, timeout: Duration) -> StallWatch {
+        let watch = StallWatch {
+            dir: dir.to_path_buf(),
+            needle: format!("[{}]", video_id),
+            timeout,
+            last_size: Cell::new(0),
+            last_change: Cell::new(Instant::now()),
+            tripped: Cell::new(false)
+        };
+        watch.last_size.set(watch.size());
+        watch
+    }
+
+    /// Combined size of every file of the recording, partial ones included.
+    fn size(&self) -> u64 {
+        files_in(&self.dir)
+            .iter()
+            .filter(|path| file_name(path).contains(&self.needle))
+            .filter_map(|path| fs::metadata(path).ok())
+            .map(|meta| meta.len())
+            .sum()
+    }
+
+    fn stalled(&self) -> bool {
+        let size = self.size();
+        if size != self.last_size.get() {
+            self.last_size.set(size);
+            self.last_change.set(Instant::now());
+            return false;
+        }
+        if self.last_change.get().elapsed() < self.timeout {
+            return false;
+        }
+        warn!("No new output in {} for {:?}, restarting yt-dlp", self.needle, self.timeout);
+        self.tripped.set(true);
+        true
+    }
+
+    /// Whether the watch has fired.
+    pub fn tripped(&self) -> bool {
+        self.tripped.get()
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Periodically checks for fresh cookies: a cookie file that changed on
+/// disk, or, for browser cookies, simply the interval passing.
+pub struct CookieRefresh {
+    cookies: Cookies,
+    every: Duration,
+    next_check: Cell<Instant>,
+    file_modified: Option<SystemTime>,
+    tripped: Cell<bool>
+}
+
+impl CookieRefresh {
+    pub fn new(cookies: &Cookies, every: Duration) -> CookieRefresh {
+        CookieRefresh {
+            cookies: cookies.clone(),
+            every,
+            next_check: Cell::new(Instant::now() + every),
+            file_modified: match cookies {
+                Cookies::File(path) => modified(path),
+                Cookies::Browser(_) => None
+            },
+            tripped: Cell::new(false)
+        }
+    }
+
+    fn refreshed(&self) -> bool {
+        if Instant::now() < self.next_check.get() {
+            return false;
+        }
+        self.next_check.set(Instant::now() + self.every);
+        let refreshed = match &self.cookies {
+            Cookies::File(path) => modified(path) != self.file_modified,
+            Cookies::Browser(_) => true
+        };
+        if refreshed {
+            info!("Cookies refreshed, restarting yt-dlp to pick them up");
+            self.tripped.set(true);
+        }
+        refreshed
+    }
+
+    pub fn tripped(&self) -> bool {
+        self.tripped.get()
+    }
+}
+
+/// When to interrupt a running yt-dlp so it finalizes its output and exits.
+pub struct Stop<'a> {
+    /// Deadline, e.g. the next daily rollover
+    pub at: Option<Instant>,
+    /// Set from elsewhere to stop as soon as possible
+    pub requested: &'a AtomicBool,
+    /// Interrupt once the output stops growing
+    pub stall: Option<StallWatch>,
+    /// Interrupt once fresh cookies are available
+    pub cookie_refresh: Option<CookieRefresh>,
+    /// Interrupt as soon as yt-dlp picks a pre-muxed format
+    pub require_merge: bool
+}
+
+impl Stop<'_> {
+    fn due(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+            || self.at.is_some_and(|at| Instant::now() >= at)
+            || self.stall.as_ref().is_some_and(|stall| stall.stalled())
+            || self.cookie_refresh.as_ref().is_some_and(|refresh| refresh.refreshed())
+    }
+}
+
+/// Launches yt-dlp. Swapped out in tests so no process is started.
+pub trait Spawner {
+    /// Runs `program` with `args` and the extra environment `env` to
+    /// completion, interrupting it once `stop` is due.
+    fn run(&self, program: &str, args: &[String], env: &[(String, String)], stop: &Stop) -> io::Result<Exit>;
+}
+
+pub struct ProcessSpawner {
+    /// Extra attempts when starting the process fails for a transient reason
+    pub spawn_retries: u32,
+    /// Niceness to run the process with
+    pub nice: Option<i32>,
+    /// Where to send progress lines picked out of stdout
+    pub progress: Option<ProgressSink>,
+    /// How long an interrupted process gets to finalize before it's killed
+    pub grace: Duration
+}
+
+/// Delay before the first spawn retry, doubled for each one after.
+const SPAWN_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Spawn failures that can go away on their own on a busy system.
+fn is_transient(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    if let Some(code) = err.raw_os_error() {
+        return [libc::EAGAIN, libc::ENOMEM, libc::EMFILE, libc::ENFILE, libc::EINTR].contains(&code);
+    }
+    matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted | io::ErrorKind::OutOfMemory)
+}
+
+/// Somewhere between zero and `max`, taken from the clock so that retries
+/// of several recordings don't line up.
+fn jitter(max: Duration) -> Duration {
+    let nanos = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().subsec_nanos();
+    max.mul_f64(f64::from(nanos) / 1e9)
+}
+
+/// Calls `attempt` until it succeeds, fails for a non-transient reason, or
+/// `retries` retries with jittered exponential backoff have been used up.
+fn retry_transient<T>(retries: u32, backoff: Duration, mut attempt: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut tries = 0;
+    loop {
+        match attempt() {
+            Err(err) if tries < retries && is_transient(&err) => {
+                tries += 1;
+                let delay = backoff * 2u32.pow(tries - 1) + jitter(backoff);
+                warn!("Starting yt-dlp failed ({}), retry {}/{} in {:?}", err, tries, retries, delay);
+                thread::sleep(delay);
+            },
+            result => return result
+        }
+    }
+}
+
+#[cfg(unix)]
+fn renice(child: &Child, nice: i32) {
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, child.id() as libc::id_t, nice) } != 0 {
+        warn!("Could not set yt-dlp niceness to {}: {}", nice, io::Error::last_os_error());
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn renice(_child: &Child, nice: i32) {
+    warn!("Niceness {} is not supported on this platform, ignoring it", nice);
+}
+
+/// Windows priority class closest to the Unix niceness `nice`.
+#[cfg(windows)]
+fn priority_class(nice: i32) -> u32 {
+    const IDLE_PRIORITY_CLASS: u32 = 0x40;
+    const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x4000;
+    const NORMAL_PRIORITY_CLASS: u32 = 0x20;
+    const ABOVE_NORMAL_PRIORITY_CLASS: u32 = 0x8000;
+    match nice {
+        10.. => IDLE_PRIORITY_CLASS,
+        1..=9 => BELOW_NORMAL_PRIORITY_CLASS,
+        0 => NORMAL_PRIORITY_CLASS,
+        _ => ABOVE_NORMAL_PRIORITY_CLASS
+    }
+}
+
+#[cfg(unix)]
+fn interrupt(child: &mut Child) -> io::Result<()> {
+    if unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Sends CTRL_BREAK to the process group the child leads, which yt-dlp
+/// and its ffmpeg take as a request to finish up.
+#[cfg(windows)]
+fn interrupt(child: &mut Child) -> io::Result<()> {
+    const CTRL_BREAK_EVENT: u32 = 1;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GenerateConsoleCtrlEvent(ctrl_event: u32, process_group_id: u32) -> i32;
+    }
+    if unsafe { GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, child.id()) } != 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn interrupt(child: &mut Child) -> io::Result<()> {
+    child.kill()
+}
+
+impl Spawner for ProcessSpawner {
+    fn run(&self, program: &str, args: &[String], env: &[(String, String)], stop: &Stop) -> io::Result<Exit> {
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .envs(env.iter().cloned())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        // keep a terminal's Ctrl-C to ourselves, so yt-dlp is interrupted
+        // exactly once, by us, and isn't cut off while finalizing
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+            const CREATE_NEW_PROCESS_GROUP: u32 = 0x200;
+            command.creation_flags(CREATE_NEW_PROCESS_GROUP | self.nice.map_or(0, priority_class));
+        }
+        let mut child = retry_transient(self.spawn_retries, SPAWN_BACKOFF, || command.spawn())?;
+        #[cfg(not(windows))]
+        if let Some(nice) = self.nice {
+            renice(&child, nice);
+        }
+
+        // pass stderr through while watching it for session expiry and a full disk
+        let auth_expired = Arc::new(AtomicBool::new(false));
+        let disk_full = Arc::new(AtomicBool::new(false));
+        let stderr = child.stderr.take().map(|stderr| {
+            let auth_expired = auth_expired.clone();
+            let disk_full = disk_full.clone();
+            thread::spawn(move || {
+                for line in BufReader::new(stderr).lines().map_while(|line| line.ok()) {
+                    eprintln!("{}", line);
+                    if is_auth_expired(&line) {
+                        auth_expired.store(true, Ordering::SeqCst);
+                    }
+                    if is_disk_full(&line) {
+                        disk_full.store(true, Ordering::SeqCst);
+                    }
+                }
+            })
+        });
+
+        // pass stdout through too, except for the progress lines, while
+        // watching it for the format yt-dlp picks
+        let unmerged = Arc::new(AtomicBool::new(false));
+        let stdout = child.stdout.take().map(|stdout| {
+            let sink = self.progress.clone();
+            let unmerged = unmerged.clone();
+            thread::spawn(move || {
+                let mut last = None::<Instant>;
+                for line in BufReader::new(stdout).lines().map_while(|line| line.ok()) {
+                    match Progress::parse(&line).zip(sink.as_ref()) {
+                        Some((progress, sink)) => {
+                            let finished = progress.status == "finished";
+                            if finished || last.is_none_or(|last| last.elapsed() >= PROGRESS_EVERY) {
+                                last = Some(Instant::now());
+                                sink(progress);
+                            }
+                        },
+                        None => {
+                            println!("{}", line);
+                            if chosen_format(&line).is_some_and(|format| !format.contains('+')) {
+                                unmerged.store(true, Ordering::SeqCst);
+                            }
+                        }
+                    }
+                }
+            })
+        });
+
+        let mut interrupted = None::<Instant>;
+        let mut killed = false;
+        loop {
+            if let Some(status) = child.try_wait()? {
+                for output in [stderr, stdout].into_iter().flatten() {
+                    let _ = output.join();
+                }
+                return Ok(Exit {
+                    code: status.code(),
+                    interrupted: interrupted.is_some(),
+                    auth_expired: auth_expired.load(Ordering::SeqCst),
+                    disk_full: disk_full.load(Ordering::SeqCst),
+                    unmerged: unmerged.load(Ordering::SeqCst)
+                });
+            }
+            let rejected = stop.require_merge && unmerged.load(Ordering::SeqCst);
+            match interrupted {
+                None if stop.due() || disk_full.load(Ordering::SeqCst) || rejected => {
+                    interrupt(&mut child)?;
+                    interrupted = Some(Instant::now());
+                },
+                Some(at) if !killed && at.elapsed() >= self.grace => {
+                    warn!("{} didn't finish within {:?} of being interrupted, killing it", program, self.grace);
+                    child.kill()?;
+                    killed = true;
+                },
+                _ => {}
+            }
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+}
+
+pub fn should_skip(on_existing: OnExisting, existing: &[PathBuf]) -> bool {
+    on_existing == OnExisting::Skip && !existing.is_empty()
+}
+
+/// Placeholders of `--output-template` and the yt-dlp fields they stand for.
+const TEMPLATE_FIELDS: &[(&str, &str)] = &[
+    ("channel", "%(channel)s"),
+    ("title", "%(title)s"),
+    ("date", "%(epoch>%Y-%m-%d)s"),
+    ("id", "%(id)s")
+];
+
+/// Turns an `--output-template` like `{channel} {date} {title} [{id}]` into
+/// a yt-dlp filename stem. It must keep `[{id}]`, which is how recordings
+/// are found again, and can't name directories since those are only
+/// searched at the top.
+pub fn parse_output_template(template: &str) -> std::result::Result<String, String> {
+    if !template.contains("[{id}]") {
+        return Err(format!("output template {} must contain [{{id}}]", template));
+    }
+    if template.contains(['/', '\\']) {
+        return Err(format!("output template {} can't name directories, use output_dir", template));
+    }
+    let mut stem = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').ok_or_else(|| format!("unterminated {{ in output template {}", template))? + start;
+        let name = &rest[start + 1..end];
+        let (_, field) = TEMPLATE_FIELDS
+            .iter()
+            .find(|(placeholder, _)| *placeholder == name)
+            .ok_or_else(|| format!("unknown placeholder {{{}}} in output template, expected channel, title, date or id", name))?;
+        stem.push_str(&rest[..start].replace('%', "%%"));
+        stem.push_str(field);
+        rest = &rest[end + 1..];
+    }
+    stem.push_str(&rest.replace('%', "%%"));
+    Ok(stem)
+}
+
+fn output_template(base: Option<&str>, on_existing: OnExisting, existing: &[PathBuf], date: Option<&String>) -> Option<String> {
+    let index = match on_existing {
+        OnExisting::Suffix => existing
+            .iter()
+            .filter(|path| date.is_none_or(|date| path.to_string_lossy().contains(date.as_str())))
+            .count(),
+        _ => 0
+    };
+    if base.is_none() && date.is_none() && index == 0 {
+        return None;
+    }
+
+    let mut stem = base.unwrap_or("%(title)s [%(id)s]").to_owned();
+    if let Some(date) = date {
+        stem.push_str(&format!(" {}", date));
+    }
+    if index > 0 {
+        stem.push_str(&format!(".{}", index));
+    }
+    Some(format!("{}.%(ext)s", stem))
+}
+
+/// MPEG-TS while downloading HLS, fragmented MP4 from ffmpeg and MKV when
+/// merging formats, none of which need an index written at the very end.
+const CRASH_SAFE_ARGS: &[&str] = &[
+    "--hls-use-mpegts",
+    "--downloader-args", "ffmpeg:-movflags +frag_keyframe+empty_moov+default_base_moof",
+    "--merge-output-format", "mkv"
+];
+
+/// Arguments deciding how yt-dlp reaches YouTube, shared by every run.
+fn connection_args(options: &RecordOptions) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(location) = &options.config_location {
+        args.push("--config-location".to_owned());
+        args.push(location.to_string_lossy().into_owned());
+    }
+    if let Some(cookies) = &options.cookies {
+        args.extend(cookies.ytdlp_args());
+    }
+    if let Some(address) = options.source_address {
+        args.push("--source-address".to_owned());
+        args.push(address.to_string());
+    }
+    if options.force_ipv4 {
+        args.push("--force-ipv4".to_owned());
+    }
+    if options.force_ipv6 {
+        args.push("--force-ipv6".to_owned());
+    }
+    args
+}
+
+pub fn command_line(options: &RecordOptions, existing: &[PathBuf], date: Option<&String>, link: String) -> Vec<String> {
+    let mut args = Vec::new();
+    if options.output_dir != Path::new(".") {
+        args.push("-P".to_owned());
+        args.push(options.output_dir.to_string_lossy().into_owned());
+    }
+    args.extend(connection_args(options));
+    if let Some(rate) = &options.limit_rate {
+        args.push("--limit-rate".to_owned());
+        args.push(rate.clone());
+    }
+    if options.structured_progress {
+        args.extend(["--newline", "--progress-template"].map(String::from));
+        args.push(format!("download:{}%(progress)j", PROGRESS_MARKER));
+    }
+    args.extend(options.on_existing.ytdlp_args());
+    if let Some(archive) = &options.download_archive {
+        args.push("--download-archive".to_owned());
+        args.push(archive.to_string_lossy().into_owned());
+    }
+    if let Some(template) = output_template(options.output_template.as_deref(), options.on_existing, existing, date) {
+        args.push("-o".to_owned());
+        args.push(template);
+    }
+    if options.no_part {
+        args.push("--no-part".to_owned());
+    }
+    if options.prefer_hdr {
+        args.extend(["-S", "hdr"].map(String::from));
+    }
+    if let Some(format) = &options.format {
+        args.push("-f".to_owned());
+        args.push(format.clone());
+    } else if options.require_merge {
+        args.extend(["-f", "bestvideo+bestaudio"].map(String::from));
+    }
+    if options.crash_safe {
+        args.extend(CRASH_SAFE_ARGS.iter().map(|arg| arg.to_string()));
+    }
+    if options.write_metadata_files {
+        args.extend(["--write-description", "--write-info-json", "--write-thumbnail"].map(String::from));
+    } else if options.capture_extras {
+        args.extend(["--write-description", "--write-thumbnail"].map(String::from));
+    }
+    let mut sub_langs = options.sub_langs.clone();
+    if options.capture_extras {
+        sub_langs.push(LIVE_CHAT.to_owned());
+        // yt-dlp fills in the type's extension, and the caption language
+        for kind in ["description", "thumbnail", "subtitle"] {
+            args.push("-o".to_owned());
+            args.push(format!("{}:%(id)s.extras/%(title)s [%(id)s].%(ext)s", kind));
+        }
+    }
+    if !sub_langs.is_empty() {
+        args.extend(["--write-subs", "--sub-langs"].map(String::from));
+        args.push(sub_langs.join(","));
+    }
+    if options.write_auto_subs {
+        args.push("--write-auto-subs".to_owned());
+    }
+    if let Some(secs) = options.wait_for_video {
+        args.push("--wait-for-video".to_owned());
+        args.push(secs.to_string());
+    }
+    args.extend(options.extra_args.iter().cloned());
+    args.push(link);
+    args
+}
+
+/// With `require_merge`, fails unless yt-dlp merged separate video and
+/// audio: it mustn't have picked a pre-muxed format, and every file it
+/// finished since `since` must have both streams according to ffprobe.
+fn check_merged(options: &RecordOptions, video_id: &String, exit: &Exit, since: SystemTime) -> Result<()> {
+    let not_merged = |reason: String| RecorderError::NotMerged { video_id: video_id.clone(), reason };
+    if exit.unmerged {
+        return Err(not_merged("yt-dlp picked a pre-muxed format".to_owned()));
+    }
+    let ffprobe = merge::ffprobe_program();
+    for path in recordings_since(&options.output_dir, video_id, since) {
+        let streams = merge::stream_types(&ffprobe, &path)?;
+        for kind in ["video", "audio"] {
+            if !streams.iter().any(|stream| stream == kind) {
+                return Err(not_merged(format!("{} has no {} stream", path.display(), kind)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Restarts after yt-dlp reports an expired session, per recording.
+const MAX_AUTH_RETRIES: u32 = 3;
+
+/// Records `link` with `spawner`, blocking until yt-dlp exits, `stop` is
+/// set or the maximum duration is up. With daily rollover, yt-dlp is restarted into a new dated file at
+/// each midnight; with a stall timeout, it's restarted when it wedges.
+/// With cookies, it's restarted when they are refreshed or, a few times,
+/// when yt-dlp reports the session expired. With `require_merge`, it's
+/// stopped and the recording fails once it falls back to a pre-muxed format.
+/// With `smart_format`, the format is picked once up front.
+pub fn record(
+    spawner: &dyn Spawner,
+    options: &RecordOptions,
+    video_id: &String,
+    link: String,
+    stop: &AtomicBool
+) -> Result<Option<i32>> {
+    let options = &with_smart_format(options, video_id, &link);
+    let mut auth_retries = 0;
+    let deadline = options.max_duration.map(|max| Instant::now() + max);
+    loop {
+        let existing = existing_recordings(&options.output_dir, video_id);
+        let now = Utc::now();
+        let date = options.roll_daily.map(|tz| tz.date_stamp(now));
+        let rollover = options.roll_daily.map(|tz| Instant::now() + tz.until_midnight(now));
+        let stop_at = Stop {
+            at: rollover.into_iter().chain(deadline).min(),
+            requested: stop,
+            stall: options.stall_timeout.map(|timeout| StallWatch::new(&options.output_dir, video_id, timeout)),
+            cookie_refresh: options.cookies
+                .as_ref()
+                .zip(options.cookie_refresh)
+                .map(|(cookies, every)| CookieRefresh::new(cookies, every)),
+            require_merge: options.require_merge
+        };
+
+        let started = SystemTime::now();
+        let exit = spawner.run(
+            &options.program,
+            &command_line(options, &existing, date.as_ref(), link.clone()),
+            &options.env,
+            &stop_at
+        ).map_err(|source| RecorderError::Spawn { program: options.program.clone(), source })?;
+        if exit.code == Some(0) && !options.no_part {
+            for path in finalize_parts(&options.output_dir, video_id)? {
+                info!("Finalized leftover partial file into {}", path.display());
+            }
+        }
+        if exit.disk_full {
+            return Err(RecorderError::DiskFull(options.output_dir.clone()));
+        }
+        if options.require_merge {
+            check_merged(options, video_id, &exit, started)?;
+        }
+        if stop.load(Ordering::SeqCst) || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Ok(exit.code);
+        }
+        if !exit.interrupted {
+            if exit.auth_expired && options.cookies.is_some() && auth_retries < MAX_AUTH_RETRIES {
+                auth_retries += 1;
+                warn!("Session for {} expired, restarting with refreshed cookies", video_id);
+                continue;
+            }
+            if exit.auth_expired && exit.code != Some(0) {
+                let hint = match options.cookies {
+                    Some(_) => "and the cookies given don't get access to it".to_owned(),
+                    None => "pass --cookies-file or --cookies-from-browser of an account with access".to_owned()
+                };
+                return Err(RecorderError::AuthRequired { video_id: video_id.clone(), hint });
+            }
+            return Ok(exit.code);
+        }
+        if stop_at.cookie_refresh.as_ref().is_some_and(|refresh| refresh.tripped()) {
+            debug!("Restarted {} with refreshed cookies", video_id);
+        } else if stop_at.stall.as_ref().is_some_and(|stall| stall.tripped()) {
+            warn!("Recording of {} stalled, reconnecting", video_id);
+        } else {
+            info!("Rolling {} over to a new file", video_id);
+        }
+    }
+}
+
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockSpawner {
+    pub calls: std::sync::Mutex<Vec<(String, Vec<String>)>>
+}
+
+#[cfg(test)]
+impl Spawner for MockSpawner {
+    fn run(&self, program: &str, args: &[String], _env: &[(String, String)], _stop: &Stop) -> io::Result<Exit> {
+        self.calls.lock().unwrap_or_else(PoisonError::into_inner).push((program.to_owned(), args.to_vec()));
+        Ok(Exit { code: Some(0), interrupted: false, auth_expired: false, disk_full: false, unmerged: false })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINK: &str = "https://www.youtube.com/watch?v=abc";
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ytlr-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn options(on_existing: OnExisting, dir: &Path) -> RecordOptions {
+        RecordOptions {
+            program: "yt-dlp".to_owned(),
+            on_existing,
+            output_dir: dir.to_path_buf(),
+            roll_daily: None,
+            write_metadata_files: false,
+            write_auto_subs: false,
+            sub_langs: Vec::new(),
+            capture_extras: false,
+            no_part: false,
+            config_location: None,
+            extra_args: Vec::new(),
+            download_archive: None,
+            stall_timeout: None,
+            cookies: None,
+            cookie_refresh: None,
+            source_address: None,
+            force_ipv4: false,
+            force_ipv6: false,
+            env: Vec::new(),
+            prefer_hdr: false,
+            limit_rate: None,
+            structured_progress: false,
+            crash_safe: false,
+            max_duration: None,
+            require_merge: false,
+            smart_format: false,
+            max_height: None,
+            format: None,
+            output_template: None,
+            wait_for_video: None
+        }
+    }
+
+    /// The arguments of the one yt-dlp run, after the `-P` every test's
+    /// scratch directory adds.
+    fn recorded_args(options: &RecordOptions) -> Vec<String> {
+        let spawner = MockSpawner::default();
+        record(&spawner, options, &"abc".to_owned(), LINK.to_owned(), &AtomicBool::new(false)).unwrap();
+        let calls = spawner.calls.lock().unwrap_or_else(PoisonError::into_inner);
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0, "yt-dlp");
+        assert_eq!(calls[0].1[..2], ["-P".to_owned(), options.output_dir.to_string_lossy().into_owned()]);
+        calls[0].1[2..].to_vec()
+    }
+
+    #[test]
+    fn resume_and_overwrite_flags() {
+        let dir = scratch_dir("flags");
+        assert_eq!(recorded_args(&options(OnExisting::Resume, &dir)), vec!["--continue", LINK]);
+        assert_eq!(
+            recorded_args(&options(OnExisting::Overwrite, &dir)),
+            vec!["--force-overwrites", "--no-continue", LINK]
+        );
+    }
+
+    #[test]
+    fn suffix_numbers_after_existing_recordings() {
+        let dir = scratch_dir("suffix");
+        assert_eq!(recorded_args(&options(OnExisting::Suffix, &dir)), vec!["--no-overwrites", LINK]);
+
+        fs::write(dir.join("Stream [abc].mp4"), b"").unwrap();
+        fs::write(dir.join("Stream [abc].1.mp4.part"), b"").unwrap();
+        assert_eq!(
+            recorded_args(&options(OnExisting::Suffix, &dir)),
+            vec!["--no-overwrites", "-o", "%(title)s [%(id)s].1.%(ext)s", LINK]
+        );
+    }
+
+    #[test]
+    fn output_templates_fill_in_placeholders() {
+        assert_eq!(
+            parse_output_template("{channel} {date} {title} [{id}] 100%").unwrap(),
+            "%(channel)s %(epoch>%Y-%m-%d)s %(title)s [%(id)s] 100%%"
+        );
+        assert!(parse_output_template("{title} {id}").is_err());
+        assert!(parse_output_template("{channel}/{title} [{id}]").is_err());
+        assert!(parse_output_template("{uploader} [{id}]").is_err());
+
+        let dir = scratch_dir("template");
+        let mut options = options(OnExisting::Suffix, &dir);
+        options.output_template = parse_output_template("{channel} - {title} [{id}]").ok();
+        options.format = Some("best[height<=720]".to_owned());
+        options.extra_args = vec!["--embed-chapters".to_owned()];
+        fs::write(dir.join("Someone - Stream [abc].mp4"), b"").unwrap();
+        assert_eq!(
+            recorded_args(&options),
+            vec!["--no-overwrites", "-o", "%(channel)s - %(title)s [%(id)s].1.%(ext)s", "-f", "best[height<=720]", "--embed-chapters", LINK]
+        );
+    }
+
+    #[test]
+    fn skip_only_when_a_finished_file_exists() {
+        let dir = scratch_dir("skip");
+        fs::write(dir.join("Stream [abc].mp4.part"), b"").unwrap();
+        assert!(!should_skip(OnExisting::Skip, &existing_recordings(&dir, &"abc".to_owned())));
+
+        fs::write(dir.join("Stream [abc].info.json"), b"").unwrap();
+        assert!(!should_skip(OnExisting::Skip, &existing_recordings(&dir, &"abc".to_owned())));
+
+        fs::write(dir.join("Stream [abc].mp4"), b"").unwrap();
+        let existing = existing_recordings(&dir, &"abc".to_owned());
+        assert!(should_skip(OnExisting::Skip, &existing));
+        assert!(!should_skip(OnExisting::Resume, &existing));
+    }
+
+    #[test]
+    fn daily_rollover_dates_the_file() {
+        let dir = scratch_dir("rollover");
+        let mut options = options(OnExisting::Suffix, &dir);
+        options.roll_daily = Some("utc".parse().unwrap());
+        let date = Utc::now().format("%Y-%m-%d").to_string();
+
+        fs::write(dir.join(format!("Stream [abc] {}.mp4", date)), b"").unwrap();
+        assert_eq!(
+            recorded_args(&options),
+            vec!["--no-overwrites".to_owned(), "-o".to_owned(), format!("%(title)s [%(id)s] {}.1.%(ext)s", date), LINK.to_owned()]
+        );
+    }
+
+    #[test]
+    fn captions_are_requested_and_kept_apart_from_recordings() {
+        let dir = scratch_dir("subs");
+        let mut options = options(OnExisting::Resume, &dir);
+        options.sub_langs = vec!["en".to_owned(), "de.*".to_owned()];
+        options.write_auto_subs = true;
+        assert_eq!(
+            recorded_args(&options),
+            vec!["--continue", "--write-subs", "--sub-langs", "en,de.*", "--write-auto-subs", LINK]
+        );
+        assert!(parse_sub_lang("-live_chat").is_ok());
+        assert!(parse_sub_lang("en,de").is_err());
+
+        fs::write(dir.join("Stream [abc].mp4"), b"").unwrap();
+        fs::write(dir.join("Stream [abc].en.vtt"), b"").unwrap();
+        fs::write(dir.join("Stream [abc].de.vtt.part"), b"").unwrap();
+        assert_eq!(existing_recordings(&dir, &"abc".to_owned()), vec![dir.join("Stream [abc].mp4")]);
+        assert_eq!(subtitle_files(&dir, "abc"), vec![dir.join("Stream [abc].en.vtt")]);
+    }
+
+    #[test]
+    fn extras_go_to_their_own_directory() {
+        let dir = scratch_dir("extras");
+        let mut options = options(OnExisting::Resume, &dir);
+        options.sub_langs = vec!["en".to_owned()];
+        options.capture_extras = true;
+        assert_eq!(recorded_args(&options), vec![
+            "--continue",
+            "--write-description",
+            "--write-thumbnail",
+            "-o",
+            "description:%(id)s.extras/%(title)s [%(id)s].%(ext)s",
+            "-o",
+            "thumbnail:%(id)s.extras/%(title)s [%(id)s].%(ext)s",
+            "-o",
+            "subtitle:%(id)s.extras/%(title)s [%(id)s].%(ext)s",
+            "--write-subs",
+            "--sub-langs",
+            "en,live_chat",
+            LINK
+        ]);
+
+        fs::create_dir_all(dir.join(extras_dir("abc"))).unwrap();
+        fs::write(dir.join("abc.extras/Stream [abc].live_chat.json"), b"").unwrap();
+        fs::write(dir.join("abc.extras/Stream [abc].webp"), b"").unwrap();
+        assert_eq!(subtitle_files(&dir, "abc"), vec![dir.join("abc.extras/Stream [abc].live_chat.json")]);
+        assert!(existing_recordings(&dir, &"abc".to_owned()).is_empty());
+    }
+
+    #[test]
+    fn finalize_renames_leftover_part() {
+        let dir = scratch_dir("finalize");
+        fs::write(dir.join("Stream [abc].mp4.part"), b"data").unwrap();
+        fs::write(dir.join("Stream [abc].f1.mp4.part"), b"").unwrap();
+
+        let finalized = finalize_parts(&dir, &"abc".to_owned()).unwrap();
+        assert_eq!(finalized, vec![dir.join("Stream [abc].mp4")]);
+        assert_eq!(partial_files(&dir, None), vec![dir.join("Stream [abc].f1.mp4.part")]);
+    }
+
+    #[test]
+    fn titles_with_part_in_them_are_finished_recordings() {
+        let dir = scratch_dir("party");
+        fs::write(dir.join("Live.party [abc].mp4"), b"data").unwrap();
+        fs::write(dir.join("Live.party [abc].f1.mp4.part-Frag3"), b"").unwrap();
+        fs::write(dir.join("Live.party [abc].f1.mp4.ytdl"), b"").unwrap();
+
+        assert!(!is_partial("Live.party [abc].mp4"));
+        assert_eq!(existing_recordings(&dir, &"abc".to_owned()), vec![dir.join("Live.party [abc].mp4")]);
+        recover_parts(&dir, RecoverParts::Clean).unwrap();
+        assert!(dir.join("Live.party [abc].mp4").exists());
+        assert!(partial_files(&dir, None).is_empty());
+    }
+
+    #[test]
+    fn stall_watch_fires_only_without_growth() {
+        let dir = scratch_dir("stall");
+        let watch = StallWatch::new(&dir, &"abc".to_owned(), Duration::ZERO);
+        assert!(watch.stalled());
+
+        fs::write(dir.join("Stream [abc].mp4.part"), b"data").unwrap();
+        assert!(!watch.stalled());
+        assert!(watch.stalled());
+        assert!(watch.tripped());
+    }
+
+    #[test]
+    fn cookies_are_passed_and_expiry_is_detected() {
+        let dir = scratch_dir("cookies");
+        let mut options = options(OnExisting::Resume, &dir);
+        options.cookies = Some(Cookies::Browser("firefox".to_owned()));
+        assert_eq!(recorded_args(&options), vec!["--cookies-from-browser", "firefox", "--continue", LINK]);
+
+        assert!(is_auth_expired("ERROR: [youtube] abc: Join this channel to get access to members-only content"));
+        assert!(!is_auth_expired("[download]  12.5% of ~1.20GiB"));
+    }
+
+    #[test]
+    fn disk_full_is_detected() {
+        assert!(is_disk_full("ERROR: unable to write data: [Errno 28] No space left on device"));
+        assert!(is_disk_full("[ffmpeg] av_interleaved_write_frame(): No space left on device"));
+        assert!(!is_disk_full("[download] Destination: Stream [abc].mp4"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn child_runs_with_niceness() {
+        let spawner = ProcessSpawner { spawn_retries: 0, nice: Some(5), progress: None, grace: Duration::from_secs(60) };
+        let stop = Stop { at: None, requested: &AtomicBool::new(false), stall: None, cookie_refresh: None, require_merge: false };
+        let args = ["-c", "sleep 0.2; exit $(nice)"].map(String::from);
+        assert_eq!(spawner.run("sh", &args, &[], &stop).unwrap().code, Some(5));
+    }
+
+    #[test]
+    fn rate_limits_and_crash_safe_containers_reach_yt_dlp() {
+        let dir = scratch_dir("limit-rate");
+        let mut options = options(OnExisting::Resume, &dir);
+        options.limit_rate = Some("2M".to_owned());
+        assert_eq!(recorded_args(&options), vec!["--limit-rate", "2M", "--continue", LINK]);
+
+        options.limit_rate = None;
+        let crash_safe = |args: &[String]| args.windows(CRASH_SAFE_ARGS.len()).any(|window| window == CRASH_SAFE_ARGS);
+        assert!(!crash_safe(&command_line(&options, &[], None, LINK.to_owned())));
+        options.crash_safe = true;
+        let args = command_line(&options, &[], None, LINK.to_owned());
+        assert!(crash_safe(&args));
+        assert_eq!(args.last().map(String::as_str), Some(LINK));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn interrupted_children_are_killed_after_the_grace_period() {
+        let spawner = ProcessSpawner { spawn_retries: 0, nice: None, progress: None, grace: Duration::from_millis(300) };
+        let stop = Stop { at: None, requested: &AtomicBool::new(true), stall: None, cookie_refresh: None, require_merge: false };
+        let args = ["-c", "trap '' INT; sleep 10"].map(String::from);
+        let started = Instant::now();
+        let exit = spawner.run("sh", &args, &[], &stop).unwrap();
+        assert!(exit.interrupted);
+        assert_eq!(exit.code, None);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn recording_ends_for_good_at_the_max_duration() {
+        struct Interrupted(std::sync::Mutex<u32>);
+        impl Spawner for Interrupted {
+            fn run(&self, _program: &str, _args: &[String], _env: &[(String, String)], stop: &Stop) -> io::Result<Exit> {
+                *self.0.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+                assert!(stop.due());
+                Ok(Exit { code: Some(0), interrupted: true, auth_expired: false, disk_full: false, unmerged: false })
+            }
+        }
+
+        let dir = scratch_dir("sample");
+        let mut options = options(OnExisting::Resume, &dir);
+        options.max_duration = Some(Duration::ZERO);
+        let spawner = Interrupted(std::sync::Mutex::new(0));
+        record(&spawner, &options, &"abc".to_owned(), LINK.to_owned(), &AtomicBool::new(false)).unwrap();
+        assert_eq!(*spawner.0.lock().unwrap_or_else(PoisonError::into_inner), 1);
+    }
+
+    #[test]
+    fn pre_muxed_format_fails_a_merge_only_recording() {
+        assert_eq!(chosen_format("[info] abc: Downloading 1 format(s): 299+140"), Some("299+140"));
+        assert_eq!(chosen_format("[info] abc: Downloading 1 format(s): 96"), Some("96"));
+        assert_eq!(chosen_format("[download] Destination: Stream [abc].mp4"), None);
+
+        struct PreMuxed;
+        impl Spawner for PreMuxed {
+            fn run(&self, _program: &str, args: &[String], _env: &[(String, String)], stop: &Stop) -> io::Result<Exit> {
+                assert!(stop.require_merge);
+                assert!(args.windows(2).any(|pair| pair == ["-f", "bestvideo+bestaudio"]));
+                Ok(Exit { code: None, interrupted: true, auth_expired: false, disk_full: false, unmerged: true })
+            }
+        }
+
+        let dir = scratch_dir("require-merge");
+        let mut options = options(OnExisting::Resume, &dir);
+        options.require_merge = true;
+        let result = record(&PreMuxed, &options, &"abc".to_owned(), LINK.to_owned(), &AtomicBool::new(false));
+        assert!(matches!(result, Err(RecorderError::NotMerged { .. })));
+    }
+
+    #[test]
+    fn smart_format_prefers_the_tallest_fitting_then_the_shortest() {
+        let listing = r#"{"id":"abc","formats":[
+            {"format_id":"140","vcodec":"none","acodec":"mp4a.40.2","tbr":129.5},
+            {"format_id":"95","height":720,"vcodec":"avc1","acodec":"mp4a","tbr":2500.0},
+            {"format_id":"137","height":1080,"vcodec":"avc1","acodec":"none","tbr":4400.0},
+            {"format_id":"248","height":1080,"vcodec":"vp9","acodec":"none","tbr":4100.0},
+            {"format_id":"271","height":1440,"vcodec":"vp9","acodec":"none","tbr":9000.0}
+        ]}"#;
+        let formats = serde_json::from_str::<Listing>(listing).unwrap().formats;
+
+        assert_eq!(pick_format(&formats, Some(1080), false).unwrap().selector(), "137+bestaudio");
+        assert_eq!(pick_format(&formats, None, false).unwrap().format_id, "271");
+        assert_eq!(pick_format(&formats, Some(720), false).unwrap().selector(), "95");
+        assert_eq!(pick_format(&formats, Some(720), true).unwrap().format_id, "137");
+        assert_eq!(pick_format(&formats, Some(360), false).unwrap().format_id, "95");
+        assert_eq!(pick_format(&formats[..1], Some(1080), false), None);
+
+        assert_eq!(parse_channel_height("@news=720"), Ok(("@news".to_owned(), 720)));
+        assert!(parse_channel_height("@news=0").is_err());
+    }
+
+    #[test]
+    fn probes_find_the_stream_on_air() {
+        let live = br#"{"id":"42","title":"Speedrun","uploader":"Someone","is_live":true,"formats":[]}"#;
+        let probe = parse_probe(live).unwrap();
+        assert_eq!((probe.id.as_str(), probe.uploader.as_deref()), ("42", Some("Someone")));
+        assert_eq!(parse_probe(br#"{"id":"42","title":"Replay","is_live":false}"#), None);
+        let playlist = br#"{"id":"page","_type":"playlist","entries":[{"id":"7","title":"Now","live_status":"is_live"}]}"#;
+        assert_eq!(parse_probe(playlist).unwrap().id, "7");
+        assert_eq!(parse_probe(b"not json"), None);
+    }
+
+    #[test]
+    fn progress_lines_are_parsed() {
+        let line = r#"[recorder-progress] {"status":"downloading","downloaded_bytes":2500000,"total_bytes":null,"speed":1250000.0,"fragment_index":12,"elapsed":2.1}"#;
+        let progress = Progress::parse(line).unwrap();
+        assert_eq!(progress.fragment_index, Some(12));
+        assert_eq!(progress.to_string(), "2.5 MB at 1.2 MB/s");
+
+        let progress = Progress { total_bytes: Some(10_000_000), ..progress };
+        assert_eq!(progress.to_string(), "25.0% of 2.5 MB at 1.2 MB/s");
+        assert_eq!(Progress::parse("[download] 25.0% of 10.00MiB"), None);
+    }
+
+    #[test]
+    fn only_transient_spawn_errors_are_retried() {
+        let mut calls = 0;
+        let result = retry_transient(3, Duration::ZERO, || {
+            calls += 1;
+            if calls < 3 { Err(io::Error::from(io::ErrorKind::WouldBlock)) } else { Ok(calls) }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let mut calls = 0;
+        let result: io::Result<()> = retry_transient(3, Duration::ZERO, || {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+}
@@ -0,0 +1,121 @@
+//! Runs the real binary for one `--once` poll against a mock Data API and a
+//! stub yt-dlp, so the spawn, dedup, sidecar and manifest pipeline is exercised
+//! without network access or the real yt-dlp. The stub is a shell script.
+#![cfg(unix)]
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    os::unix::fs::PermissionsExt,
+    path::PathBuf,
+    process::Command,
+    thread,
+    time::{Duration, Instant}
+};
+
+const CHANNEL_ID: &str = "UCmockmockmockmockmock00";
+const VIDEO_ID: &str = "mockvideo01";
+
+const STUB: &str = r#"#!/bin/sh
+for arg; do link=$arg; done
+echo recorded > "Mock stream [${link##*v=}].mp4"
+"#;
+
+/// The canned answer to a Data API request for `path`.
+fn respond(path: &str) -> (&'static str, String) {
+    let body = if path.starts_with("/channels?") {
+        format!(
+            r#"{{"kind":"youtube#channelListResponse","etag":"e","pageInfo":{{"totalResults":1,"resultsPerPage":1}},"items":[{{"kind":"youtube#channel","etag":"e","id":"{}","snippet":{{"title":"Mock channel"}}}}]}}"#,
+            CHANNEL_ID
+        )
+    } else if path.starts_with("/search?") {
+        format!(
+            r#"{{"items":[{{"id":{{"videoId":"{}"}},"snippet":{{"channelId":"{}","title":"Mock stream","description":"","channelTitle":"Mock channel","liveBroadcastContent":"live"}}}}]}}"#,
+            VIDEO_ID, CHANNEL_ID
+        )
+    } else if path.starts_with("/videos?") {
+        format!(
+            r#"{{"kind":"youtube#videoListResponse","etag":"e","items":[{{"kind":"youtube#video","etag":"e","id":"{}","liveStreamingDetails":{{"concurrentViewers":"42"}}}}]}}"#,
+            VIDEO_ID
+        )
+    } else {
+        return ("404 Not Found", String::new());
+    };
+    ("200 OK", body)
+}
+
+/// Serves `respond` on a local port, one request per connection, and
+/// returns its base URL.
+fn mock_api() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut header = String::new();
+            while reader.read_line(&mut header).unwrap() > 2 {
+                header.clear();
+            }
+            let path = request_line.split(' ').nth(1).unwrap_or_default();
+            let (status, body) = respond(path);
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status, body.len(), body
+            ).unwrap();
+        }
+    });
+    format!("http://{}", addr)
+}
+
+fn scratch_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ytlr-end-to-end-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn once_records_a_live_stream_with_a_sidecar() {
+    let dir = scratch_dir();
+    let stub = dir.join("yt-dlp");
+    fs::write(&stub, STUB).unwrap();
+    fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut recorder = Command::new(env!("CARGO_BIN_EXE_auto-youtube-live-recorder"))
+        .args(["--api-key", "test", "--channel", CHANNEL_ID, "--once", "--manifest-format", "json"])
+        .arg("--api-base-url")
+        .arg(mock_api())
+        .arg("--yt-dlp-path")
+        .arg(&stub)
+        .current_dir(&dir)
+        .spawn()
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let status = loop {
+        if let Some(status) = recorder.try_wait().unwrap() {
+            break status;
+        }
+        if Instant::now() > deadline {
+            recorder.kill().unwrap();
+            panic!("recorder still running after --once");
+        }
+        thread::sleep(Duration::from_millis(100));
+    };
+    assert!(status.success());
+
+    assert_eq!(fs::read_to_string(dir.join(format!("Mock stream [{}].mp4", VIDEO_ID))).unwrap(), "recorded\n");
+    let sidecar = fs::read_to_string(dir.join(format!("{}.meta.json", VIDEO_ID))).unwrap();
+    let sidecar: serde_json::Value = serde_json::from_str(&sidecar).unwrap();
+    assert_eq!(sidecar["channel_title"], "Mock channel");
+    assert_eq!(sidecar["concurrent_viewers"], "42");
+    let manifest = fs::read_to_string(dir.join("manifest.json")).unwrap();
+    let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+    assert_eq!(manifest[0]["video_id"], VIDEO_ID);
+    assert_eq!(manifest[0]["files"][0], format!("./Mock stream [{}].mp4", VIDEO_ID));
+}